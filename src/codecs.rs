@@ -19,6 +19,27 @@ pub fn decode_c_str(c_str: *const ffi::yaml_char_t) -> Option<String> {
     }
 }
 
+/// Splits a tag into its handle and suffix, so `!foo!bar` gives `!foo!` and `bar`.
+///
+/// The verbatim form `!<...>` has no handle, and neither have full tag URIs such as
+/// `tag:yaml.org,2002:str`, which is how libyaml reports every tag whose handle came from
+/// a `%TAG` directive or the default `!!` handle.
+pub fn split_tag(tag: &str) -> (Option<String>, String) {
+    if tag.starts_with("!<") && tag.ends_with('>') {
+        return (None, tag[2..tag.len() - 1].to_string());
+    }
+    if !tag.starts_with('!') {
+        return (None, tag.to_string());
+    }
+
+    let rest = &tag[1..];
+    match rest.find('!') {
+        Some(i) if rest[..i].chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+            (Some(tag[..i + 2].to_string()), tag[i + 2..].to_string()),
+        _ => (Some("!".to_string()), rest.to_string())
+    }
+}
+
 pub fn decode_buf(buf: *const ffi::yaml_char_t, length: libc::size_t) -> Option<String> {
     if buf == ptr::null() {
         None
@@ -28,3 +49,112 @@ pub fn decode_buf(buf: *const ffi::yaml_char_t, length: libc::size_t) -> Option<
         }
     }
 }
+
+pub fn bom(encoding: ffi::YamlEncoding) -> &'static [u8] {
+    match encoding {
+        ffi::YamlEncoding::YamlUtf8Encoding => b"\xef\xbb\xbf",
+        ffi::YamlEncoding::YamlUtf16LeEncoding => b"\xff\xfe",
+        ffi::YamlEncoding::YamlUtf16BeEncoding => b"\xfe\xff",
+        _ => b""
+    }
+}
+
+/// The encoding whose BOM starts `bytes`, as libyaml checks when detecting the encoding.
+pub fn detect_bom(bytes: &[u8]) -> Option<ffi::YamlEncoding> {
+    [ffi::YamlEncoding::YamlUtf8Encoding, ffi::YamlEncoding::YamlUtf16LeEncoding, ffi::YamlEncoding::YamlUtf16BeEncoding]
+        .iter().cloned().find(|&encoding| bytes.starts_with(bom(encoding)))
+}
+
+pub fn encode_str(s: &str, encoding: ffi::YamlEncoding) -> Vec<u8> {
+    match encoding {
+        ffi::YamlEncoding::YamlUtf16LeEncoding => {
+            Some(0xfeff).into_iter().chain(s.encode_utf16()).flat_map(|u: u16| vec![u as u8, (u >> 8) as u8]).collect()
+        },
+        ffi::YamlEncoding::YamlUtf16BeEncoding => {
+            Some(0xfeff).into_iter().chain(s.encode_utf16()).flat_map(|u: u16| vec![(u >> 8) as u8, u as u8]).collect()
+        },
+        _ => s.as_bytes().to_vec()
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes `bytes` in padded base64, as `!!binary` scalars hold them.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decodes padded base64, skipping the whitespace and line breaks a `!!binary` scalar may
+/// hold. Any other character outside the alphabet, or missing or misplaced padding, gives
+/// `None`.
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if digits.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(digits.len() / 4 * 3);
+    for (n, quad) in digits.chunks(4).enumerate() {
+        let padding = quad.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && n + 1 < digits.len() / 4) {
+            return None;
+        }
+        let mut group = 0u32;
+        for &b in &quad[..4 - padding] {
+            group = group << 6 | BASE64_ALPHABET.iter().position(|&c| c == b)? as u32;
+        }
+        group <<= 6 * padding;
+        bytes.extend_from_slice(&[(group >> 16) as u8, (group >> 8) as u8, group as u8][..3 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use codecs::{decode_base64, encode_base64, split_tag};
+    use parser::{YamlParser, YamlByteParser};
+    use event::YamlEventSpec;
+    use ffi::YamlEncoding::YamlUtf8Encoding;
+
+    #[test]
+    fn test_split_tag() {
+        assert_eq!((Some("!foo!".to_string()), "bar".to_string()), split_tag("!foo!bar"));
+        assert_eq!((Some("!!".to_string()), "str".to_string()), split_tag("!!str"));
+        assert_eq!((Some("!".to_string()), "a/b!c".to_string()), split_tag("!a/b!c"));
+        assert_eq!((Some("!".to_string()), "".to_string()), split_tag("!"));
+        assert_eq!((None, "tag:example.com,2000:x".to_string()), split_tag("!<tag:example.com,2000:x>"));
+
+        let parser = YamlByteParser::init(b"- !local x\n- !!int 1\n", YamlUtf8Encoding);
+        let parts: Vec<_> = parser.parse().filter_map(|evt| match evt.unwrap().spec {
+            YamlEventSpec::YamlScalarEvent(param) => param.tag_parts(),
+            _ => None
+        }).collect();
+        assert_eq!(vec![(Some("!".to_string()), "local".to_string()), (None, "tag:yaml.org,2002:int".to_string())], parts);
+    }
+
+    #[test]
+    fn test_base64() {
+        let cases: [(&[u8], &str); 5] = [(b"", ""), (b"f", "Zg=="), (b"fo", "Zm8="), (b"foo", "Zm9v"), (b"foobar", "Zm9vYmFy")];
+        for &(bytes, text) in cases.iter() {
+            assert_eq!(text, encode_base64(bytes));
+            assert_eq!(Some(bytes.to_vec()), decode_base64(text));
+        }
+
+        assert_eq!(Some(b"foobar".to_vec()), decode_base64(" Zm9v\n  YmFy\n"));
+        assert_eq!(None, decode_base64("Zm9"));
+        assert_eq!(None, decode_base64("Zm9v!mFy"));
+        assert_eq!(None, decode_base64("Zg==Zm9v"));
+        assert_eq!(None, decode_base64("Z==="));
+    }
+}
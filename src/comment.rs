@@ -0,0 +1,331 @@
+use ffi::{YamlEncoding, YamlErrorType};
+use error::{YamlError, YamlMark};
+use event::{YamlEvent, YamlEventSpec};
+use parser::{YamlParser, YamlEventStream, YamlByteParser};
+use emitter::{YamlEventEmitter, YamlEmitter};
+
+use std::char;
+use std::collections::VecDeque;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct YamlComment {
+    pub text: String,
+    pub mark: YamlMark
+}
+
+#[derive(Debug)]
+pub enum YamlCommentedEvent {
+    YamlEventItem(YamlEvent),
+    YamlCommentItem(YamlComment),
+}
+
+pub(crate) struct YamlSourceCursor {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    prev: Option<char>
+}
+
+fn is_break(c: char) -> bool {
+    c == '\n' || c == '\r' || c == '\u{85}' || c == '\u{2028}' || c == '\u{2029}'
+}
+
+fn is_blank_or_break(c: char) -> bool {
+    c == ' ' || c == '\t' || is_break(c)
+}
+
+pub(crate) fn decode_source(bytes: &[u8], encoding: YamlEncoding) -> Vec<char> {
+    let chars: Vec<char> = match encoding {
+        YamlEncoding::YamlUtf16LeEncoding | YamlEncoding::YamlUtf16BeEncoding => {
+            let units = bytes.chunks(2).filter(|pair| pair.len() == 2).map(|pair| {
+                if encoding == YamlEncoding::YamlUtf16LeEncoding {
+                    (pair[0] as u16) | ((pair[1] as u16) << 8)
+                } else {
+                    ((pair[0] as u16) << 8) | (pair[1] as u16)
+                }
+            });
+            char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+        },
+        _ => String::from_utf8_lossy(bytes).chars().collect()
+    };
+
+    // libyaml does not count the BOM in its marks
+    if chars.first() == Some(&'\u{feff}') {
+        chars[1..].to_vec()
+    } else {
+        chars
+    }
+}
+
+impl YamlSourceCursor {
+    pub(crate) fn new(chars: Vec<char>) -> YamlSourceCursor {
+        YamlSourceCursor {
+            chars,
+            pos: 0,
+            line: 0,
+            column: 0,
+            prev: None
+        }
+    }
+
+    fn mark(&self) -> YamlMark {
+        YamlMark {
+            index: self.pos,
+            line: self.line,
+            column: self.column
+        }
+    }
+
+    fn bump(&mut self) {
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        if is_break(c) && !(c == '\r' && self.chars.get(self.pos) == Some(&'\n')) {
+            self.line += 1;
+            self.column = 0;
+        } else {
+            self.column += 1;
+        }
+        self.prev = Some(c);
+    }
+
+    pub(crate) fn skip_to(&mut self, index: usize) {
+        while self.pos < index && self.pos < self.chars.len() {
+            self.bump();
+        }
+    }
+
+    /// Skips whitespace and comments, returning the mark of the first other character.
+    pub(crate) fn find_content(&mut self) -> Option<YamlMark> {
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            if c == '#' && self.prev.map_or(true, is_blank_or_break) {
+                while self.pos < self.chars.len() && !is_break(self.chars[self.pos]) {
+                    self.bump();
+                }
+            } else if is_blank_or_break(c) || c == '\u{feff}' {
+                self.bump();
+            } else {
+                return Some(self.mark());
+            }
+        }
+        None
+    }
+
+    fn scan_to(&mut self, index: usize, comments: &mut VecDeque<YamlCommentedEvent>) {
+        while self.pos < index && self.pos < self.chars.len() {
+            if self.chars[self.pos] == '#' && self.prev.map_or(true, is_blank_or_break) {
+                let mark = self.mark();
+                let mut text = String::new();
+                self.bump();
+                while self.pos < self.chars.len() && !is_break(self.chars[self.pos]) {
+                    text.push(self.chars[self.pos]);
+                    self.bump();
+                }
+                comments.push_back(YamlCommentedEvent::YamlCommentItem(YamlComment {
+                    text,
+                    mark
+                }));
+            } else {
+                self.bump();
+            }
+        }
+    }
+}
+
+/// Event stream interleaving the comments of the source with its events, in source order.
+///
+/// libyaml discards comments while scanning, so they are recovered by decoding a copy of the
+/// input and scanning the text between consecutive events. This costs an extra pass over the
+/// input and keeps the decoded source (four bytes per character) alive for the stream's lifetime.
+pub struct YamlCommentedEventStream<'r> {
+    events: YamlEventStream<YamlByteParser<'r>>,
+    bytes: &'r [u8],
+    cursor: Option<YamlSourceCursor>,
+    pending: VecDeque<YamlCommentedEvent>
+}
+
+impl<'r> YamlCommentedEventStream<'r> {
+    pub(crate) fn new(parser: Box<YamlByteParser<'r>>, bytes: &'r [u8]) -> YamlCommentedEventStream<'r> {
+        YamlCommentedEventStream {
+            events: parser.parse(),
+            bytes,
+            cursor: None,
+            pending: VecDeque::new()
+        }
+    }
+}
+
+impl<'r> Iterator for YamlCommentedEventStream<'r> {
+    type Item = Result<YamlCommentedEvent, YamlError>;
+
+    fn next(&mut self) -> Option<Result<YamlCommentedEvent, YamlError>> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(Ok(item));
+        }
+
+        let evt = match self.events.next() {
+            Some(Ok(evt)) => evt,
+            Some(Err(err)) => return Some(Err(err)),
+            None => return None
+        };
+
+        if let YamlEventSpec::YamlStreamStartEvent(encoding) = evt.spec {
+            self.cursor = Some(YamlSourceCursor::new(decode_source(self.bytes, encoding)));
+        }
+
+        if let Some(ref mut cursor) = self.cursor {
+            cursor.scan_to(evt.start.index, &mut self.pending);
+            if evt.spec == YamlEventSpec::YamlStreamEndEvent {
+                let len = cursor.chars.len();
+                cursor.scan_to(len, &mut self.pending);
+            }
+            cursor.skip_to(evt.end.index);
+        }
+
+        self.pending.push_back(YamlCommentedEvent::YamlEventItem(evt));
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Emits `events` as a UTF-8 stream with `comments` from the same source restored.
+///
+/// libyaml cannot write comments, so the events are emitted first and each comment is then
+/// placed using the source marks of the events around it. A comment on the line where the
+/// preceding event ends is appended to the line where that event ends in the output; any other
+/// comment goes on its own line before the next event. Events added by a transformation should
+/// reuse the marks of a neighbouring source event to keep nearby comments in place.
+pub fn emit_with_comments(events: &[YamlEvent], comments: &[YamlComment]) -> Result<String, YamlError> {
+    let mut buffer = Vec::new();
+    {
+        let mut emitter = YamlEmitter::init(&mut buffer);
+        for evt in events.iter() {
+            match evt.spec {
+                YamlEventSpec::YamlStreamStartEvent(_) =>
+                    emitter.emit_event(&YamlEventSpec::YamlStreamStartEvent(YamlEncoding::YamlUtf8Encoding))?,
+                ref spec => emitter.emit_event(spec)?
+            }
+        }
+    }
+    let output = String::from_utf8(buffer).unwrap();
+
+    // the emitted text is parsed again to find where each event ended up
+    let parser = YamlByteParser::init(output.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+    let emitted: Vec<YamlEvent> = parser.parse().collect::<Result<_, _>>()?;
+    if emitted.len() != events.len() {
+        return Err(YamlError::new(YamlErrorType::YAML_EMITTER_ERROR,
+            Some("emitted output does not reproduce the events".to_string())));
+    }
+
+    let mut lines: Vec<String> = output.lines().map(|line| line.to_string()).collect();
+    // comments going on their own line before each output line, or after the last one
+    let mut leading: Vec<Vec<&YamlComment>> = vec![Vec::new(); lines.len() + 1];
+    for comment in comments.iter() {
+        let index = comment.mark.index;
+        let prev = events.iter().rposition(|evt| evt.end.index > evt.start.index && evt.end.index <= index);
+        match prev {
+            Some(i) if events[i].end.line == comment.mark.line && emitted[i].end.line < lines.len() => {
+                let line = &mut lines[emitted[i].end.line];
+                line.push_str(" #");
+                line.push_str(&comment.text);
+            },
+            _ => {
+                let next = events.iter().position(|evt| evt.start.index >= index);
+                let line = next.map_or(lines.len(), |i| emitted[i].start.line);
+                leading[line.min(lines.len())].push(comment);
+            }
+        }
+    }
+
+    let mut result = String::new();
+    for (i, comments) in leading.iter().enumerate() {
+        let line = lines.get(i).map(|line| &line[..]).unwrap_or("");
+        let indent = &line[..line.len() - line.trim_start().len()];
+        for comment in comments.iter() {
+            result.push_str(indent);
+            result.push('#');
+            result.push_str(&comment.text);
+            result.push('\n');
+        }
+        if i < lines.len() {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use comment::{YamlComment, YamlCommentedEvent};
+    use error::{YamlError, YamlMark};
+    use event::YamlEventSpec;
+    use parser::YamlByteParser;
+    use ffi::YamlEncoding::*;
+
+    fn comments(data: &[u8]) -> Vec<(String, usize, usize)> {
+        let parser = YamlByteParser::init(data, YamlAnyEncoding);
+        let items: Result<Vec<YamlCommentedEvent>, YamlError> = parser.parse_with_comments().collect();
+        items.unwrap().into_iter().filter_map(|item| match item {
+            YamlCommentedEvent::YamlCommentItem(YamlComment { text, mark }) => Some((text, mark.line, mark.column)),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn test_comments_interleaved() {
+        let data = "# head\na: 1 # trailing\nb: [x, # inner\n    \"not # comment\"]\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let items: Vec<String> = parser.parse_with_comments().map(|res| match res.unwrap() {
+            YamlCommentedEvent::YamlCommentItem(comment) => format!("#{}", comment.text),
+            YamlCommentedEvent::YamlEventItem(evt) => match evt.spec {
+                YamlEventSpec::YamlScalarEvent(param) => param.value,
+                _ => "-".to_string()
+            }
+        }).collect();
+
+        assert_eq!(vec!["-", "# head", "-", "-", "a", "1", "# trailing", "b", "-", "x", "# inner",
+                        "not # comment", "-", "-", "-", "-"], items);
+    }
+
+    #[test]
+    fn test_comment_marks() {
+        let data = "a: 1 # one\n# two\n";
+        assert_eq!(vec![(" one".to_string(), 0, 5), (" two".to_string(), 1, 0)], comments(data.as_bytes()));
+
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        match parser.parse_with_comments().nth(5) {
+            Some(Ok(YamlCommentedEvent::YamlCommentItem(comment))) =>
+                assert_eq!(YamlMark { index: 5, line: 0, column: 5 }, comment.mark),
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_comments_utf16() {
+        let data: Vec<u8> = "\u{feff}[1] # c\n".encode_utf16().flat_map(|u| vec![(u & 0xff) as u8, (u >> 8) as u8]).collect();
+        assert_eq!(vec![(" c".to_string(), 0, 4)], comments(&data[..]));
+    }
+
+    #[test]
+    fn test_emit_with_comments() {
+        let data = "# head\na: 1 # one\nb:\n  # inner\n  - x # two\n  - {y: z} # three\n# tail\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let mut events = Vec::new();
+        let mut comments = Vec::new();
+        for item in parser.parse_with_comments() {
+            match item.unwrap() {
+                YamlCommentedEvent::YamlEventItem(mut evt) => {
+                    if let YamlEventSpec::YamlScalarEvent(ref mut param) = evt.spec {
+                        param.value = param.value.to_uppercase();
+                    }
+                    events.push(evt);
+                },
+                YamlCommentedEvent::YamlCommentItem(comment) => comments.push(comment)
+            }
+        }
+
+        let output = super::emit_with_comments(&events, &comments).unwrap();
+        assert_eq!("# head\nA: 1 # one\nB:\n# inner\n- X # two\n- {Y: Z} # three\n# tail\n", output);
+    }
+}
@@ -1,10 +1,14 @@
 use document;
 use document::{YamlNode, YamlNodeData};
+use ffi;
 use ffi::{YamlErrorType, YamlScalarStyle};
 use error::{YamlMark, YamlError, YamlErrorContext};
 
 use std::f64;
 use std::char;
+use std::str::FromStr;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 use regex::Regex;
 
 pub trait YamlConstructor<T, E> {
@@ -21,6 +25,99 @@ pub trait YamlConstructor<T, E> {
     }
 }
 
+/// The base a non-decimal integer was written in, as given by `YamlScalarData::radix`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum YamlRadix {
+    YamlBinaryRadix,
+    YamlOctalRadix,
+    YamlHexRadix,
+}
+
+impl YamlRadix {
+    /// Writes `value` as a `0b`, `0o` or `0x` literal, with any sign before the prefix.
+    pub fn format(self, value: isize) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        let digits = value.unsigned_abs();
+        match self {
+            YamlRadix::YamlBinaryRadix => format!("{}0b{:b}", sign, digits),
+            YamlRadix::YamlOctalRadix => format!("{}0o{:o}", sign, digits),
+            YamlRadix::YamlHexRadix => format!("{}0x{:x}", sign, digits)
+        }
+    }
+}
+
+/// The parts of a YAML timestamp such as `2001-12-14t21:59:43.10-05:00` or `2002-12-14`.
+///
+/// A date alone has every time field zero and no `offset`. The fraction of a second is kept to
+/// nanoseconds, and `offset` is the zone's offset east of UTC in minutes, zero for `Z`, or
+/// `None` when the timestamp has no zone.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct YamlTimestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    pub offset: Option<i32>
+}
+
+impl YamlTimestamp {
+    /// Parses the YAML 1.1 timestamp forms, giving `None` for other text and for dates and
+    /// times out of range, such as February 30th.
+    pub fn parse(text: &str) -> Option<YamlTimestamp> {
+        static PATTERN: OnceLock<Regex> = OnceLock::new();
+        let pattern = PATTERN.get_or_init(|| Regex::new(concat!(
+            r"^([0-9]{4})-([0-9]{1,2})-([0-9]{1,2})",
+            r"(?:(?:[Tt]|[ \t]+)([0-9]{1,2}):([0-9]{2}):([0-9]{2})(?:\.([0-9]*))?",
+            r"(?:[ \t]*(Z|([-+])([0-9]{1,2})(?::([0-9]{2}))?))?)?$")).unwrap());
+        let caps = pattern.captures(text)?;
+        let field = |i: usize| caps.get(i).map_or(0, |m| m.as_str().parse::<u32>().unwrap_or(u32::MAX));
+
+        let (year, month, day) = (field(1), field(2), field(3));
+        if caps.get(4).is_none() && (caps[2].len() != 2 || caps[3].len() != 2) {
+            return None;
+        }
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        if !(1..=12).contains(&month) || day < 1 || day > month_days[month as usize - 1] {
+            return None;
+        }
+        let (hour, minute, second) = (field(4), field(5), field(6));
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        let nanosecond = caps.get(7).map_or(0, |m| {
+            let digits: String = m.as_str().chars().chain("000000000".chars()).take(9).collect();
+            digits.parse().unwrap()
+        });
+        let offset = match caps.get(8) {
+            None => None,
+            Some(_) if caps.get(9).is_none() => Some(0),
+            Some(_) => {
+                let (zone_hour, zone_minute) = (field(10), field(11));
+                if zone_hour > 23 || zone_minute > 59 {
+                    return None;
+                }
+                let minutes = (zone_hour * 60 + zone_minute) as i32;
+                Some(if &caps[9] == "-" { -minutes } else { minutes })
+            }
+        };
+
+        Some(YamlTimestamp {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: hour as u8,
+            minute: minute as u8,
+            second: second as u8,
+            nanosecond,
+            offset
+        })
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum YamlStandardData {
     YamlInteger(isize),
@@ -32,6 +129,192 @@ pub enum YamlStandardData {
     YamlMapping(Vec<(YamlStandardData, YamlStandardData)>),
 }
 
+/// Parses a single UTF-8 document, so empty input and multi-document streams are errors.
+impl FromStr for YamlStandardData {
+    type Err = YamlError;
+
+    fn from_str(s: &str) -> Result<YamlStandardData, YamlError> {
+        ::parse_single_document(s.as_bytes(), ffi::YamlEncoding::YamlUtf8Encoding)
+    }
+}
+
+/// Types a scalar can be converted to by typed accessors such as `YamlSequenceData::iter_as`.
+///
+/// Scalars are resolved by `YamlStandardConstructor` first, so `0x1f` converts to an `i64`
+/// and `yes` to a `bool`, while a quoted `"1"` is only a `String`.
+pub trait FromYamlScalar: Sized {
+    const TYPE_NAME: &'static str;
+
+    fn from_yaml_scalar(data: YamlStandardData) -> Option<Self>;
+}
+
+impl FromYamlScalar for i64 {
+    const TYPE_NAME: &'static str = "i64";
+
+    fn from_yaml_scalar(data: YamlStandardData) -> Option<i64> {
+        match data {
+            YamlStandardData::YamlInteger(value) => Some(value as i64),
+            _ => None
+        }
+    }
+}
+
+impl FromYamlScalar for f64 {
+    const TYPE_NAME: &'static str = "f64";
+
+    fn from_yaml_scalar(data: YamlStandardData) -> Option<f64> {
+        match data {
+            YamlStandardData::YamlFloat(value) => Some(value),
+            YamlStandardData::YamlInteger(value) => Some(value as f64),
+            _ => None
+        }
+    }
+}
+
+impl FromYamlScalar for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn from_yaml_scalar(data: YamlStandardData) -> Option<bool> {
+        match data {
+            YamlStandardData::YamlBool(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+impl FromYamlScalar for String {
+    const TYPE_NAME: &'static str = "String";
+
+    fn from_yaml_scalar(data: YamlStandardData) -> Option<String> {
+        match data {
+            YamlStandardData::YamlString(value) => Some(value),
+            _ => None
+        }
+    }
+}
+
+/// A typed slot filled from a scalar by `YamlMappingData::extract`.
+///
+/// `FromYamlScalar` cannot be used as a trait object, so fields are passed as `Option<T>`
+/// slots, which stay `None` when their key is missing.
+pub trait YamlScalarField {
+    fn type_name(&self) -> &'static str;
+
+    /// Stores the converted value, returning false if `data` has the wrong type.
+    fn set_from(&mut self, data: YamlStandardData) -> bool;
+}
+
+impl<T: FromYamlScalar> YamlScalarField for Option<T> {
+    fn type_name(&self) -> &'static str {
+        T::TYPE_NAME
+    }
+
+    fn set_from(&mut self, data: YamlStandardData) -> bool {
+        *self = T::from_yaml_scalar(data);
+        self.is_some()
+    }
+}
+
+pub(crate) fn convert_field(ctor: &YamlStandardConstructor, node: YamlNode, field: &mut dyn YamlScalarField) -> Result<(), YamlError> {
+    match node {
+        YamlNode::YamlScalarNode(scalar) => {
+            let mark = scalar.start_mark();
+            let value = scalar.get_value();
+            let data = ctor.construct_scalar(scalar)?;
+            if field.set_from(data) {
+                Ok(())
+            } else {
+                Err(standard_error(format!("cannot convert {:?} to {}", value, field.type_name()), &mark))
+            }
+        },
+        YamlNode::YamlSequenceNode(sequence) =>
+            Err(standard_error(format!("expected {} but found a sequence", field.type_name()), &sequence.start_mark())),
+        YamlNode::YamlMappingNode(mapping) =>
+            Err(standard_error(format!("expected {} but found a mapping", field.type_name()), &mapping.start_mark()))
+    }
+}
+
+pub(crate) fn convert_scalar<T: FromYamlScalar>(ctor: &YamlStandardConstructor, node: YamlNode) -> Result<T, YamlError> {
+    let mut value: Option<T> = None;
+    convert_field(ctor, node, &mut value)?;
+    Ok(value.unwrap())
+}
+
+pub(crate) fn missing_key_error(key: &str, mark: &YamlMark) -> YamlError {
+    standard_error(format!("missing key {:?}", key), mark)
+}
+
+pub(crate) fn merge_error(node: &YamlNode) -> YamlError {
+    standard_error("expected a mapping or a sequence of mappings to merge".to_string(), &node_start_mark(node))
+}
+
+pub type YamlScalarResolver = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+/// Chooses the tags of untagged scalars as documents are loaded, installed with
+/// `YamlParser::with_tag_resolver`. Scalars tagged in the source, including with the
+/// non-specific `!`, are not passed to it.
+///
+/// The typed accessors of `YamlScalarData`, and so `to_json_value`, follow the chosen tags,
+/// as does `YamlStandardConstructor` unless its own `with_scalar_resolver` function gives a
+/// tag, which takes precedence. `YamlDocument::to_events` leaves them out, as they were not in
+/// the source. The serde deserializer reads its own events and never consults a resolver.
+pub trait YamlTagResolver {
+    /// The tag of a scalar with the given value and style, or `None` to keep libyaml's
+    /// default `!!str`.
+    fn resolve(&self, value: &str, style: YamlScalarStyle) -> Option<String>;
+}
+
+/// Resolves as the core schema, which is how untagged scalars are read without a resolver:
+/// plain scalars get the `!!null`, `!!bool`, `!!int`, `!!float` or `!!str` tag of the value
+/// `YamlStandardConstructor` gives them, and other scalars `!!str`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YamlCoreTagResolver;
+
+impl YamlTagResolver for YamlCoreTagResolver {
+    fn resolve(&self, value: &str, style: YamlScalarStyle) -> Option<String> {
+        if style != YamlScalarStyle::YamlPlainScalarStyle {
+            return Some(ffi::YAML_STR_TAG.to_string());
+        }
+        let tag = match document::core_constructor().resolve_plain(value.to_string()) {
+            YamlStandardData::YamlNull => ffi::YAML_NULL_TAG,
+            YamlStandardData::YamlBool(_) => ffi::YAML_BOOL_TAG,
+            YamlStandardData::YamlInteger(_) => ffi::YAML_INT_TAG,
+            YamlStandardData::YamlFloat(_) => ffi::YAML_FLOAT_TAG,
+            _ => ffi::YAML_STR_TAG
+        };
+        Some(tag.to_string())
+    }
+}
+
+pub type YamlTagConverter = dyn Fn(&str) -> Option<YamlStandardData> + Send + Sync;
+
+/// Conversions for scalars with custom tags, installed by `with_tag_registry`.
+#[derive(Clone, Default)]
+pub struct YamlTagRegistry {
+    converters: HashMap<String, Arc<YamlTagConverter>>
+}
+
+impl YamlTagRegistry {
+    pub fn new() -> YamlTagRegistry {
+        YamlTagRegistry::default()
+    }
+
+    /// Converts the values of scalars tagged `tag`, whatever their style, with `f`. The tag is
+    /// matched after its handle is resolved, so `!!x` is registered as `tag:yaml.org,2002:x`
+    /// and a local tag as written, such as `!duration`. Returning `None` rejects the value.
+    pub fn with_tag<F>(mut self, tag: &str, f: F) -> YamlTagRegistry
+        where F: Fn(&str) -> Option<YamlStandardData> + Send + Sync + 'static
+    {
+        self.converters.insert(tag.to_string(), Arc::new(f));
+        self
+    }
+}
+
+/// Converts nodes with the default schema.
+///
+/// Integers too large for an `isize` do not fail to load: a decimal one resolves to a float, as
+/// it also matches the float pattern, while a binary, octal or hexadecimal one stays a string.
+/// Tagged `!!int`, either is rejected.
 #[derive(Clone)]
 pub struct YamlStandardConstructor {
     dec_int_pat:Regex,
@@ -44,7 +327,14 @@ pub struct YamlStandardConstructor {
     nan_pat:Regex,
     null_pat:Regex,
     true_pat:Regex,
-    false_pat:Regex
+    false_pat:Regex,
+    bool_true_pat:Regex,
+    bool_false_pat:Regex,
+    scalar_resolver:Option<Arc<YamlScalarResolver>>,
+    tag_registry:YamlTagRegistry,
+    trim_plain_scalars:bool,
+    require_string_keys:bool,
+    reject_duplicate_keys:bool
 }
 
 fn standard_error(message: String, mark: &YamlMark) -> YamlError {
@@ -63,6 +353,28 @@ fn standard_error(message: String, mark: &YamlMark) -> YamlError {
     }
 }
 
+pub(crate) fn duplicate_key_error(first: YamlMark, second: YamlMark) -> YamlError {
+    YamlError {
+        kind: YamlErrorType::YAML_PARSER_ERROR,
+        problem: Some("found duplicate mapping key".to_string()),
+        io_error: None,
+        context: Some(YamlErrorContext {
+            byte_offset: second.index,
+            problem_mark: second,
+            context: Some("first occurrence of the key".to_string()),
+            context_mark: first
+        })
+    }
+}
+
+fn node_start_mark(node: &YamlNode) -> YamlMark {
+    match *node {
+        YamlNode::YamlScalarNode(ref scalar) => scalar.start_mark(),
+        YamlNode::YamlSequenceNode(ref sequence) => sequence.start_mark(),
+        YamlNode::YamlMappingNode(ref mapping) => mapping.start_mark()
+    }
+}
+
 fn take(iter: &mut Iterator<Item=char>, n: usize) -> String
 {
     let mut s = String::new();
@@ -86,12 +398,168 @@ impl YamlStandardConstructor {
             pos_inf_pat: Regex::new(r"^[+]?(\.inf|\.Inf|\.INF)$").unwrap(),
             neg_inf_pat: Regex::new(r"^-(\.inf|\.Inf|\.INF)$").unwrap(),
             nan_pat: Regex::new(r"^(\.nan|\.NaN|\.NAN)$").unwrap(),
-            null_pat: Regex::new(r"^(null|Null|NULL|~)$").unwrap(),
+            null_pat: Regex::new(r"^(null|Null|NULL|~|)$").unwrap(),
             true_pat: Regex::new(r"^(true|True|TRUE|yes|Yes|YES)$").unwrap(),
-            false_pat: Regex::new(r"^(false|False|FALSE|no|No|NO)$").unwrap()
+            false_pat: Regex::new(r"^(false|False|FALSE|no|No|NO)$").unwrap(),
+            bool_true_pat: Regex::new(r"^(y|Y|yes|Yes|YES|true|True|TRUE|on|On|ON)$").unwrap(),
+            bool_false_pat: Regex::new(r"^(n|N|no|No|NO|false|False|FALSE|off|Off|OFF)$").unwrap(),
+            scalar_resolver: None,
+            tag_registry: YamlTagRegistry::new(),
+            trim_plain_scalars: false,
+            require_string_keys: false,
+            reject_duplicate_keys: false
+        }
+    }
+
+    /// Installs a function resolving plain scalars to tags, overriding the default schema.
+    ///
+    /// When the function returns `None`, the scalar falls back to the tag chosen by the
+    /// `YamlTagResolver` of the parser, if any, and then to the default resolution.
+    /// The resolved tag must be one of the `!!null`, `!!bool`, `!!int`, `!!float` or
+    /// `!!str` tags; `!!bool` accepts every YAML 1.1 boolean literal, including `on`/`off`.
+    pub fn with_scalar_resolver<F>(mut self, f: F) -> YamlStandardConstructor
+        where F: Fn(&str) -> Option<String> + Send + Sync + 'static
+    {
+        self.scalar_resolver = Some(Arc::new(f));
+        self
+    }
+
+    /// Converts scalars with a tag in `registry` by its function instead of resolving them.
+    /// Scalars with other tags are resolved as if untagged.
+    pub fn with_tag_registry(mut self, registry: YamlTagRegistry) -> YamlStandardConstructor {
+        self.tag_registry = registry;
+        self
+    }
+
+    /// Trims Unicode whitespace from both ends of plain scalars before they are resolved, so
+    /// a value pasted with a trailing non-breaking space still loads as `8080`.
+    ///
+    /// The YAML spec only strips spaces and tabs around plain scalars, which libyaml already
+    /// does, so a trimming constructor can load different values than other YAML tools for the
+    /// same input. Quoted and block scalars are never trimmed.
+    pub fn with_plain_trimming(mut self, trim: bool) -> YamlStandardConstructor {
+        self.trim_plain_scalars = trim;
+        self
+    }
+
+    /// Rejects mapping keys that are collections or scalars resolving to anything but a string,
+    /// such as `1` or `true`, with an error at the key.
+    pub fn require_string_keys(mut self, required: bool) -> YamlStandardConstructor {
+        self.require_string_keys = required;
+        self
+    }
+
+    /// Rejects a mapping with two keys that resolve to the same value, such as `1` and `0x1`,
+    /// with an error at the second key whose context points at the first.
+    ///
+    /// By default every pair is kept, so a `YamlMapping` may hold a key more than once.
+    pub fn reject_duplicate_keys(mut self, reject: bool) -> YamlStandardConstructor {
+        self.reject_duplicate_keys = reject;
+        self
+    }
+
+    pub(crate) fn match_radix_int(&self, value: &str) -> Option<(isize, YamlRadix)> {
+        if let Some(caps) = self.bin_int_pat.captures(value) {
+            return parse_int(&caps[1], &caps[2], 2).map(|i| (i, YamlRadix::YamlBinaryRadix));
+        }
+        if let Some(caps) = self.oct_int_pat.captures(value) {
+            return parse_int(&caps[1], &caps[2], 8).map(|i| (i, YamlRadix::YamlOctalRadix));
+        }
+        if let Some(caps) = self.hex_int_pat.captures(value) {
+            return parse_int(&caps[1], &caps[2], 16).map(|i| (i, YamlRadix::YamlHexRadix));
+        }
+        None
+    }
+
+    fn match_int(&self, value: &str) -> Option<isize> {
+        if let Some((i, _)) = self.match_radix_int(value) {
+            return Some(i);
+        }
+
+        if self.dec_int_pat.is_match(value) {
+            parse_int("", value, 10)
+        } else {
+            None
+        }
+    }
+
+    fn match_float(&self, value: &str) -> Option<f64> {
+        if let Some(caps) = self.flt_pat.captures(value) {
+            return Some(parse_float(&caps[1], &caps[2]));
+        }
+
+        if self.pos_inf_pat.is_match(value) {
+            Some(f64::INFINITY)
+        } else if self.neg_inf_pat.is_match(value) {
+            Some(f64::NEG_INFINITY)
+        } else if self.nan_pat.is_match(value) {
+            Some(f64::NAN)
+        } else {
+            None
         }
     }
 
+    // the default schema for plain scalars, ignoring any installed resolver
+    pub(crate) fn resolve_plain(&self, value: String) -> YamlStandardData {
+        if let Some(i) = self.match_int(&value[..]) {
+            return YamlStandardData::YamlInteger(i);
+        }
+
+        if let Some(f) = self.match_float(&value[..]) {
+            YamlStandardData::YamlFloat(f)
+        } else if self.null_pat.is_match(&value[..]) {
+            YamlStandardData::YamlNull
+        } else if self.true_pat.is_match(&value[..]) {
+            YamlStandardData::YamlBool(true)
+        } else if self.false_pat.is_match(&value[..]) {
+            YamlStandardData::YamlBool(false)
+        } else {
+            YamlStandardData::YamlString(value)
+        }
+    }
+
+    fn check_string_key(&self, key: &YamlNode) -> Result<(), YamlError> {
+        match *key {
+            YamlNode::YamlScalarNode(ref scalar) => {
+                let value = scalar.get_value();
+                match self.construct_scalar(scalar.clone())? {
+                    YamlStandardData::YamlString(_) => Ok(()),
+                    _ => Err(standard_error(format!("expected a string key but found {:?}", value), &scalar.start_mark()))
+                }
+            },
+            YamlNode::YamlSequenceNode(ref sequence) =>
+                Err(standard_error("expected a string key but found a sequence".to_string(), &sequence.start_mark())),
+            YamlNode::YamlMappingNode(ref mapping) =>
+                Err(standard_error("expected a string key but found a mapping".to_string(), &mapping.start_mark()))
+        }
+    }
+
+    pub(crate) fn construct_tagged_scalar(&self, tag: &str, value: String, mark: &YamlMark) -> Result<YamlStandardData, YamlError> {
+        let data = if tag == ffi::YAML_NULL_TAG {
+            Some(YamlStandardData::YamlNull)
+        } else if tag == ffi::YAML_BOOL_TAG {
+            if self.bool_true_pat.is_match(&value[..]) {
+                Some(YamlStandardData::YamlBool(true))
+            } else if self.bool_false_pat.is_match(&value[..]) {
+                Some(YamlStandardData::YamlBool(false))
+            } else {
+                None
+            }
+        } else if tag == ffi::YAML_INT_TAG {
+            self.match_int(&value[..]).map(YamlStandardData::YamlInteger)
+        } else if tag == ffi::YAML_FLOAT_TAG {
+            self.match_float(&value[..])
+                .or_else(|| self.match_int(&value[..]).map(|i| i as f64))
+                .map(YamlStandardData::YamlFloat)
+        } else if tag == ffi::YAML_STR_TAG {
+            return Ok(YamlStandardData::YamlString(value));
+        } else {
+            return Err(standard_error(format!("could not determine a constructor for the tag {}", tag), mark));
+        };
+
+        data.ok_or_else(|| standard_error(format!("invalid value {:?} for the tag {}", value, tag), mark))
+    }
+
     fn parse_double_quoted(value: &str, mark: &YamlMark) -> Result<String, YamlError> {
         let mut buf = String::new();
         let mut it = value.chars();
@@ -165,16 +633,11 @@ fn parse_escape_sequence(rep: &str, expected_len: usize) -> Option<char> {
     }
 }
 
-fn parse_int(sign: &str, data: &str, radix: u32) -> isize {
-    let sign_flag = if sign == "-" {
-            -1
-        } else {
-            1
-        };
-
-    let filtered:String = data.chars().filter(|&c| c != '_').collect();
-    let unsigned:isize = isize::from_str_radix(&filtered[..], radix).unwrap();
-    return unsigned * sign_flag;
+// `None` when the value does not fit an `isize`; it is parsed with its sign, so the most
+// negative value still does
+fn parse_int(sign: &str, data: &str, radix: u32) -> Option<isize> {
+    let filtered:String = sign.chars().chain(data.chars().filter(|&c| c != '_')).collect();
+    isize::from_str_radix(&filtered[..], radix).ok()
 }
 
 fn parse_float(sign: &str, data: &str) -> f64 {
@@ -191,49 +654,32 @@ impl YamlConstructor<YamlStandardData, YamlError> for YamlStandardConstructor {
         let value = scalar.get_value();
         let mark = scalar.start_mark();
 
+        if let Some(tag) = scalar.tag() {
+            if let Some(convert) = self.tag_registry.converters.get(&tag) {
+                return convert(&value[..]).ok_or_else(|| standard_error(format!("invalid value {:?} for the tag {}", value, tag), &mark));
+            }
+        }
+
         match scalar.style() {
             YamlScalarStyle::YamlPlainScalarStyle => {
-                match self.bin_int_pat.captures(&value[..]) {
-                    Some(caps) => return Ok(YamlStandardData::YamlInteger(parse_int(
-                                &caps[1], &caps[2], 2))),
-                    None => ()
-                };
-                match self.oct_int_pat.captures(&value[..]) {
-                    Some(caps) => return Ok(YamlStandardData::YamlInteger(parse_int(
-                                &caps[1], &caps[2], 8))),
-                    None => ()
-                };
-                match self.hex_int_pat.captures(&value[..]) {
-                    Some(caps) => return Ok(YamlStandardData::YamlInteger(parse_int(
-                                &caps[1], &caps[2], 16))),
-                    None => ()
+                let value = if self.trim_plain_scalars {
+                    value.trim().to_string()
+                } else {
+                    value
                 };
 
-                if self.dec_int_pat.is_match(&value[..]) {
-                    return Ok(YamlStandardData::YamlInteger(parse_int("", &value[..], 10)));
+                if let Some(ref resolver) = self.scalar_resolver {
+                    if let Some(tag) = resolver(&value[..]) {
+                        return self.construct_tagged_scalar(&tag[..], value, &mark);
+                    }
                 }
-
-                match self.flt_pat.captures(&value[..]) {
-                    Some(caps) => return Ok(YamlStandardData::YamlFloat(parse_float(
-                                &caps[1], &caps[2]))),
-                    None => ()
-                };
-
-                if self.pos_inf_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlFloat(f64::INFINITY))
-                } else if self.neg_inf_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlFloat(f64::NEG_INFINITY))
-                } else if self.nan_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlFloat(f64::NAN))
-                } else if self.null_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlNull)
-                } else if self.true_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlBool(true))
-                } else if self.false_pat.is_match(&value[..]) {
-                    Ok(YamlStandardData::YamlBool(false))
-                } else {
-                    Ok(YamlStandardData::YamlString(value))
+                if scalar.has_resolved_tag() {
+                    if let Some(tag) = scalar.tag() {
+                        return self.construct_tagged_scalar(&tag[..], value, &mark);
+                    }
                 }
+
+                Ok(self.resolve_plain(value))
             },
             YamlScalarStyle::YamlDoubleQuotedScalarStyle => {
                 YamlStandardConstructor::parse_double_quoted(&value[..], &mark).map(YamlStandardData::YamlString)
@@ -250,17 +696,23 @@ impl YamlConstructor<YamlStandardData, YamlError> for YamlStandardConstructor {
     }
 
     fn construct_mapping(&self, mapping: document::YamlMappingData) -> Result<YamlStandardData, YamlError> {
-        let pairs = mapping.pairs().map(|(key_node, value_node)| {
-            match self.construct(key_node) {
-                Ok(key) => match self.construct(value_node) {
-                    Ok(value) => Ok((key, value)),
-                    Err(e) => Err(e)
-                },
-                Err(e) => Err(e)
+        let mut pairs: Vec<(YamlStandardData, YamlStandardData)> = Vec::new();
+        let mut key_marks: Vec<YamlMark> = Vec::new();
+        for (key_node, value_node) in mapping.pairs() {
+            if self.require_string_keys {
+                self.check_string_key(&key_node)?;
             }
-        });
-        let res:Result<Vec<(YamlStandardData, YamlStandardData)>, YamlError> = pairs.collect();
-        res.map(YamlStandardData::YamlMapping)
+            let key_mark = node_start_mark(&key_node);
+            let key = self.construct(key_node)?;
+            if self.reject_duplicate_keys {
+                if let Some(first) = pairs.iter().position(|(seen, _)| *seen == key) {
+                    return Err(duplicate_key_error(key_marks[first], key_mark));
+                }
+                key_marks.push(key_mark);
+            }
+            pairs.push((key, self.construct(value_node)?));
+        }
+        Ok(YamlStandardData::YamlMapping(pairs))
     }
 }
 
@@ -269,8 +721,55 @@ mod test {
     use super::YamlStandardData::*;
     use parser::{YamlParser, YamlByteParser};
     use std::f64;
+    use ffi;
     use ffi::YamlEncoding::YamlUtf8Encoding;
-    use constructor::{YamlConstructor, YamlStandardConstructor};
+    use ffi::YamlErrorType;
+    use constructor::{YamlConstructor, YamlStandardConstructor, YamlTagRegistry};
+
+    #[test]
+    fn test_sequence_iter_as() {
+        use document::{YamlDocument, YamlNode};
+
+        let data = "- [1, 0x10, -3]\n- [1.5, 2, .inf]\n- [1, two, [3]]\n- [yes, \"1\"]\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+        let rows: Vec<_> = match docs[0].root() {
+            Some(YamlNode::YamlSequenceNode(seq)) => seq.values().map(|node| match node {
+                YamlNode::YamlSequenceNode(row) => row,
+                _ => panic!("unexpected node")
+            }).collect(),
+            _ => panic!("unexpected root")
+        };
+
+        assert_eq!(Ok(vec![1, 16, -3]), rows[0].iter_as::<i64>().collect::<Result<Vec<_>, _>>());
+        assert_eq!(Ok(vec![1.5, 2.0, f64::INFINITY]), rows[1].iter_as::<f64>().collect::<Result<Vec<_>, _>>());
+
+        let results: Vec<_> = rows[2].iter_as::<i64>().collect();
+        assert_eq!(Ok(1), results[0]);
+        let errors: Vec<(Option<String>, usize)> = results.into_iter().skip(1).map(|res| {
+            let err = res.unwrap_err();
+            (err.problem, err.context.unwrap().problem_mark.column)
+        }).collect();
+        assert_eq!(vec![(Some("cannot convert \"two\" to i64".to_string()), 6),
+                        (Some("expected i64 but found a sequence".to_string()), 11)], errors);
+
+        let mut mixed = rows[3].iter_as::<bool>();
+        assert_eq!(Some(Ok(true)), mixed.next());
+        assert!(mixed.next().unwrap().is_err());
+        assert_eq!(Ok(vec!["1".to_string()]), rows[3].iter_as::<String>().skip(1).collect::<Result<Vec<_>, _>>());
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Ok(YamlMapping(vec![(YamlString("a".to_string()), YamlInteger(1))])), "a: 1".parse());
+
+        for data in ["", "# only a comment\n", "--- 1\n--- 2\n"].iter() {
+            match data.parse::<super::YamlStandardData>() {
+                Err(err) => assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, err.kind),
+                res => panic!("unexpected result: {:?}", res)
+            }
+        }
+    }
 
     #[test]
     fn test_standard_constructor() {
@@ -286,6 +785,16 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_null_key() {
+        let expected = YamlMapping(vec![(YamlNull, YamlString("x".to_string()))]);
+        // libyaml rejects a bare `: x`, so an empty key needs the explicit `?` indicator
+        for data in ["{null: x}", "{~: x}", "?\n: x", "{? : x}"].iter() {
+            assert_eq!(Ok(vec![expected.clone()]), ::parse_bytes_utf8(data.as_bytes()), "{:?}", data);
+        }
+        assert_eq!(Ok(vec![YamlMapping(vec![(YamlString("".to_string()), YamlNull)])]), ::parse_bytes_utf8(b"'': \n"));
+    }
+
     #[test]
     fn test_integer_parser() {
         let data = "[0o10, 0x21, -30]";
@@ -300,6 +809,14 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_integer_overflow() {
+        let data = "[9223372036854775807, -9223372036854775808, -0x8000000000000000, 99999999999999999999, 0x10000000000000000]";
+        assert_eq!(Ok(vec![YamlSequence(vec![YamlInteger(isize::MAX), YamlInteger(isize::MIN), YamlInteger(isize::MIN),
+                                             YamlFloat(1e20), YamlString("0x10000000000000000".to_string())])]),
+                   ::parse_bytes_utf8(data.as_bytes()));
+    }
+
     #[test]
     fn test_float_parser() {
         let data = "[0.3, -.4, 1e+2, -1.2e-3]";
@@ -411,4 +928,181 @@ mod test {
             _ => panic!("document parse failure")
         }
     }
+
+    #[test]
+    fn test_scalar_resolver() {
+        let data = "[ON, OFF, '1', 007, 1, yes]";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+
+        match parser.load().next() {
+            Some(Ok(doc)) => {
+                let ctor = YamlStandardConstructor::new().with_scalar_resolver(|value| {
+                    match value {
+                        "ON" | "OFF" => Some(ffi::YAML_BOOL_TAG.to_string()),
+                        _ if value.starts_with('0') => Some(ffi::YAML_STR_TAG.to_string()),
+                        _ => None
+                    }
+                });
+                assert_eq!(Ok(YamlSequence(vec![YamlBool(true), YamlBool(false), YamlString("1".to_string()),
+                                                YamlString("007".to_string()), YamlInteger(1), YamlBool(true)])),
+                           ctor.construct(doc.root().unwrap()))
+            },
+            _ => panic!("document parse failure")
+        }
+    }
+
+    #[test]
+    fn test_scalar_resolver_invalid_value() {
+        let data = "[1, two]";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+
+        match parser.load().next() {
+            Some(Ok(doc)) => {
+                let ctor = YamlStandardConstructor::new().with_scalar_resolver(|_| Some(ffi::YAML_INT_TAG.to_string()));
+                match ctor.construct(doc.root().unwrap()) {
+                    Err(e) => {
+                        assert_eq!(YamlErrorType::YAML_PARSER_ERROR, e.kind);
+                        assert_eq!(4, e.context.unwrap().problem_mark.index);
+                    },
+                    res => panic!("unexpected result: {:?}", res)
+                }
+            },
+            _ => panic!("document parse failure")
+        }
+    }
+
+    #[test]
+    fn test_plain_trimming() {
+        let data = "port: 8080\u{a0}\nname: \u{3000}app\u{a0}\nquoted: ' x\u{a0}'\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+
+        let untrimmed = YamlStandardConstructor::new().construct(doc.root().unwrap()).unwrap();
+        assert_eq!(YamlMapping(vec![
+            (YamlString("port".to_string()), YamlString("8080\u{a0}".to_string())),
+            (YamlString("name".to_string()), YamlString("\u{3000}app\u{a0}".to_string())),
+            (YamlString("quoted".to_string()), YamlString(" x\u{a0}".to_string()))
+        ]), untrimmed);
+
+        let trimmed = YamlStandardConstructor::new().with_plain_trimming(true).construct(doc.root().unwrap()).unwrap();
+        assert_eq!(YamlMapping(vec![
+            (YamlString("port".to_string()), YamlInteger(8080)),
+            (YamlString("name".to_string()), YamlString("app".to_string())),
+            (YamlString("quoted".to_string()), YamlString(" x\u{a0}".to_string()))
+        ]), trimmed);
+    }
+
+    #[test]
+    fn test_require_string_keys() {
+        let ctor = YamlStandardConstructor::new().require_string_keys(true);
+        let construct = |data: &str| {
+            let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let doc = parser.load().next().unwrap().unwrap();
+            ctor.construct(doc.root().unwrap())
+        };
+
+        assert!(construct("a: 1\n'2': {b: [c]}\n").is_ok());
+        assert!(YamlStandardConstructor::new().construct(YamlByteParser::init(b"1: a", YamlUtf8Encoding)
+            .load().next().unwrap().unwrap().root().unwrap()).is_ok());
+
+        let errors: Vec<(Option<String>, usize)> = ["a:\n  1: x\n", "a: 1\n[b]: 2\n", "{a: 1, {b: c}: 2}", "true: x"].iter().map(|data| {
+            let err = construct(data).unwrap_err();
+            (err.problem, err.context.unwrap().problem_mark.index)
+        }).collect();
+        assert_eq!(vec![
+            (Some("expected a string key but found \"1\"".to_string()), 5),
+            (Some("expected a string key but found a sequence".to_string()), 5),
+            (Some("expected a string key but found a mapping".to_string()), 7),
+            (Some("expected a string key but found \"true\"".to_string()), 0)
+        ], errors);
+    }
+
+    #[test]
+    fn test_reject_duplicate_keys() {
+        let ctor = YamlStandardConstructor::new().reject_duplicate_keys(true);
+        let construct = |ctor: &YamlStandardConstructor, data: &str| {
+            let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let doc = parser.load().next().unwrap().unwrap();
+            ctor.construct(doc.root().unwrap())
+        };
+
+        assert!(construct(&ctor, "a: 1\nb: {a: 2}\n'1': x\n1: y\n").is_ok());
+        assert_eq!(Ok(YamlMapping(vec![(YamlString("a".to_string()), YamlInteger(1)), (YamlString("a".to_string()), YamlInteger(2))])),
+                   construct(&YamlStandardConstructor::new(), "a: 1\na: 2\n"));
+
+        let marks: Vec<((usize, usize), (usize, usize))> = ["a: 1\nb: 2\na: 3\n", "x:\n  1: a\n  0x1: b\n", "{[a, b]: 1, [a, b]: 2}"].iter().map(|data| {
+            let err = construct(&ctor, data).unwrap_err();
+            assert_eq!(Some("found duplicate mapping key".to_string()), err.problem);
+            let context = err.context.unwrap();
+            assert_eq!(Some("first occurrence of the key".to_string()), context.context);
+            ((context.context_mark.line, context.context_mark.column), (context.problem_mark.line, context.problem_mark.column))
+        }).collect();
+        assert_eq!(vec![((0, 0), (2, 0)), ((1, 2), (2, 2)), ((0, 1), (0, 12))], marks);
+    }
+
+    #[test]
+    fn test_tag_registry() {
+        let registry = YamlTagRegistry::new()
+            .with_tag("!duration", |value| {
+                let (number, unit) = value.split_at(value.len().saturating_sub(1));
+                let scale = match unit {
+                    "s" => 1,
+                    "m" => 60,
+                    _ => return None
+                };
+                number.parse::<isize>().ok().map(|n| YamlInteger(n * scale))
+            })
+            .with_tag("tag:yaml.org,2002:upper", |value| Some(YamlString(value.to_uppercase())));
+
+        let data = "timeout: !duration 30s\nretry: !duration \"2m\"\nname: !!upper app\nother: !unknown 5\n";
+        let doc = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).load().next().unwrap().unwrap();
+        assert_eq!(Ok(YamlMapping(vec![
+            (YamlString("timeout".to_string()), YamlInteger(30)),
+            (YamlString("retry".to_string()), YamlInteger(120)),
+            (YamlString("name".to_string()), YamlString("APP".to_string())),
+            (YamlString("other".to_string()), YamlInteger(5))
+        ])), doc.to_value_with_registry(&registry));
+
+        let doc = YamlByteParser::init(b"- !duration soon", YamlUtf8Encoding).load().next().unwrap().unwrap();
+        let err = doc.to_value_with_registry(&registry).unwrap_err();
+        assert_eq!(Some("invalid value \"soon\" for the tag !duration".to_string()), err.problem);
+        assert_eq!(2, err.context.unwrap().problem_mark.index);
+    }
+
+    #[test]
+    fn test_mapping_extract() {
+        use document::YamlNode;
+        use constructor::YamlScalarField;
+
+        let data = "host: example.com\nport: 8080\nratio: 2\ndebug: yes\nextra: [1]\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let mapping = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping,
+            _ => panic!("unexpected root")
+        };
+
+        let mut host: Option<String> = None;
+        let mut port: Option<i64> = None;
+        let mut ratio: Option<f64> = None;
+        let mut debug: Option<bool> = None;
+        let mut timeout: Option<i64> = Some(30);
+        {
+            let mut fields: Vec<(&str, &mut dyn YamlScalarField)> = vec![
+                ("host", &mut host), ("port", &mut port), ("ratio", &mut ratio), ("debug", &mut debug), ("timeout", &mut timeout)
+            ];
+            assert_eq!(Ok(()), mapping.extract(&mut fields, false));
+
+            let err = mapping.extract(&mut fields, true).unwrap_err();
+            assert_eq!(Some("missing key \"timeout\"".to_string()), err.problem);
+        }
+        assert_eq!((Some("example.com".to_string()), Some(8080), Some(2.0), Some(true), Some(30)), (host, port, ratio, debug, timeout));
+
+        let mut host: Option<i64> = None;
+        let mut extra: Option<String> = None;
+        let err = mapping.extract(&mut [("host", &mut host)], false).unwrap_err();
+        assert_eq!((Some("cannot convert \"example.com\" to i64".to_string()), 6), (err.problem, err.context.unwrap().problem_mark.index));
+        let err = mapping.extract(&mut [("extra", &mut extra)], false).unwrap_err();
+        assert_eq!(Some("expected String but found a sequence".to_string()), err.problem);
+    }
 }
@@ -0,0 +1,472 @@
+use constructor::YamlStandardData;
+use document;
+use error::{YamlError, YamlErrorContext, YamlMark};
+use event::{YamlEvent, YamlEventSpec, YamlScalarParam};
+use ffi;
+use ffi::YamlErrorType::*;
+use parser::{YamlParser, YamlByteParser, YamlParserLimits, limit_error};
+
+use serde::de;
+use serde::de::{DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Deserializes the only document of a UTF-8 string, with the errors of `parse_single_document`
+/// for empty input and several documents.
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, YamlError> {
+    from_slice(input.as_bytes())
+}
+
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, YamlError> {
+    let mut deserializer = YamlDeserializer::new(bytes)?;
+    let value = T::deserialize(&mut deserializer).map_err(|err| deserializer.locate(err))?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// Deserializer over the events of a single document, which it reads in full up front.
+///
+/// Plain scalars, and those tagged `!!null`, `!!bool`, `!!int` or `!!float`, resolve like the
+/// `as_*` accessors of `YamlScalarData`, while quoted scalars are strings. A string field takes
+/// the text of any scalar, so `1` can be read as `"1"`. An alias reads its anchored node again,
+/// and reading more events through aliases than the `max_alias_expansions` of the default
+/// `YamlParserLimits` is a `YAML_LIMIT_ERROR`.
+pub struct YamlDeserializer {
+    events: Vec<YamlEvent>,
+    // the node each alias refers to, as the range of its events
+    alias_targets: HashMap<usize, (usize, usize)>,
+    // ranges of events being read, the last being the innermost alias
+    frames: Vec<(usize, usize)>,
+    // events read again through aliases so far
+    expanded: usize,
+    last_mark: YamlMark
+}
+
+impl YamlDeserializer {
+    pub fn new(bytes: &[u8]) -> Result<YamlDeserializer, YamlError> {
+        let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+        let mut events = Vec::new();
+        let mut documents = 0;
+
+        for res in parser.parse() {
+            let evt = res?;
+            match evt.spec {
+                YamlEventSpec::YamlDocumentStartEvent(..) => {
+                    documents += 1;
+                    if documents > 1 {
+                        return Err(YamlError::new(YAML_COMPOSER_ERROR, Some("expected a single document but found another".to_string())));
+                    }
+                },
+                YamlEventSpec::YamlStreamStartEvent(_) | YamlEventSpec::YamlStreamEndEvent | YamlEventSpec::YamlDocumentEndEvent(_) => (),
+                _ => events.push(evt)
+            }
+        }
+        if documents == 0 {
+            return Err(YamlError::new(YAML_COMPOSER_ERROR, Some("expected a single document but the input is empty".to_string())));
+        }
+
+        let alias_targets = find_alias_targets(&events)?;
+        let end = events.len();
+        Ok(YamlDeserializer {
+            events,
+            alias_targets,
+            frames: vec![(0, end)],
+            expanded: 0,
+            last_mark: YamlMark { index: 0, line: 0, column: 0 }
+        })
+    }
+
+    /// Checks that the whole document was read.
+    pub fn end(&mut self) -> Result<(), YamlError> {
+        match self.peek()? {
+            None => Ok(()),
+            Some(evt) => Err(error_at("found trailing content after the document".to_string(), &evt.start))
+        }
+    }
+
+    // The next event, having entered any alias in the way.
+    fn peek(&mut self) -> Result<Option<&YamlEvent>, YamlError> {
+        loop {
+            let (pos, end) = match self.frames.last() {
+                Some(&frame) => frame,
+                None => return Ok(None)
+            };
+            if pos == end {
+                if self.frames.len() == 1 {
+                    return Ok(None);
+                }
+                self.frames.pop();
+                continue;
+            }
+            if let YamlEventSpec::YamlAliasEvent(_) = self.events[pos].spec {
+                let (start, end) = self.alias_targets[&pos];
+                self.expanded += end - start;
+                let max = YamlParserLimits::default().max_alias_expansions;
+                if self.expanded > max {
+                    return Err(limit_error(format!("aliases expand to more than {} events", max), &self.events[pos].start));
+                }
+                self.frames.last_mut().unwrap().0 += 1;
+                self.frames.push((start, end));
+                continue;
+            }
+            return Ok(Some(&self.events[pos]));
+        }
+    }
+
+    fn next(&mut self) -> Result<YamlEvent, YamlError> {
+        let evt = match self.peek()? {
+            Some(evt) => evt.clone(),
+            None => return Err(error_at("unexpected end of the document".to_string(), &self.last_mark))
+        };
+        self.frames.last_mut().unwrap().0 += 1;
+        self.last_mark = evt.start;
+        Ok(evt)
+    }
+
+    fn peek_null(&mut self) -> Result<bool, YamlError> {
+        match self.peek()? {
+            Some(&YamlEvent { spec: YamlEventSpec::YamlScalarEvent(ref param), ref start, .. }) =>
+                Ok(resolve(param, start)? == Some(YamlStandardData::YamlNull)),
+            _ => Ok(false)
+        }
+    }
+
+    fn expect_end(&mut self, mapping: bool) -> Result<(), YamlError> {
+        let evt = self.next()?;
+        match evt.spec {
+            YamlEventSpec::YamlSequenceEndEvent if !mapping => Ok(()),
+            YamlEventSpec::YamlMappingEndEvent if mapping => Ok(()),
+            _ => Err(error_at(format!("found more {} than expected", if mapping { "entries" } else { "items" }), &evt.start))
+        }
+    }
+
+    // Places an error raised by a visitor at the last event read.
+    fn locate(&self, mut err: YamlError) -> YamlError {
+        if err.context.is_none() {
+            err.context = error_at(String::new(), &self.last_mark).context;
+        }
+        err
+    }
+}
+
+fn error_at(problem: String, mark: &YamlMark) -> YamlError {
+    YamlError {
+        kind: YAML_VALIDATION_ERROR,
+        problem: Some(problem),
+        io_error: None,
+        context: Some(YamlErrorContext {
+            byte_offset: mark.index,
+            problem_mark: *mark,
+            context: None,
+            context_mark: *mark
+        })
+    }
+}
+
+// Anchors may be redefined, so each alias is matched with the latest anchor before it. An alias
+// inside the node its anchor is on would be read forever and is an error.
+fn find_alias_targets(events: &[YamlEvent]) -> Result<HashMap<usize, (usize, usize)>, YamlError> {
+    let mut anchors: HashMap<&str, (usize, Option<usize>)> = HashMap::new();
+    let mut open: Vec<Option<(&str, usize)>> = Vec::new();
+    let mut targets = HashMap::new();
+
+    for (index, evt) in events.iter().enumerate() {
+        match evt.spec {
+            YamlEventSpec::YamlScalarEvent(ref param) => if let Some(ref anchor) = param.anchor {
+                anchors.insert(&anchor[..], (index, Some(index + 1)));
+            },
+            YamlEventSpec::YamlSequenceStartEvent(ref param) | YamlEventSpec::YamlMappingStartEvent(ref param) => {
+                if let Some(ref anchor) = param.anchor {
+                    anchors.insert(&anchor[..], (index, None));
+                }
+                open.push(param.anchor.as_ref().map(|anchor| (&anchor[..], index)));
+            },
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => {
+                if let Some(Some((anchor, start))) = open.pop() {
+                    // unless the anchor was redefined inside the collection
+                    if let Some(target) = anchors.get_mut(anchor).filter(|target| target.0 == start) {
+                        target.1 = Some(index + 1);
+                    }
+                }
+            },
+            YamlEventSpec::YamlAliasEvent(ref name) => match anchors.get(&name[..]) {
+                Some(&(start, Some(end))) => {
+                    targets.insert(index, (start, end));
+                },
+                Some(&(_, None)) => return Err(error_at(format!("found recursive alias *{}", name), &evt.start)),
+                None => return Err(error_at(format!("found undefined alias *{}", name), &evt.start))
+            },
+            _ => ()
+        }
+    }
+    Ok(targets)
+}
+
+fn resolve(param: &YamlScalarParam, mark: &YamlMark) -> Result<Option<YamlStandardData>, YamlError> {
+    match document::core_scalar(param.tag.as_ref().map(|tag| &tag[..]), &param.value, param.style, mark) {
+        Some(res) => res.map(Some),
+        None => Ok(None)
+    }
+}
+
+impl de::Error for YamlError {
+    fn custom<T: fmt::Display>(msg: T) -> YamlError {
+        YamlError::new(YAML_VALIDATION_ERROR, Some(msg.to_string()))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut YamlDeserializer {
+    type Error = YamlError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        let evt = self.next()?;
+        match evt.spec {
+            YamlEventSpec::YamlScalarEvent(param) => match resolve(&param, &evt.start)? {
+                Some(YamlStandardData::YamlInteger(i)) => visitor.visit_i64(i as i64),
+                // integers past i64::MAX resolve to floats, but one that fits in a u64 reads back as such
+                Some(YamlStandardData::YamlFloat(f)) => match (&param.tag, param.value.parse::<u64>()) {
+                    (&None, Ok(u)) => visitor.visit_u64(u),
+                    _ => visitor.visit_f64(f)
+                },
+                Some(YamlStandardData::YamlBool(b)) => visitor.visit_bool(b),
+                Some(YamlStandardData::YamlNull) => visitor.visit_unit(),
+                _ => visitor.visit_string(param.value)
+            },
+            YamlEventSpec::YamlSequenceStartEvent(_) => {
+                let value = visitor.visit_seq(YamlSeqAccess { de: &mut *self })?;
+                self.expect_end(false)?;
+                Ok(value)
+            },
+            YamlEventSpec::YamlMappingStartEvent(_) => {
+                let value = visitor.visit_map(YamlMapAccess { de: &mut *self })?;
+                self.expect_end(true)?;
+                Ok(value)
+            },
+            _ => Err(error_at("expected a node".to_string(), &evt.start))
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        if !matches!(self.peek()?, Some(&YamlEvent { spec: YamlEventSpec::YamlScalarEvent(_), .. })) {
+            return self.deserialize_any(visitor);
+        }
+        match self.next()?.spec {
+            YamlEventSpec::YamlScalarEvent(param) => visitor.visit_string(param.value),
+            _ => unreachable!()
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        if self.peek_null()? {
+            self.next()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, YamlError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, YamlError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, YamlError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // a unit variant is a scalar naming it, and any other variant a mapping from its name to
+    // its content
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V)
+        -> Result<V::Value, YamlError>
+    {
+        let evt = self.next()?;
+        match evt.spec {
+            YamlEventSpec::YamlScalarEvent(param) => visitor.visit_enum(param.value.into_deserializer()),
+            YamlEventSpec::YamlMappingStartEvent(_) => {
+                let value = visitor.visit_enum(YamlEnumAccess { de: &mut *self })?;
+                self.expect_end(true)?;
+                Ok(value)
+            },
+            _ => Err(error_at("expected an enum variant".to_string(), &evt.start))
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 bytes byte_buf
+        seq tuple tuple_struct map struct ignored_any
+    }
+}
+
+struct YamlSeqAccess<'a> {
+    de: &'a mut YamlDeserializer
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for YamlSeqAccess<'a> {
+    type Error = YamlError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, YamlError> {
+        match self.de.peek()? {
+            Some(&YamlEvent { spec: YamlEventSpec::YamlSequenceEndEvent, .. }) => Ok(None),
+            _ => seed.deserialize(&mut *self.de).map(Some)
+        }
+    }
+}
+
+struct YamlMapAccess<'a> {
+    de: &'a mut YamlDeserializer
+}
+
+impl<'de, 'a> de::MapAccess<'de> for YamlMapAccess<'a> {
+    type Error = YamlError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, YamlError> {
+        match self.de.peek()? {
+            Some(&YamlEvent { spec: YamlEventSpec::YamlMappingEndEvent, .. }) => Ok(None),
+            _ => seed.deserialize(&mut *self.de).map(Some)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, YamlError> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+struct YamlEnumAccess<'a> {
+    de: &'a mut YamlDeserializer
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for YamlEnumAccess<'a> {
+    type Error = YamlError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), YamlError> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for YamlEnumAccess<'a> {
+    type Error = YamlError;
+
+    fn unit_variant(self) -> Result<(), YamlError> {
+        de::Deserialize::deserialize(self.de)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, YamlError> {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, YamlError> {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, YamlError> {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use de::from_str;
+    use error::YamlError;
+    use ffi::YamlErrorType;
+
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        name: String,
+        port: u16,
+        tags: Vec<String>,
+        limits: HashMap<String, f64>,
+        backup: Option<Box<Server>>,
+        mode: Mode
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Mode {
+        Active,
+        Weighted(u8),
+        Range { low: i32, high: i32 }
+    }
+
+    #[test]
+    fn test_from_str() {
+        let data = "name: main\nport: 8080\ntags: [a, 1, 'yes']\nlimits: &l {cpu: 1.5, mem: 2}\n\
+                    backup:\n  name: spare\n  port: 0x50\n  tags: []\n  limits: *l\n  backup: ~\n  mode: {Range: {low: -1, high: 1}}\n\
+                    mode: Active\n";
+        let server: Server = from_str(data).unwrap();
+        let limits: HashMap<String, f64> = vec![("cpu".to_string(), 1.5), ("mem".to_string(), 2.0)].into_iter().collect();
+        assert_eq!(Server {
+            name: "main".to_string(),
+            port: 8080,
+            tags: vec!["a".to_string(), "1".to_string(), "yes".to_string()],
+            limits: limits.clone(),
+            backup: Some(Box::new(Server {
+                name: "spare".to_string(),
+                port: 80,
+                tags: vec![],
+                limits,
+                backup: None,
+                mode: Mode::Range { low: -1, high: 1 }
+            })),
+            mode: Mode::Active
+        }, server);
+
+        assert_eq!(Ok(vec![Mode::Weighted(3), Mode::Active]), from_str("[{Weighted: 3}, Active]"));
+        assert_eq!(Ok((true, None, Some(2))), from_str::<(bool, Option<u8>, Option<u8>)>("[yes, null, 2]"));
+        assert_eq!(Ok(vec![vec![1], vec![2], vec![2]]), from_str::<Vec<Vec<u8>>>("[&a [1], &a [2], *a]"));
+    }
+
+    #[test]
+    fn test_from_str_errors() {
+        let error = |data: &str| -> (String, (usize, usize)) {
+            let err: YamlError = from_str::<HashMap<String, Vec<u8>>>(data).unwrap_err();
+            let mark = err.context.unwrap().problem_mark;
+            (err.problem.unwrap(), (mark.line, mark.column))
+        };
+
+        assert_eq!(("invalid value: integer `300`, expected u8".to_string(), (1, 7)), error("a: [1]\nb: [2, 300]\n"));
+        assert_eq!(("invalid type: string \"x\", expected a sequence".to_string(), (0, 3)), error("a: x\n"));
+        assert_eq!(("found recursive alias *a".to_string(), (0, 7)), error("a: &a [*a]\n"));
+        assert_eq!(("found undefined alias *b".to_string(), (0, 3)), error("a: *b\n"));
+        assert_eq!(Some("expected a single document but found another".to_string()),
+                   from_str::<u8>("1\n--- 2\n").unwrap_err().problem);
+
+        // a plain scalar with a tag outside the core schema is a string
+        for &data in ["[!!str 1]", "[!x 1]"].iter() {
+            assert_eq!(Some("invalid type: string \"1\", expected u8".to_string()), from_str::<Vec<u8>>(data).unwrap_err().problem);
+        }
+    }
+
+    #[test]
+    fn test_from_str_alias_expansion() {
+        use serde::de::IgnoredAny;
+
+        let mut data = "a0: &a0 [x, x, x, x, x, x, x, x, x, x]\n".to_string();
+        for level in 1..4 {
+            data.push_str(&format!("a{}: &a{} [{}]\n", level, level, vec![format!("*a{}", level - 1); 10].join(", ")));
+        }
+        assert!(from_str::<IgnoredAny>(&data).is_ok());
+
+        data.push_str(&format!("a4: [{}]\n", vec!["*a3"; 10].join(", ")));
+        let err = from_str::<IgnoredAny>(&data).unwrap_err();
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, err.kind);
+    }
+}
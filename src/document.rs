@@ -3,25 +3,779 @@ use libc;
 use codecs;
 use ffi;
 use ffi::yaml_node_type_t::*;
-use error::YamlMark;
+use ffi::yaml_event_type_t::*;
+use ffi::YamlErrorType::*;
+use error::{YamlError, YamlErrorContext, YamlMark};
+use constructor;
+use constructor::{FromYamlScalar, YamlConstructor, YamlRadix, YamlScalarField, YamlStandardConstructor, YamlStandardData, YamlTagRegistry, YamlTagResolver, YamlTimestamp};
+use event::{YamlEventSpec, YamlVersionDirective, YamlTagDirective, YamlScalarParam, YamlSequenceParam};
+use parser;
+use parser::{InternalEvent, YamlParserLimits};
+use emitter;
+use emitter::{YamlEventEmitter, YamlEmitter};
 
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
 use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::OnceLock;
 use std::mem;
+use std::mem::MaybeUninit;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum YamlNodeKind {
+    YamlScalarKind,
+    YamlSequenceKind,
+    YamlMappingKind,
+}
+
+struct YamlAnchor {
+    name: String,
+    index: libc::c_int
+}
+
+// an alias the composer resolved to the node at `index`
+struct YamlAlias {
+    name: String,
+    index: libc::c_int,
+    mark: YamlMark,
+    // whether the alias is inside the collection it refers to
+    recursive: bool
+}
 
 pub struct YamlDocument {
-    document_mem: ffi::yaml_document_t
+    document_mem: ffi::yaml_document_t,
+    anchors: Vec<YamlAnchor>,
+    aliases: Vec<YamlAlias>,
+    // nodes given a tag in the source, as libyaml stores a default tag for the others
+    tagged: HashSet<libc::c_int>,
+    // untagged scalars whose tag a YamlTagResolver chose
+    resolved: HashSet<libc::c_int>
+}
+
+fn node_kind(node: &ffi::yaml_node_t) -> YamlNodeKind {
+    match node.node_type {
+        YAML_SCALAR_NODE => YamlNodeKind::YamlScalarKind,
+        YAML_SEQUENCE_NODE => YamlNodeKind::YamlSequenceKind,
+        YAML_MAPPING_NODE => YamlNodeKind::YamlMappingKind,
+        _ => panic!("invalid node")
+    }
+}
+
+unsafe fn parse_internal_event(parser: &mut ffi::yaml_parser_t) -> Option<InternalEvent> {
+    let mut event_mem = MaybeUninit::uninit();
+
+    if ffi::yaml_parser_parse(parser, event_mem.as_mut_ptr()) == 0 {
+        None
+    } else {
+        Some(InternalEvent {
+            event_mem: event_mem.assume_init()
+        })
+    }
+}
+
+unsafe fn set_composer_error(parser: &mut ffi::yaml_parser_t,
+        context: Option<(&'static [u8], ffi::yaml_mark_t)>,
+        problem: &'static [u8], problem_mark: ffi::yaml_mark_t) {
+    parser.error = YAML_COMPOSER_ERROR;
+    if let Some((context, context_mark)) = context {
+        parser.context = context.as_ptr() as *const libc::c_char;
+        parser.context_mark = context_mark;
+    }
+    parser.problem = problem.as_ptr() as *const libc::c_char;
+    parser.problem_mark = problem_mark;
+}
+
+fn added_node(index: libc::c_int) -> Result<libc::c_int, YamlError> {
+    if index == 0 {
+        Err(YamlError::new(YAML_MEMORY_ERROR, None))
+    } else {
+        Ok(index)
+    }
+}
+
+const CORE_SCALAR_TAGS: [&str; 4] = [ffi::YAML_NULL_TAG, ffi::YAML_BOOL_TAG, ffi::YAML_INT_TAG, ffi::YAML_FLOAT_TAG];
+
+// compiling the constructor's patterns is costly, so the scalar accessors share one
+pub(crate) fn core_constructor() -> &'static YamlStandardConstructor {
+    static CTOR: OnceLock<YamlStandardConstructor> = OnceLock::new();
+    CTOR.get_or_init(YamlStandardConstructor::new)
+}
+
+// The core schema value of an untagged plain scalar or one with an explicit null, bool, int or
+// float tag, which is an error if the value does not fit the tag. Other scalars are strings and
+// give `None`.
+pub(crate) fn core_scalar(tag: Option<&str>, value: &str, style: ffi::YamlScalarStyle, mark: &YamlMark) -> Option<Result<YamlStandardData, YamlError>> {
+    let ctor = core_constructor();
+    match tag {
+        Some(tag) if CORE_SCALAR_TAGS.contains(&tag) => Some(ctor.construct_tagged_scalar(tag, value.to_string(), mark)),
+        None if style == ffi::YamlScalarStyle::YamlPlainScalarStyle => Some(Ok(ctor.resolve_plain(value.to_string()))),
+        _ => None
+    }
+}
+
+// The kind of a scalar key's value and a text equal for equal values, so that keys can be
+// compared through a hash. NaN equals nothing, so it gives `None`.
+fn hashable_key(value: YamlStandardData) -> Option<(u8, String)> {
+    match value {
+        YamlStandardData::YamlString(text) => Some((0, text)),
+        YamlStandardData::YamlInteger(i) => Some((1, i.to_string())),
+        YamlStandardData::YamlFloat(f) if f.is_nan() => None,
+        // -0.0 equals 0.0
+        YamlStandardData::YamlFloat(f) => Some((2, (if f == 0.0 { 0.0 } else { f }).to_bits().to_string())),
+        YamlStandardData::YamlBool(b) => Some((3, b.to_string())),
+        YamlStandardData::YamlNull => Some((4, String::new())),
+        YamlStandardData::YamlSequence(_) | YamlStandardData::YamlMapping(_) => None
+    }
+}
+
+// plain unless that would read back as another type
+pub(crate) fn string_style(text: &str, ctor: &YamlStandardConstructor) -> ffi::YamlScalarStyle {
+    match ctor.resolve_plain(text.to_string()) {
+        YamlStandardData::YamlString(_) => ffi::YamlScalarStyle::YamlPlainScalarStyle,
+        _ => ffi::YamlScalarStyle::YamlSingleQuotedScalarStyle
+    }
+}
+
+pub(crate) fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        ".nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { ".inf" } else { "-.inf" }.to_string()
+    } else {
+        // Debug keeps the fraction of whole numbers, so 1.0 does not read back as an integer
+        format!("{:?}", value)
+    }
+}
+
+// the items of a sequence, or the keys and values of a mapping
+unsafe fn child_indices(node: &ffi::yaml_node_t) -> Vec<libc::c_int> {
+    let mut children = Vec::new();
+    match node.node_type {
+        YAML_SEQUENCE_NODE => {
+            let sequence_data: &ffi::yaml_sequence_node_t = mem::transmute(&node.data);
+            let mut item = sequence_data.items.start as *const libc::c_int;
+            while item != sequence_data.items.top as *const libc::c_int {
+                children.push(*item);
+                item = item.offset(1);
+            }
+        },
+        YAML_MAPPING_NODE => {
+            let mapping_data: &ffi::yaml_sequence_node_t = mem::transmute(&node.data);
+            let mut pair = mapping_data.items.start as *const ffi::yaml_node_pair_t;
+            while pair != mapping_data.items.top as *const ffi::yaml_node_pair_t {
+                children.push((*pair).key);
+                children.push((*pair).value);
+                pair = pair.offset(1);
+            }
+        },
+        _ => ()
+    }
+    children
+}
+
+fn collect_radixes(node: YamlNode, path: &mut Vec<usize>, open: &mut Vec<*const ffi::yaml_node_t>,
+        radixes: &mut HashMap<Vec<usize>, YamlRadix>) {
+    let ptr = match node {
+        YamlNode::YamlScalarNode(ref scalar) => {
+            if let Some(radix) = scalar.radix() {
+                radixes.insert(path.clone(), radix);
+            }
+            return;
+        },
+        YamlNode::YamlSequenceNode(ref sequence) => unsafe { sequence.internal_node() as *const _ },
+        YamlNode::YamlMappingNode(ref mapping) => unsafe { mapping.internal_node() as *const _ }
+    };
+    if open.contains(&ptr) {
+        return;
+    }
+
+    open.push(ptr);
+    let children: Vec<YamlNode> = match node {
+        YamlNode::YamlSequenceNode(sequence) => sequence.values().collect(),
+        YamlNode::YamlMappingNode(mapping) => mapping.pairs().flat_map(|(key, value)| vec![key, value]).collect(),
+        YamlNode::YamlScalarNode(_) => Vec::new()
+    };
+    for (i, child) in children.into_iter().enumerate() {
+        path.push(i);
+        collect_radixes(child, path, open, radixes);
+        path.pop();
+    }
+    open.pop();
+}
+
+// the non-specific tag "!" resolves to the default tag, as in libyaml's loader
+unsafe fn node_tag(tag: *const ffi::yaml_char_t) -> *const ffi::yaml_char_t {
+    if !tag.is_null() && *tag == b'!' && *tag.offset(1) == 0 {
+        ptr::null()
+    } else {
+        tag
+    }
 }
 
 impl YamlDocument {
-    pub unsafe fn parser_load(parser: &mut ffi::yaml_parser_t) -> Option<Box<YamlDocument>> {
+    // Mirrors yaml_parser_load, which discards anchor names once aliases are resolved.
+    pub(crate) unsafe fn parser_load(parser: &mut ffi::yaml_parser_t, resolver: Option<&dyn YamlTagResolver>) -> Option<Box<YamlDocument>> {
+        let mut document = Box::new(YamlDocument {
+            document_mem: mem::zeroed(),
+            anchors: Vec::new(),
+            aliases: Vec::new(),
+            tagged: HashSet::new(),
+            resolved: HashSet::new()
+        });
+
+        if parser.stream_start_produced == 0 {
+            parse_internal_event(parser)?;
+        }
+
+        if parser.stream_end_produced != 0 {
+            return Some(document);
+        }
+
+        let event = parse_internal_event(parser)?;
+        match event.event_mem.event_type {
+            YAML_STREAM_END_EVENT | YAML_NO_EVENT => Some(document),
+            _ => if document.compose(parser, &event.event_mem, resolver) {
+                Some(document)
+            } else {
+                None
+            }
+        }
+    }
+
+    unsafe fn compose(&mut self, parser: &mut ffi::yaml_parser_t, start: &ffi::yaml_event_t,
+            resolver: Option<&dyn YamlTagResolver>) -> bool {
+        let start_data: &ffi::yaml_document_start_event_t = mem::transmute(&start.data);
+        if ffi::yaml_document_initialize(&mut self.document_mem, start_data.version_directive,
+                start_data.tag_directives.start, start_data.tag_directives.end,
+                start_data.implicit, 0) == 0 {
+            parser.error = YAML_MEMORY_ERROR;
+            return false;
+        }
+        self.document_mem.start_mark = start.start_mark;
+
+        // open collections, with the pending key of each mapping
+        let mut parents: Vec<(libc::c_int, Option<libc::c_int>)> = Vec::new();
+        // the node of each anchor, as `self.anchors` keeps them in order for `anchors`
+        let mut anchor_nodes: HashMap<String, libc::c_int> = HashMap::new();
+
+        loop {
+            let event = match parse_internal_event(parser) {
+                Some(event) => event,
+                None => return false
+            };
+            let evt = &event.event_mem;
+
+            let (index, anchor, tag, collection) = match evt.event_type {
+                YAML_DOCUMENT_END_EVENT => {
+                    let end_data: &ffi::yaml_document_end_event_t = mem::transmute(&evt.data);
+                    self.document_mem.end_implicit = end_data.implicit;
+                    self.document_mem.end_mark = evt.end_mark;
+                    return true;
+                },
+                YAML_ALIAS_EVENT => {
+                    let alias_data: &ffi::yaml_alias_event_t = mem::transmute(&evt.data);
+                    let name = codecs::decode_c_str(alias_data.anchor).unwrap();
+                    match anchor_nodes.get(&name) {
+                        Some(&index) => {
+                            self.aliases.push(YamlAlias {
+                                name,
+                                index,
+                                mark: YamlMark::conv(&evt.start_mark),
+                                recursive: parents.iter().any(|&(parent, _)| parent == index)
+                            });
+                            (index, ptr::null(), ptr::null(), false)
+                        },
+                        None => {
+                            set_composer_error(parser, None, b"found undefined alias\0", evt.start_mark);
+                            return false;
+                        }
+                    }
+                },
+                YAML_SCALAR_EVENT => {
+                    let scalar_data: &ffi::yaml_scalar_event_t = mem::transmute(&evt.data);
+                    // libyaml copies the tag, so the resolved one only has to outlive the call
+                    let resolved_tag = match resolver {
+                        Some(resolver) if scalar_data.tag.is_null() => {
+                            let value = str::from_utf8(slice::from_raw_parts(scalar_data.value, scalar_data.length)).unwrap();
+                            resolver.resolve(value, scalar_data.style).and_then(|tag| CString::new(tag).ok())
+                        },
+                        _ => None
+                    };
+                    let tag = match resolved_tag {
+                        Some(ref tag) => tag.as_ptr() as *const ffi::yaml_char_t,
+                        None => node_tag(scalar_data.tag)
+                    };
+                    let index = ffi::yaml_document_add_scalar(&mut self.document_mem, tag,
+                        scalar_data.value, scalar_data.length as libc::c_int, scalar_data.style);
+                    if resolved_tag.is_some() {
+                        self.resolved.insert(index);
+                    }
+                    (index, scalar_data.anchor, scalar_data.tag, false)
+                },
+                YAML_SEQUENCE_START_EVENT => {
+                    let sequence_data: &ffi::yaml_sequence_start_event_t = mem::transmute(&evt.data);
+                    let index = ffi::yaml_document_add_sequence(&mut self.document_mem, node_tag(sequence_data.tag),
+                        sequence_data.style);
+                    (index, sequence_data.anchor, sequence_data.tag, true)
+                },
+                YAML_MAPPING_START_EVENT => {
+                    let mapping_data: &ffi::yaml_mapping_start_event_t = mem::transmute(&evt.data);
+                    let index = ffi::yaml_document_add_mapping(&mut self.document_mem, node_tag(mapping_data.tag),
+                        mapping_data.style);
+                    (index, mapping_data.anchor, mapping_data.tag, true)
+                },
+                YAML_SEQUENCE_END_EVENT | YAML_MAPPING_END_EVENT => {
+                    let (index, _) = parents.pop().unwrap();
+                    let node = ffi::yaml_document_get_node(&self.document_mem, index) as *mut ffi::yaml_node_t;
+                    (*node).end_mark = evt.end_mark;
+                    continue;
+                },
+                _ => {
+                    set_composer_error(parser, None, b"found unexpected event\0", evt.start_mark);
+                    return false;
+                }
+            };
+
+            if index == 0 {
+                parser.error = YAML_MEMORY_ERROR;
+                return false;
+            }
+
+            if evt.event_type != YAML_ALIAS_EVENT {
+                let node = ffi::yaml_document_get_node(&self.document_mem, index) as *mut ffi::yaml_node_t;
+                (*node).start_mark = evt.start_mark;
+                (*node).end_mark = evt.end_mark;
+                if !tag.is_null() {
+                    self.tagged.insert(index);
+                }
+
+                if let Some(name) = codecs::decode_c_str(anchor) {
+                    if let Some(&first) = anchor_nodes.get(&name) {
+                        let first_node = ffi::yaml_document_get_node(&self.document_mem, first);
+                        set_composer_error(parser, Some((b"found duplicate anchor; first occurrence\0", (*first_node).start_mark)),
+                            b"second occurrence\0", evt.start_mark);
+                        return false;
+                    }
+                    anchor_nodes.insert(name.clone(), index);
+                    self.anchors.push(YamlAnchor {
+                        name,
+                        index
+                    });
+                }
+            }
+
+            if let Some(&mut (parent, ref mut key)) = parents.last_mut() {
+                let parent_node = ffi::yaml_document_get_node(&self.document_mem, parent);
+                let appended = match (*parent_node).node_type {
+                    YAML_SEQUENCE_NODE => ffi::yaml_document_append_sequence_item(&mut self.document_mem, parent, index),
+                    _ => match key.take() {
+                        Some(key_index) => ffi::yaml_document_append_mapping_pair(&mut self.document_mem, parent, key_index, index),
+                        None => {
+                            *key = Some(index);
+                            1
+                        }
+                    }
+                };
+
+                if appended == 0 {
+                    parser.error = YAML_MEMORY_ERROR;
+                    return false;
+                }
+            }
+
+            if collection {
+                parents.push((index, None));
+            }
+        }
+    }
+
+    /// Builds a document holding `value`, to inspect through the node API or emit with `to_events`.
+    ///
+    /// Scalars are written so that `YamlStandardConstructor` reads the same value back: strings
+    /// whose plain form would resolve to another type, such as `"1"` or `"yes"`, are single quoted.
+    pub fn from_value(value: &YamlStandardData) -> Result<Box<YamlDocument>, YamlError> {
+        YamlDocument::from_value_with_radixes(value, &HashMap::new())
+    }
+
+    /// Like `from_value`, but the integers at the paths of `radixes` are written in the given
+    /// base, so that the map `integer_radixes` found in a loaded document keeps `0o755` from
+    /// being written back as `493`.
+    pub fn from_value_with_radixes(value: &YamlStandardData, radixes: &HashMap<Vec<usize>, YamlRadix>) -> Result<Box<YamlDocument>, YamlError> {
+        let mut document = YamlDocument::empty_value_document()?;
+        document.add_value(value, core_constructor(), None, (radixes, &mut Vec::new()))?;
+        Ok(document)
+    }
+
+    /// The base of every integer of the document written in binary, octal or hexadecimal, by
+    /// the path that leads to it in `to_value`: the index of each sequence item from the root,
+    /// with the pairs of a mapping counting as a key then a value, so the `0xff` of
+    /// `{a: 1, b: 0xff}` is at `[3]`. Nodes inside a recursive alias are not visited.
+    pub fn integer_radixes(&self) -> HashMap<Vec<usize>, YamlRadix> {
+        let mut radixes = HashMap::new();
+        if let Some(root) = self.root() {
+            collect_radixes(root, &mut Vec::new(), &mut Vec::new(), &mut radixes);
+        }
+        radixes
+    }
+
+    /// Copies the document into a `YamlStandardData`, which does not borrow the document and
+    /// can be kept after it is dropped. An empty document gives `YamlNull`.
+    pub fn to_value(&self) -> Result<YamlStandardData, YamlError> {
+        match self.root() {
+            Some(node) => core_constructor().construct(node),
+            None => Ok(YamlStandardData::YamlNull)
+        }
+    }
+
+    /// Like `from_value`, but a non-empty sequence or mapping equal to one seen before becomes
+    /// the same node, emitted by `to_events` as an alias. Anchors are named `a1`, `a2`, ... in
+    /// the order they appear in the output, so equal values always give the same names.
+    ///
+    /// Each collection is compared with every earlier one, which is meant for config-sized values.
+    pub fn from_value_shared(value: &YamlStandardData) -> Result<Box<YamlDocument>, YamlError> {
+        let mut document = YamlDocument::empty_value_document()?;
+        document.add_value(value, core_constructor(), Some(&mut Vec::new()), (&HashMap::new(), &mut Vec::new()))?;
+
+        document.anchors.sort_by_key(|anchor| anchor.index);
+        for (n, anchor) in document.anchors.iter_mut().enumerate() {
+            anchor.name = format!("a{}", n + 1);
+        }
+        Ok(document)
+    }
+
+    fn empty_value_document() -> Result<Box<YamlDocument>, YamlError> {
         let mut document = Box::new(YamlDocument {
-            document_mem: mem::uninitialized()
+            document_mem: unsafe { mem::zeroed() },
+            anchors: Vec::new(),
+            aliases: Vec::new(),
+            tagged: HashSet::new(),
+            resolved: HashSet::new()
         });
 
-        if ffi::yaml_parser_load(parser, &mut document.document_mem) == 0 {
+        unsafe {
+            if ffi::yaml_document_initialize(&mut document.document_mem, ptr::null(), ptr::null(), ptr::null(), 1, 1) == 0 {
+                return Err(YamlError::new(YAML_MEMORY_ERROR, None));
+            }
+        }
+        Ok(document)
+    }
+
+    // `shared` holds the collections added so far when equal ones should share a node, and
+    // `radixes` the bases of integers by their path, with the path of `value`.
+    fn add_value<'v>(&mut self, value: &'v YamlStandardData, ctor: &YamlStandardConstructor,
+            mut shared: Option<&mut Vec<(&'v YamlStandardData, libc::c_int)>>,
+            radixes: (&HashMap<Vec<usize>, YamlRadix>, &mut Vec<usize>)) -> Result<libc::c_int, YamlError> {
+        let (radixes, path) = radixes;
+        let doc: *mut ffi::yaml_document_t = &mut self.document_mem;
+        let collection = match *value {
+            YamlStandardData::YamlSequence(ref items) => !items.is_empty(),
+            YamlStandardData::YamlMapping(ref pairs) => !pairs.is_empty(),
+            _ => false
+        };
+        if collection {
+            if let Some(seen) = shared.as_mut() {
+                if let Some(&(_, index)) = seen.iter().find(|&&(seen_value, _)| seen_value == value) {
+                    if !self.anchors.iter().any(|anchor| anchor.index == index) {
+                        self.anchors.push(YamlAnchor {
+                            name: String::new(),
+                            index
+                        });
+                    }
+                    return Ok(index);
+                }
+            }
+        }
+
+        let (text, style) = match *value {
+            YamlStandardData::YamlSequence(ref items) => {
+                let index = added_node(unsafe { ffi::yaml_document_add_sequence(doc, ptr::null(), ffi::YamlSequenceStyle::YamlAnySequenceStyle) })?;
+                if let Some(seen) = shared.as_mut() {
+                    seen.push((value, index));
+                }
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i);
+                    let item_index = self.add_value(item, ctor, shared.as_deref_mut(), (radixes, path))?;
+                    path.pop();
+                    added_node(unsafe { ffi::yaml_document_append_sequence_item(doc, index, item_index) })?;
+                }
+                return Ok(index);
+            },
+            YamlStandardData::YamlMapping(ref pairs) => {
+                let index = added_node(unsafe { ffi::yaml_document_add_mapping(doc, ptr::null(), ffi::YamlSequenceStyle::YamlAnySequenceStyle) })?;
+                if let Some(seen) = shared.as_mut() {
+                    seen.push((value, index));
+                }
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    path.push(2 * i);
+                    let key_index = self.add_value(key, ctor, shared.as_deref_mut(), (radixes, path))?;
+                    path.pop();
+                    path.push(2 * i + 1);
+                    let value_index = self.add_value(value, ctor, shared.as_deref_mut(), (radixes, path))?;
+                    path.pop();
+                    added_node(unsafe { ffi::yaml_document_append_mapping_pair(doc, index, key_index, value_index) })?;
+                }
+                return Ok(index);
+            },
+            YamlStandardData::YamlString(ref text) => (text.clone(), string_style(text, ctor)),
+            YamlStandardData::YamlInteger(i) => match radixes.get(&path[..]) {
+                Some(radix) => (radix.format(i), ffi::YamlScalarStyle::YamlPlainScalarStyle),
+                None => (i.to_string(), ffi::YamlScalarStyle::YamlPlainScalarStyle)
+            },
+            YamlStandardData::YamlFloat(f) => (float_repr(f), ffi::YamlScalarStyle::YamlPlainScalarStyle),
+            YamlStandardData::YamlBool(b) => (b.to_string(), ffi::YamlScalarStyle::YamlPlainScalarStyle),
+            YamlStandardData::YamlNull => ("null".to_string(), ffi::YamlScalarStyle::YamlPlainScalarStyle)
+        };
+
+        added_node(unsafe {
+            ffi::yaml_document_add_scalar(doc, ptr::null(), text.as_ptr(), text.len() as libc::c_int, style)
+        })
+    }
+
+    /// Converts the document with the default schema, except for scalars whose tag is in
+    /// `registry`. An empty document converts to `YamlNull`.
+    pub fn to_value_with_registry(&self, registry: &YamlTagRegistry) -> Result<YamlStandardData, YamlError> {
+        let ctor = YamlStandardConstructor::new().with_tag_registry(registry.clone());
+        match self.root() {
+            Some(root) => ctor.construct(root),
+            None => Ok(YamlStandardData::YamlNull)
+        }
+    }
+
+    /// Emits the document as a single-document UTF-8 stream, keeping its anchors, tags and styles.
+    pub fn to_writer(&self, writer: &mut dyn Write) -> Result<(), YamlError> {
+        let events = self.to_events();
+        let mut emitter = YamlEmitter::init(writer);
+        emitter.emit_stream(ffi::YamlEncoding::YamlUtf8Encoding, |e| e.emit_events(&events))
+    }
+
+    /// Like `to_writer`, but returns the output as a string.
+    pub fn to_string(&self) -> Result<String, YamlError> {
+        let output = reencode(self, ffi::YamlEncoding::YamlUtf8Encoding)?;
+        // libyaml emits valid UTF-8
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    /// Events reproducing the document, with nodes in document order. Nodes shared through
+    /// an alias are emitted under their anchor the first time and as aliases afterwards. Only
+    /// the tags given in the source are written, so a tag chosen by a `YamlTagResolver` is left
+    /// for the next parse to resolve again.
+    pub fn to_events(&self) -> Vec<YamlEventSpec> {
+        let doc = &self.document_mem;
+        let version_directive = if doc.version_directive.is_null() {
             None
         } else {
-            Some(document)
+            let directive = unsafe { &*doc.version_directive };
+            Some(YamlVersionDirective { major: directive.major as isize, minor: directive.minor as isize })
+        };
+        let mut tag_directives = Vec::new();
+        let mut tag_ptr = doc.tag_directives.start;
+        while !tag_ptr.is_null() && tag_ptr != doc.tag_directives.end {
+            unsafe {
+                tag_directives.push(YamlTagDirective {
+                    handle: codecs::decode_c_str((*tag_ptr).handle as *const ffi::yaml_char_t).unwrap(),
+                    prefix: codecs::decode_c_str((*tag_ptr).prefix as *const ffi::yaml_char_t).unwrap()
+                });
+                tag_ptr = tag_ptr.offset(1);
+            }
+        }
+
+        let mut events = vec![YamlEventSpec::YamlDocumentStartEvent(version_directive, tag_directives, doc.start_implicit != 0)];
+        if !self.is_empty() {
+            self.node_events(1, &mut HashSet::new(), &mut events);
+        }
+        events.push(YamlEventSpec::YamlDocumentEndEvent(doc.end_implicit != 0));
+        events
+    }
+
+    fn node_events(&self, index: libc::c_int, emitted: &mut HashSet<libc::c_int>, events: &mut Vec<YamlEventSpec>) {
+        let anchor = self.anchors.iter().find(|anchor| anchor.index == index).map(|anchor| anchor.name.clone());
+        if !emitted.insert(index) {
+            if let Some(anchor) = anchor {
+                events.push(YamlEventSpec::YamlAliasEvent(anchor));
+                return;
+            }
+        }
+
+        unsafe {
+            let node = &*ffi::yaml_document_get_node(&self.document_mem, index);
+            let tag = codecs::decode_c_str(node.tag);
+            // libyaml stores a default tag on the nodes without one
+            let implicit = !self.tagged.contains(&index);
+            match node.node_type {
+                YAML_SCALAR_NODE => {
+                    let scalar_data: &ffi::yaml_scalar_node_t = mem::transmute(&node.data);
+                    let plain = scalar_data.style == ffi::YamlScalarStyle::YamlPlainScalarStyle;
+                    events.push(YamlEventSpec::YamlScalarEvent(YamlScalarParam {
+                        anchor,
+                        tag: if implicit { None } else { tag },
+                        value: codecs::decode_buf(scalar_data.value, scalar_data.length).unwrap(),
+                        plain_implicit: implicit && plain,
+                        // a plain scalar the emitter has to quote still reads back as a string
+                        quoted_implicit: implicit,
+                        style: scalar_data.style
+                    }));
+                },
+                YAML_SEQUENCE_NODE => {
+                    let sequence_data: &ffi::yaml_sequence_node_t = mem::transmute(&node.data);
+                    events.push(YamlEventSpec::YamlSequenceStartEvent(YamlSequenceParam {
+                        anchor,
+                        tag: if implicit { None } else { tag },
+                        implicit,
+                        style: sequence_data.style
+                    }));
+                    let mut item = sequence_data.items.start as *const libc::c_int;
+                    while item != sequence_data.items.top as *const libc::c_int {
+                        self.node_events(*item, emitted, events);
+                        item = item.offset(1);
+                    }
+                    events.push(YamlEventSpec::YamlSequenceEndEvent);
+                },
+                YAML_MAPPING_NODE => {
+                    let mapping_data: &ffi::yaml_sequence_node_t = mem::transmute(&node.data);
+                    events.push(YamlEventSpec::YamlMappingStartEvent(YamlSequenceParam {
+                        anchor,
+                        tag: if implicit { None } else { tag },
+                        implicit,
+                        style: mapping_data.style
+                    }));
+                    let mut pair = mapping_data.items.start as *const ffi::yaml_node_pair_t;
+                    while pair != mapping_data.items.top as *const ffi::yaml_node_pair_t {
+                        self.node_events((*pair).key, emitted, events);
+                        self.node_events((*pair).value, emitted, events);
+                        pair = pair.offset(1);
+                    }
+                    events.push(YamlEventSpec::YamlMappingEndEvent);
+                },
+                _ => panic!("invalid node")
+            }
+        }
+    }
+
+    pub fn start_mark(&self) -> YamlMark {
+        YamlMark::conv(&self.document_mem.start_mark)
+    }
+
+    pub fn end_mark(&self) -> YamlMark {
+        YamlMark::conv(&self.document_mem.end_mark)
+    }
+
+    pub fn anchors(&self) -> Vec<(String, YamlNodeKind)> {
+        self.anchors.iter().map(|anchor| {
+            let kind = unsafe { node_kind(&*ffi::yaml_document_get_node(&self.document_mem, anchor.index)) };
+            (anchor.name.clone(), kind)
+        }).collect()
+    }
+
+    /// Checks that every alias refers to an anchor defined before it, outside the alias itself.
+    ///
+    /// Loading already fails on an alias to an undefined anchor, so for a loaded document this
+    /// mostly reports recursive aliases such as the one in `&a [*a]`, whose node contains itself
+    /// and would send a constructor into endless recursion. The error names the alias and is at
+    /// its position.
+    pub fn check_aliases(&self) -> Result<(), YamlError> {
+        for alias in self.aliases.iter() {
+            let problem = if !self.anchors.iter().any(|anchor| anchor.name == alias.name && anchor.index == alias.index) {
+                "found undefined alias"
+            } else if alias.recursive {
+                "found recursive alias"
+            } else {
+                continue;
+            };
+            return Err(YamlError {
+                kind: YAML_COMPOSER_ERROR,
+                problem: Some(format!("{} *{}", problem, alias.name)),
+                io_error: None,
+                context: Some(YamlErrorContext {
+                    byte_offset: alias.mark.index,
+                    problem_mark: alias.mark,
+                    context: None,
+                    context_mark: alias.mark
+                })
+            });
+        }
+        Ok(())
+    }
+
+    /// Fails with a `YAML_LIMIT_ERROR` if the document exceeds `limits`, before anything
+    /// copies its aliased nodes. The error is at the node whose content goes over a limit.
+    /// Recursive aliases expand without end, so they fail as in `check_aliases`.
+    pub fn check_limits(&self, limits: &YamlParserLimits) -> Result<(), YamlError> {
+        if self.node_count() > limits.max_document_nodes {
+            return Err(parser::limit_error(format!("document exceeds the maximum of {} nodes", limits.max_document_nodes),
+                &self.start_mark()));
+        }
+        self.check_aliases()?;
+
+        if self.is_empty() {
+            return Ok(());
+        }
+        // the expanded size and collection depth of each node, which is shared by its aliases;
+        // nodes are visited without recursion, as the depth is not known to be small yet
+        let mut sizes: Vec<Option<(usize, usize)>> = vec![None; self.node_count() + 1];
+        // the root is the first node
+        let mut stack: Vec<(libc::c_int, bool)> = vec![(1, false)];
+        while let Some((index, children_done)) = stack.pop() {
+            if sizes[index as usize].is_some() {
+                continue;
+            }
+            let node = unsafe { &*ffi::yaml_document_get_node(&self.document_mem, index) };
+            let children = unsafe { child_indices(node) };
+            if !children_done {
+                stack.push((index, true));
+                stack.extend(children.iter().map(|&child| (child, false)));
+                continue;
+            }
+
+            let mut size: usize = 1;
+            let mut depth = 0;
+            for child in children {
+                let (child_size, child_depth) = sizes[child as usize].unwrap();
+                size = size.saturating_add(child_size);
+                depth = depth.max(child_depth);
+            }
+            if node_kind(node) != YamlNodeKind::YamlScalarKind {
+                depth += 1;
+            }
+            let mark = YamlMark::conv(&node.start_mark);
+            if size > limits.max_alias_expansions {
+                return Err(parser::limit_error(format!("document exceeds the maximum of {} nodes after alias expansion", limits.max_alias_expansions), &mark));
+            }
+            if depth > limits.max_depth {
+                return Err(parser::limit_error(format!("document exceeds the maximum nesting depth of {}", limits.max_depth), &mark));
+            }
+            sizes[index as usize] = Some((size, depth));
+        }
+        Ok(())
+    }
+
+    /// Fails on the first mapping holding a key twice, as found by `find_duplicate_keys`, with
+    /// the error `reject_duplicate_keys` gives when constructing: it is at the repeated key
+    /// and its context points at the first occurrence.
+    pub fn check_duplicate_keys(&self) -> Result<(), YamlError> {
+        for index in 1..=self.node_count() {
+            if let YamlNode::YamlMappingNode(mapping) = unsafe { self.get_node(index as libc::c_int) } {
+                if let Some(&(first, second, _)) = mapping.duplicate_keys().first() {
+                    return Err(constructor::duplicate_key_error(first, second));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The kind of the root node, or `None` for an empty document, without loading the node.
+    pub fn root_kind(&self) -> Option<YamlNodeKind> {
+        unsafe {
+            let node_ptr = ffi::yaml_document_get_root_node(&self.document_mem);
+            if node_ptr.is_null() {
+                None
+            } else {
+                Some(node_kind(&*node_ptr))
+            }
         }
     }
 
@@ -31,17 +785,27 @@ impl YamlDocument {
         }
     }
 
+    /// Number of distinct nodes; an alias shares the node of its anchor rather than adding one.
+    pub fn node_count(&self) -> usize {
+        let nodes = &self.document_mem.nodes;
+        (nodes.top as usize - nodes.start as usize) / mem::size_of::<ffi::yaml_node_t>()
+    }
+
     unsafe fn load<'r>(&'r self, node_ptr: *const ffi::yaml_node_t) -> YamlNode<'r> {
         if node_ptr == ptr::null() {
             panic!("empty node")
         }
         let node = &*node_ptr;
+        let index = (node_ptr as usize - self.document_mem.nodes.start as usize) / mem::size_of::<ffi::yaml_node_t>() + 1;
+        let tagged = self.tagged.contains(&(index as libc::c_int));
         match node.node_type {
             YAML_SCALAR_NODE => {
                 let scalar_data: &ffi::yaml_scalar_node_t = mem::transmute(&node.data);
                 YamlNode::YamlScalarNode(YamlScalarData {
                     node: node,
-                    data: scalar_data
+                    data: scalar_data,
+                    tagged,
+                    resolved: self.resolved.contains(&(index as libc::c_int))
                 })
             },
             YAML_SEQUENCE_NODE => {
@@ -49,7 +813,8 @@ impl YamlDocument {
                 YamlNode::YamlSequenceNode(YamlSequenceData {
                     doc: self,
                     node: node,
-                    data: sequence_data
+                    data: sequence_data,
+                    tagged
                 })
             },
             YAML_MAPPING_NODE => {
@@ -57,7 +822,8 @@ impl YamlDocument {
                 YamlNode::YamlMappingNode(YamlMappingData {
                     doc: self,
                     node: node,
-                    data: mapping_data
+                    data: mapping_data,
+                    tagged
                 })
             },
             _ => panic!("invalid node")
@@ -69,6 +835,31 @@ impl YamlDocument {
         self.load(node_ptr)
     }
 
+    /// Walks the root node with `YamlNode::accept`; an empty document calls nothing.
+    pub fn accept<V: YamlVisitor>(&self, visitor: &mut V) {
+        if let Some(root) = self.root() {
+            root.accept(visitor);
+        }
+    }
+
+    /// Looks up a node by a path such as `servers.0.host` or `a.b[2]`.
+    ///
+    /// Dotted segments name mapping keys, or index a sequence when they are a number; bracketed
+    /// segments only index sequences. An empty path is the root. A missing key, an index past
+    /// the end, a segment applied to a scalar, a bracket applied to a mapping or a malformed
+    /// path all give `None`. Aliases are already resolved to the anchored node, so they are
+    /// followed transparently.
+    pub fn query<'r>(&'r self, path: &str) -> Option<YamlNode<'r>> {
+        let segments = parse_query(path)?;
+        segments.iter().try_fold(self.root()?, |node, segment| match (node, segment) {
+            (YamlNode::YamlMappingNode(mapping), &QuerySegment::Key(key)) => mapping.get(key),
+            (YamlNode::YamlSequenceNode(seq), &QuerySegment::Key(key)) =>
+                key.parse::<usize>().ok().and_then(|idx| seq.get(idx)),
+            (YamlNode::YamlSequenceNode(seq), &QuerySegment::Index(idx)) => seq.get(idx),
+            _ => None
+        })
+    }
+
     pub fn root<'r>(&'r self) -> Option<YamlNode<'r>> {
         unsafe {
             let node_ptr = ffi::yaml_document_get_root_node(&self.document_mem);
@@ -81,6 +872,38 @@ impl YamlDocument {
     }
 }
 
+enum QuerySegment<'p> {
+    Key(&'p str),
+    Index(usize)
+}
+
+fn parse_query(path: &str) -> Option<Vec<QuerySegment<'_>>> {
+    let mut segments = Vec::new();
+    if path.is_empty() {
+        return Some(segments);
+    }
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(pos) => (&part[..pos], &part[pos..]),
+            None => (part, "")
+        };
+        if !key.is_empty() {
+            segments.push(QuerySegment::Key(key));
+        } else if rest.is_empty() {
+            return None;
+        }
+        while !rest.is_empty() {
+            if !rest.starts_with('[') {
+                return None;
+            }
+            let close = rest.find(']')?;
+            segments.push(QuerySegment::Index(rest[1..close].parse().ok()?));
+            rest = &rest[close + 1..];
+        }
+    }
+    Some(segments)
+}
+
 impl Drop for YamlDocument {
     fn drop(&mut self) {
         unsafe {
@@ -89,21 +912,259 @@ impl Drop for YamlDocument {
     }
 }
 
+/// A node added to a `YamlDocumentBuilder`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct YamlNodeId(libc::c_int);
+
+/// Builds a document node by node with libyaml's `yaml_document_add_*` functions, for emitting
+/// with `YamlDocument::to_string` or `to_writer`.
+///
+/// The first node added is the root. Nodes get the default tag of their kind, and a node may
+/// be added to several collections, which emits it as an alias after the first.
+pub struct YamlDocumentBuilder {
+    document: Box<YamlDocument>
+}
+
+impl YamlDocumentBuilder {
+    pub fn new() -> Result<YamlDocumentBuilder, YamlError> {
+        Ok(YamlDocumentBuilder {
+            document: YamlDocument::empty_value_document()?
+        })
+    }
+
+    pub fn add_scalar(&mut self, value: &str, style: ffi::YamlScalarStyle) -> Result<YamlNodeId, YamlError> {
+        added_node(unsafe {
+            ffi::yaml_document_add_scalar(&mut self.document.document_mem, ptr::null(), value.as_ptr(), value.len() as libc::c_int, style)
+        }).map(YamlNodeId)
+    }
+
+    /// Adds `bytes` as a `!!binary` scalar, in base64 broken into a literal block of 76
+    /// character lines.
+    pub fn add_binary(&mut self, bytes: &[u8]) -> Result<YamlNodeId, YamlError> {
+        let encoded = codecs::encode_base64(bytes);
+        let mut text = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+        for line in encoded.as_bytes().chunks(76) {
+            // base64 is ASCII
+            text.push_str(str::from_utf8(line).unwrap());
+            text.push('\n');
+        }
+        let tag = CString::new(ffi::YAML_BINARY_TAG).unwrap();
+        let index = added_node(unsafe {
+            ffi::yaml_document_add_scalar(&mut self.document.document_mem, tag.as_ptr() as *const ffi::yaml_char_t,
+                text.as_ptr(), text.len() as libc::c_int, ffi::YamlScalarStyle::YamlLiteralScalarStyle)
+        })?;
+        self.document.tagged.insert(index);
+        Ok(YamlNodeId(index))
+    }
+
+    pub fn add_sequence(&mut self, style: ffi::YamlSequenceStyle) -> Result<YamlNodeId, YamlError> {
+        added_node(unsafe {
+            ffi::yaml_document_add_sequence(&mut self.document.document_mem, ptr::null(), style)
+        }).map(YamlNodeId)
+    }
+
+    /// libyaml takes the flow or block style of a mapping as a `YamlSequenceStyle`.
+    pub fn add_mapping(&mut self, style: ffi::YamlSequenceStyle) -> Result<YamlNodeId, YamlError> {
+        added_node(unsafe {
+            ffi::yaml_document_add_mapping(&mut self.document.document_mem, ptr::null(), style)
+        }).map(YamlNodeId)
+    }
+
+    /// Appends `item` to the sequence `seq`. An id past the nodes of this builder, which libyaml
+    /// would abort on, a `seq` that is not a sequence, and an `item` that is `seq` or contains
+    /// it, which would make the document recursive, are a `YAML_COMPOSER_ERROR`. The id of a
+    /// node of another builder is only caught when it is out of range.
+    pub fn append(&mut self, seq: YamlNodeId, item: YamlNodeId) -> Result<(), YamlError> {
+        self.check_node(seq, Some(YamlNodeKind::YamlSequenceKind))?;
+        self.check_node(item, None)?;
+        self.check_not_contained(seq, item)?;
+        added_node(unsafe {
+            ffi::yaml_document_append_sequence_item(&mut self.document.document_mem, seq.0, item.0)
+        }).map(|_| ())
+    }
+
+    /// Adds the pair `key: value` to the mapping `map`, failing as `append` does.
+    pub fn insert(&mut self, map: YamlNodeId, key: YamlNodeId, value: YamlNodeId) -> Result<(), YamlError> {
+        self.check_node(map, Some(YamlNodeKind::YamlMappingKind))?;
+        self.check_node(key, None)?;
+        self.check_node(value, None)?;
+        self.check_not_contained(map, key)?;
+        self.check_not_contained(map, value)?;
+        added_node(unsafe {
+            ffi::yaml_document_append_mapping_pair(&mut self.document.document_mem, map.0, key.0, value.0)
+        }).map(|_| ())
+    }
+
+    pub fn build(self) -> Box<YamlDocument> {
+        self.document
+    }
+
+    fn check_node(&self, id: YamlNodeId, kind: Option<YamlNodeKind>) -> Result<(), YamlError> {
+        let node = unsafe { ffi::yaml_document_get_node(&self.document.document_mem, id.0) };
+        let problem = if node.is_null() {
+            format!("node {} is not in the document", id.0)
+        } else {
+            match kind {
+                Some(kind) if node_kind(unsafe { &*node }) != kind => format!("node {} is not a {:?}", id.0, kind),
+                _ => return Ok(())
+            }
+        };
+        Err(YamlError::new(YAML_COMPOSER_ERROR, Some(problem)))
+    }
+
+    // Fails if `node` is `collection` or one of its descendants is, as adding it to the
+    // collection would then put the collection inside itself.
+    fn check_not_contained(&self, collection: YamlNodeId, node: YamlNodeId) -> Result<(), YamlError> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![node.0];
+        while let Some(index) = stack.pop() {
+            if index == collection.0 {
+                return Err(YamlError::new(YAML_COMPOSER_ERROR, Some(format!("node {} contains node {}", node.0, collection.0))));
+            }
+            if visited.insert(index) {
+                stack.extend(unsafe { child_indices(&*ffi::yaml_document_get_node(&self.document.document_mem, index)) });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Emits `doc` as a single-document stream in the `target` encoding, regardless of the
+/// encoding it was parsed from. UTF-16 output starts with a byte order mark.
+pub fn reencode(doc: &YamlDocument, target: ffi::YamlEncoding) -> Result<Vec<u8>, YamlError> {
+    let mut events = vec![YamlEventSpec::YamlStreamStartEvent(target)];
+    events.extend(doc.to_events());
+    let mut output = Vec::with_capacity(emitter::estimate_emit_size(&events));
+    {
+        let mut emitter = YamlEmitter::init(&mut output);
+        emitter.emit_stream(target, |e| {
+            for evt in events[1..].iter() {
+                e.emit_event(evt)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(output)
+}
+
+/// A node of a loaded document. An alias is not a node of its own: it loads as the node its
+/// anchor is on, so `*a` and `&a 1` give the same scalar.
 pub enum YamlNode<'r> {
     YamlScalarNode(YamlScalarData<'r>),
     YamlSequenceNode(YamlSequenceData<'r>),
     YamlMappingNode(YamlMappingData<'r>),
 }
 
+impl<'r> YamlNode<'r> {
+    /// Walks the node depth first, calling `visitor` for each scalar and around each collection.
+    ///
+    /// A key is handed to `visit_key` instead of being walked, and its value is walked after.
+    /// Aliased nodes are walked again each time, so a recursive alias walks without end; see
+    /// `YamlDocument::check_aliases`.
+    pub fn accept<V: YamlVisitor>(&self, visitor: &mut V) {
+        match *self {
+            YamlNode::YamlScalarNode(ref scalar) => visitor.visit_scalar(scalar),
+            YamlNode::YamlSequenceNode(ref sequence) => {
+                visitor.visit_sequence_start(sequence);
+                for item in sequence.values() {
+                    item.accept(visitor);
+                }
+                visitor.visit_sequence_end(sequence);
+            },
+            YamlNode::YamlMappingNode(ref mapping) => {
+                visitor.visit_mapping_start(mapping);
+                for (key, value) in mapping.pairs() {
+                    visitor.visit_key(&key);
+                    value.accept(visitor);
+                }
+                visitor.visit_mapping_end(mapping);
+            }
+        }
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.as_scalar().is_some()
+    }
+
+    pub fn is_sequence(&self) -> bool {
+        self.as_sequence().is_some()
+    }
+
+    pub fn is_mapping(&self) -> bool {
+        self.as_mapping().is_some()
+    }
+
+    pub fn as_scalar(&self) -> Option<&YamlScalarData<'r>> {
+        match *self {
+            YamlNode::YamlScalarNode(ref scalar) => Some(scalar),
+            _ => None
+        }
+    }
+
+    pub fn as_sequence(&self) -> Option<&YamlSequenceData<'r>> {
+        match *self {
+            YamlNode::YamlSequenceNode(ref sequence) => Some(sequence),
+            _ => None
+        }
+    }
+
+    pub fn as_mapping(&self) -> Option<&YamlMappingData<'r>> {
+        match *self {
+            YamlNode::YamlMappingNode(ref mapping) => Some(mapping),
+            _ => None
+        }
+    }
+}
+
+/// Formats the tree in flow style, with scalar values quoted and without tags; `{:#?}` puts
+/// each item on its own line.
+impl<'r> fmt::Debug for YamlNode<'r> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            YamlNode::YamlScalarNode(ref scalar) => scalar.get_value().fmt(f),
+            YamlNode::YamlSequenceNode(ref sequence) => f.debug_list().entries(sequence.values()).finish(),
+            YamlNode::YamlMappingNode(ref mapping) => f.debug_map().entries(mapping.pairs()).finish()
+        }
+    }
+}
+
+/// Callbacks for `YamlNode::accept` and `YamlDocument::accept`, which do nothing by default.
+///
+/// A complex key can be walked from `visit_key` by calling `accept` on it.
+pub trait YamlVisitor {
+    fn visit_scalar(&mut self, _scalar: &YamlScalarData) {}
+    fn visit_sequence_start(&mut self, _sequence: &YamlSequenceData) {}
+    fn visit_sequence_end(&mut self, _sequence: &YamlSequenceData) {}
+    fn visit_mapping_start(&mut self, _mapping: &YamlMappingData) {}
+    fn visit_mapping_end(&mut self, _mapping: &YamlMappingData) {}
+    fn visit_key(&mut self, _key: &YamlNode) {}
+}
+
 pub trait YamlNodeData {
     unsafe fn internal_node<'r>(&'r self) -> &'r ffi::yaml_node_t;
 
+    /// Whether the node was given a tag in the source, `!` included, rather than the default
+    /// tag of its kind.
+    fn has_explicit_tag(&self) -> bool;
+
+    /// The tag URI of the node. Nodes without a tag in the source have the default tag of
+    /// their kind, such as `tag:yaml.org,2002:str` for scalars, whatever they resolve to.
     fn tag(&self) -> Option<String> {
         unsafe {
             codecs::decode_c_str(self.internal_node().tag)
         }
     }
 
+    /// The tag given in the source, resolved to a URI, so `!!str 123` has one and `123` has none.
+    fn explicit_tag(&self) -> Option<String> {
+        if self.has_explicit_tag() { self.tag() } else { None }
+    }
+
+    /// The tag split into handle and suffix by `codecs::split_tag`.
+    fn tag_parts(&self) -> Option<(Option<String>, String)> {
+        self.tag().map(|tag| codecs::split_tag(&tag))
+    }
+
     fn start_mark(&self) -> YamlMark {
         unsafe {
             YamlMark::conv(&self.internal_node().start_mark)
@@ -117,37 +1178,177 @@ pub trait YamlNodeData {
     }
 }
 
+/// How the trailing line breaks of a block scalar are kept, shown by `|-`, `|` and `|+`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum YamlChomping {
+    /// No trailing line break.
+    YamlStripChomping,
+    /// A single trailing line break.
+    YamlClipChomping,
+    /// Every trailing line break.
+    YamlKeepChomping,
+}
+
+#[derive(Clone)]
 pub struct YamlScalarData<'r> {
     node: &'r ffi::yaml_node_t,
-    data: &'r ffi::yaml_scalar_node_t
+    data: &'r ffi::yaml_scalar_node_t,
+    tagged: bool,
+    resolved: bool
 }
 
 impl<'r> YamlNodeData for YamlScalarData<'r> {
     unsafe fn internal_node<'a>(&'a self) -> &'a ffi::yaml_node_t {
         self.node
     }
+
+    fn has_explicit_tag(&self) -> bool {
+        self.tagged
+    }
 }
 
 impl<'r> YamlScalarData<'r> {
     pub fn get_value(&self) -> String {
-        codecs::decode_buf(self.data.value, self.data.length).unwrap()
+        self.get_str().to_string()
+    }
+
+    /// Like `get_value`, but borrowed from the document instead of copied.
+    pub fn get_str(&self) -> &'r str {
+        unsafe {
+            str::from_utf8(slice::from_raw_parts(self.data.value, self.data.length)).unwrap()
+        }
     }
 
     pub fn style(&self) -> ffi::YamlScalarStyle {
         self.data.style
     }
+
+    /// The timestamp a plain scalar such as `2001-12-15T02:59:43.1Z` or `2002-12-14` resolves
+    /// to, or that a scalar tagged `!!timestamp` holds.
+    pub fn as_timestamp(&self) -> Option<YamlTimestamp> {
+        let plain = !self.tagged && !self.resolved && self.data.style == ffi::YamlScalarStyle::YamlPlainScalarStyle;
+        if plain || self.tag().is_some_and(|tag| tag == ffi::YAML_TIMESTAMP_TAG) {
+            YamlTimestamp::parse(self.get_str())
+        } else {
+            None
+        }
+    }
+
+    /// The base of a plain integer such as `0o755`, `0xff` or `0b101`, or of one tagged `!!int`.
+    /// Decimal integers and other scalars give `None`.
+    pub fn radix(&self) -> Option<YamlRadix> {
+        let plain = !self.tagged && !self.resolved && self.data.style == ffi::YamlScalarStyle::YamlPlainScalarStyle;
+        if plain || self.tag().is_some_and(|tag| tag == ffi::YAML_INT_TAG) {
+            core_constructor().match_radix_int(self.get_str()).map(|(_, radix)| radix)
+        } else {
+            None
+        }
+    }
+
+    /// The bytes of a scalar tagged `!!binary`, decoded from base64 with any whitespace and
+    /// line breaks skipped. Untagged scalars and invalid base64 give `None`.
+    pub fn as_binary(&self) -> Option<Vec<u8>> {
+        if self.tag().is_some_and(|tag| tag == ffi::YAML_BINARY_TAG) {
+            codecs::decode_base64(self.get_str())
+        } else {
+            None
+        }
+    }
+
+    /// The chomping of a literal or folded scalar, or `None` for other styles.
+    ///
+    /// libyaml only reports the value, so this is the indicator that writes it back, which is
+    /// also the one the emitter picks: `|+` over a single trailing break reads as `|`.
+    pub fn chomping(&self) -> Option<YamlChomping> {
+        match self.data.style {
+            ffi::YamlScalarStyle::YamlLiteralScalarStyle | ffi::YamlScalarStyle::YamlFoldedScalarStyle => (),
+            _ => return None
+        }
+
+        let value = self.get_value();
+        Some(if !value.ends_with('\n') {
+            YamlChomping::YamlStripChomping
+        } else if value == "\n" || value.ends_with("\n\n") {
+            YamlChomping::YamlKeepChomping
+        } else {
+            YamlChomping::YamlClipChomping
+        })
+    }
+
+    /// Whether the tag was chosen by the `YamlTagResolver` of the parser. The typed accessors
+    /// then follow the tag instead of the core schema, as does `YamlStandardConstructor` for
+    /// plain scalars.
+    pub fn has_resolved_tag(&self) -> bool {
+        self.resolved
+    }
+
+    // The core schema value, which is `None` for strings and values that do not fit their tag.
+    fn core_value(&self) -> Option<YamlStandardData> {
+        // libyaml stores a default tag on the scalars without one
+        let tag = if self.tagged || self.resolved { self.tag() } else { None };
+        core_scalar(tag.as_ref().map(|tag| &tag[..]), &self.get_value(), self.data.style, &self.start_mark())
+            .and_then(Result::ok)
+    }
+
+    /// The integer a plain scalar such as `-12`, `0x1F`, `0o17` or `1_000` resolves to, or
+    /// that a scalar tagged `!!int` holds.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.core_value().and_then(i64::from_yaml_scalar)
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().and_then(|i| if i >= 0 { Some(i as u64) } else { None })
+    }
+
+    /// Like `as_i64`, but also accepts floats, including `.inf`, `-.inf` and `.nan`.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.core_value().and_then(f64::from_yaml_scalar)
+    }
+
+    /// The boolean of a plain `true` or `false`, in any of the core schema's capitalizations,
+    /// or of a scalar tagged `!!bool`. A quoted `"true"` is a string, so it gives `None`.
+    pub fn as_bool(&self) -> Option<bool> {
+        let value = self.core_value().and_then(bool::from_yaml_scalar)?;
+        let tagged = self.tag().is_some_and(|tag| tag == ffi::YAML_BOOL_TAG);
+        match &self.get_value()[..] {
+            "true" | "True" | "TRUE" | "false" | "False" | "FALSE" => Some(value),
+            _ if tagged => Some(value),
+            _ => None
+        }
+    }
+
+    /// Like `as_bool`, but also accepts the YAML 1.1 booleans `yes`, `no`, `on` and `off`.
+    pub fn as_yaml11_bool(&self) -> Option<bool> {
+        let untagged_plain = self.data.style == ffi::YamlScalarStyle::YamlPlainScalarStyle
+            && !self.tagged && !self.resolved;
+        match &self.get_value()[..] {
+            "yes" | "Yes" | "YES" | "on" | "On" | "ON" if untagged_plain => Some(true),
+            "no" | "No" | "NO" | "off" | "Off" | "OFF" if untagged_plain => Some(false),
+            _ => self.as_bool()
+        }
+    }
+
+    /// Whether the scalar is a plain `null`, `~` or empty value, or is tagged `!!null`.
+    pub fn is_null(&self) -> bool {
+        self.core_value() == Some(YamlStandardData::YamlNull)
+    }
 }
 
 pub struct YamlSequenceData<'r> {
     doc: &'r YamlDocument,
     node: &'r ffi::yaml_node_t,
-    data: &'r ffi::yaml_sequence_node_t
+    data: &'r ffi::yaml_sequence_node_t,
+    tagged: bool
 }
 
 impl<'r> YamlNodeData for YamlSequenceData<'r> {
     unsafe fn internal_node<'a>(&'a self) -> &'a ffi::yaml_node_t {
         self.node
     }
+
+    fn has_explicit_tag(&self) -> bool {
+        self.tagged
+    }
 }
 
 impl<'r> YamlSequenceData<'r> {
@@ -158,6 +1359,34 @@ impl<'r> YamlSequenceData<'r> {
             ptr: self.data.items.start as *const libc::c_int
         }
     }
+
+    /// The flow or block style of the source, which `YamlDocument::to_events` emits again.
+    pub fn style(&self) -> ffi::YamlSequenceStyle {
+        self.data.style
+    }
+
+    /// The number of items, read from the node without walking them.
+    pub fn len(&self) -> usize {
+        unsafe {
+            (self.data.items.top as *const libc::c_int).offset_from(self.data.items.start as *const libc::c_int) as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Converts each item with `FromYamlScalar`, yielding an error at the item's mark for
+    /// collections and scalars of another type.
+    pub fn iter_as<T: FromYamlScalar>(&self) -> impl Iterator<Item = Result<T, YamlError>> + 'r {
+        let ctor = core_constructor();
+        self.values().map(move |node| constructor::convert_scalar(ctor, node))
+    }
+
+    /// The item at `idx`, or `None` past the end of the sequence.
+    pub fn get(&self, idx: usize) -> Option<YamlNode<'r>> {
+        self.values().nth(idx)
+    }
 }
 
 pub struct YamlSequenceIter<'r> {
@@ -187,13 +1416,18 @@ impl<'r> Iterator for YamlSequenceIter<'r> {
 pub struct YamlMappingData<'r> {
     doc: &'r YamlDocument,
     node: &'r ffi::yaml_node_t,
-    data: &'r ffi::yaml_sequence_node_t
+    data: &'r ffi::yaml_sequence_node_t,
+    tagged: bool
 }
 
 impl<'r> YamlNodeData for YamlMappingData<'r> {
     unsafe fn internal_node<'a>(&'a self) -> &'a ffi::yaml_node_t {
         self.node
     }
+
+    fn has_explicit_tag(&self) -> bool {
+        self.tagged
+    }
 }
 
 impl<'r> YamlMappingData<'r> {
@@ -204,6 +1438,216 @@ impl<'r> YamlMappingData<'r> {
             ptr: self.data.items.start as *const ffi::yaml_node_pair_t
         }
     }
+
+    /// Like `YamlSequenceData::style`; libyaml gives mappings the same style type.
+    pub fn style(&self) -> ffi::YamlSequenceStyle {
+        self.data.style
+    }
+
+    /// The number of pairs, read from the node without walking them. Pairs merged in by
+    /// `merged_pairs` are not counted.
+    pub fn len(&self) -> usize {
+        unsafe {
+            (self.data.items.top as *const ffi::yaml_node_pair_t).offset_from(self.data.items.start as *const ffi::yaml_node_pair_t) as usize
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value of the first pair whose key is a scalar with the value `key`.
+    ///
+    /// Nodes are built on access, so there is no `Index` impl to borrow them from.
+    pub fn get(&self, key: &str) -> Option<YamlNode<'r>> {
+        self.pairs().find(|(k, _)| match k {
+            YamlNode::YamlScalarNode(scalar) => scalar.get_value() == key,
+            _ => false
+        }).map(|(_, value)| value)
+    }
+
+    /// `as_i64` of the value at `key`, which is `None` both for a missing key and for a value
+    /// that is not an integer; `try_get_i64` tells them apart.
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(|node| node.as_scalar().and_then(|scalar| scalar.as_i64()))
+    }
+
+    /// `Ok(None)` for a missing key, and an error at the value when it is not an integer.
+    pub fn try_get_i64(&self, key: &str) -> Result<Option<i64>, YamlError> {
+        self.get(key).map(|node| constructor::convert_scalar(core_constructor(), node)).transpose()
+    }
+
+    /// `as_bool` of the value at `key`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(|node| node.as_scalar().and_then(|scalar| scalar.as_bool()))
+    }
+
+    /// The text of the scalar at `key`, whatever it resolves to, so `port: 80` gives `"80"`.
+    pub fn get_str(&self, key: &str) -> Option<&'r str> {
+        match self.get(key) {
+            Some(YamlNode::YamlScalarNode(scalar)) => Some(scalar.get_str()),
+            _ => None
+        }
+    }
+
+    pub fn get_seq(&self, key: &str) -> Option<YamlSequenceData<'r>> {
+        match self.get(key) {
+            Some(YamlNode::YamlSequenceNode(sequence)) => Some(sequence),
+            _ => None
+        }
+    }
+
+    pub fn get_map(&self, key: &str) -> Option<YamlMappingData<'r>> {
+        match self.get(key) {
+            Some(YamlNode::YamlMappingNode(mapping)) => Some(mapping),
+            _ => None
+        }
+    }
+
+    /// The pairs with `<<` merge keys flattened, as in the YAML 1.1 merge key type.
+    ///
+    /// A merge key is a plain `<<` or one tagged `tag:yaml.org,2002:merge`. Its value is a
+    /// mapping, or a sequence of mappings of which earlier ones take precedence, and merged
+    /// mappings are flattened in turn. Merged pairs come first and are dropped when the mapping
+    /// has the same scalar key. Any other merge value is an error at that value.
+    pub fn merged_pairs(&self) -> Result<Vec<(YamlNode<'r>, YamlNode<'r>)>, YamlError> {
+        let mut merged = Vec::new();
+        let mut explicit = Vec::new();
+
+        for (key, value) in self.pairs() {
+            if !is_merge_key(&key) {
+                explicit.push((key, value));
+                continue;
+            }
+            let sources = match value {
+                YamlNode::YamlMappingNode(mapping) => vec![mapping],
+                YamlNode::YamlSequenceNode(sequence) => sequence.values().map(|item| match item {
+                    YamlNode::YamlMappingNode(mapping) => Ok(mapping),
+                    item => Err(constructor::merge_error(&item))
+                }).collect::<Result<_, _>>()?,
+                value => return Err(constructor::merge_error(&value))
+            };
+            for source in sources {
+                for (key, value) in source.merged_pairs()? {
+                    if !has_scalar_key(&merged, &key) {
+                        merged.push((key, value));
+                    }
+                }
+            }
+        }
+
+        merged.retain(|(key, _)| !has_scalar_key(&explicit, key));
+        merged.extend(explicit);
+        Ok(merged)
+    }
+
+    /// The position and text of each scalar key that repeats an earlier key of the mapping.
+    ///
+    /// Keys are compared by the value they resolve to, like `reject_duplicate_keys` of the
+    /// constructor does, so `1` and `0x1` are the same key while `1` and `'1'` are not.
+    /// Complex keys are not compared.
+    pub fn find_duplicate_keys(&self) -> Vec<(YamlMark, String)> {
+        self.duplicate_keys().into_iter().map(|(_, mark, key)| (mark, key)).collect()
+    }
+
+    // each repeated key, with the mark of its first occurrence
+    fn duplicate_keys(&self) -> Vec<(YamlMark, YamlMark, String)> {
+        let mut seen: HashMap<(u8, String), YamlMark> = HashMap::new();
+        let mut duplicates = Vec::new();
+
+        for (key, _) in self.pairs() {
+            let scalar = match key {
+                YamlNode::YamlScalarNode(scalar) => scalar,
+                _ => continue
+            };
+            let text = scalar.get_value();
+            let value = scalar.core_value().unwrap_or_else(|| YamlStandardData::YamlString(text.clone()));
+            let key = match hashable_key(value) {
+                Some(key) => key,
+                None => continue
+            };
+            match seen.get(&key) {
+                Some(&first) => duplicates.push((first, scalar.start_mark(), text)),
+                None => {
+                    seen.insert(key, scalar.start_mark());
+                }
+            }
+        }
+        duplicates
+    }
+
+    /// Fills each `(key, field)` slot from the scalar value of the matching key, ignoring other keys.
+    ///
+    /// Values of the wrong type are errors at the value. When `required` is set, a key missing
+    /// from the mapping is an error at the mapping; otherwise its slot is left untouched.
+    pub fn extract(&self, fields: &mut [(&str, &mut dyn YamlScalarField)], required: bool) -> Result<(), YamlError> {
+        let mut found = vec![false; fields.len()];
+
+        for (key, value) in self.pairs() {
+            let name = match key {
+                YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
+                _ => continue
+            };
+            if let Some(index) = fields.iter().position(|&(field, _)| field == name) {
+                constructor::convert_field(core_constructor(), value, &mut *fields[index].1)?;
+                found[index] = true;
+            }
+        }
+
+        match found.iter().position(|&found| !found) {
+            Some(index) if required => Err(constructor::missing_key_error(fields[index].0, &self.start_mark())),
+            _ => Ok(())
+        }
+    }
+
+    /// The byte range of each scalar key in `source`, the UTF-8 text the document was parsed
+    /// from, in mapping order. Complex keys are skipped.
+    ///
+    /// The marks of libyaml count characters, so they are converted by walking `source`.
+    pub fn key_spans(&self, source: &str) -> Vec<(String, Range<usize>)> {
+        let bytes = source.as_bytes();
+        // libyaml skips a BOM without counting it; keys come in source order, so the walk
+        // resumes from the last offset found
+        let mut cursor = (0, if source.starts_with('\u{feff}') { 3 } else { 0 });
+        let mut offset = |index: usize| {
+            let (chars, start) = cursor;
+            let byte = start + parser::char_offset(&bytes[start..], ffi::YamlEncoding::YamlUtf8Encoding, index - chars)
+                .unwrap_or(bytes.len() - start);
+            cursor = (index, byte);
+            byte
+        };
+        self.pairs().filter_map(|(key, _)| match key {
+            YamlNode::YamlScalarNode(scalar) => {
+                let start = offset(scalar.start_mark().index);
+                Some((scalar.get_value(), start..offset(scalar.end_mark().index)))
+            },
+            _ => None
+        }).collect()
+    }
+}
+
+fn scalar_key(node: &YamlNode) -> Option<String> {
+    match *node {
+        YamlNode::YamlScalarNode(ref scalar) => Some(scalar.get_value()),
+        _ => None
+    }
+}
+
+fn has_scalar_key(pairs: &[(YamlNode, YamlNode)], key: &YamlNode) -> bool {
+    match scalar_key(key) {
+        Some(key) => pairs.iter().any(|(other, _)| scalar_key(other).as_ref() == Some(&key)),
+        None => false
+    }
+}
+
+fn is_merge_key(node: &YamlNode) -> bool {
+    match *node {
+        YamlNode::YamlScalarNode(ref scalar) => scalar.get_value() == "<<" && match scalar.tag() {
+            Some(ref tag) if tag == "tag:yaml.org,2002:merge" => true,
+            _ => scalar.style() == ffi::YamlScalarStyle::YamlPlainScalarStyle
+        },
+        _ => false
+    }
 }
 
 pub struct YamlMappingIter<'r> {
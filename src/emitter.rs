@@ -1,40 +1,275 @@
 use ffi;
+use codecs;
 use error::YamlError;
-use event::{YamlVersionDirective, YamlTagDirective};
+use event::{YamlEventSpec, YamlVersionDirective, YamlTagDirective, YamlSequenceParam};
+use parser::{YamlParser, YamlByteParser};
 
 use std::str;
 use std::slice;
 use std::ptr;
 use std::mem;
+use std::mem::MaybeUninit;
 use std::ffi::{CStr, CString};
 use std::io;
 use std::io::Write;
 use libc;
 
 pub struct YamlBaseEmitter {
-    emitter_mem: ffi::yaml_emitter_t
+    emitter_mem: MaybeUninit<ffi::yaml_emitter_t>,
+    // whether yaml_emitter_initialize succeeded, so that dropping may delete the emitter
+    initialized: bool,
+    io_error: Option<io::Error>,
+    config: YamlEmitterConfig,
+    buffer: Option<Vec<u8>>,
+    encoding: ffi::YamlEncoding,
+    // open collections, and whether each is written in flow style
+    flow_levels: Vec<bool>,
 }
 
-impl YamlBaseEmitter {
-    unsafe fn new() -> YamlBaseEmitter {
-        YamlBaseEmitter {
-            emitter_mem: mem::uninitialized()
+impl Drop for YamlBaseEmitter {
+    fn drop(&mut self) {
+        if self.initialized {
+            unsafe {
+                ffi::yaml_emitter_delete(self.emitter_mem.as_mut_ptr());
+            }
         }
     }
 }
 
-impl Drop for YamlBaseEmitter {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::yaml_emitter_delete(&mut self.emitter_mem);
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum YamlSequenceIndent {
+    /// Block sequence items start at the column of their parent key, as libyaml emits them.
+    YamlSameAsKey,
+    /// Block sequence items are indented one level deeper than their parent key.
+    YamlIndented,
+}
+
+/// Block style for multi-line scalars, chosen by `YamlEmitterConfig::multiline_style`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum YamlMultilineStyle {
+    YamlLiteralMultiline,
+    YamlFoldedMultiline,
+    /// Folded if a line is longer than the configured width, literal otherwise.
+    YamlAutoMultiline,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct YamlEmitterConfig {
+    /// Columns per nesting level. libyaml only accepts 2 to 9, so other values are clamped
+    /// to that range.
+    pub indent: usize,
+    pub sequence_indent: YamlSequenceIndent,
+    /// Column at which libyaml folds long scalars, or `None` to never fold them.
+    pub width: Option<usize>,
+    /// Write the spec's canonical form: explicit document starts, flow collections with a comma
+    /// after every item, and double-quoted scalars.
+    pub canonical: bool,
+    /// Write non-ASCII characters as they are rather than as escapes in double-quoted scalars.
+    pub unicode: bool,
+    /// Line break written after each line. `YamlAnyBreak` leaves it to libyaml, which writes LN.
+    pub break_style: ffi::YamlBreak,
+    /// Emit every scalar double-quoted, whatever style it was given.
+    pub always_quote_scalars: bool,
+    /// Fail with a `YAML_EMITTER_ERROR` when a scalar given `YamlPlainScalarStyle` cannot be
+    /// written plain, instead of letting libyaml quote it. See `needs_quoting`.
+    pub strict_plain: bool,
+    /// Collections with at most this many items or entries are written in flow style by
+    /// `emit_events`, larger ones in block style. Only collections given `YamlAnySequenceStyle`
+    /// are affected, and one is only inlined if its nested collections are too. 0 turns this off.
+    pub flow_threshold: usize,
+    /// Block style for scalars containing a line break that were given `YamlAnyScalarStyle` or
+    /// `YamlPlainScalarStyle`, or `None` to leave them to libyaml, which quotes them. libyaml
+    /// still quotes a scalar it cannot write in block style, such as one inside a flow collection.
+    pub multiline_style: Option<YamlMultilineStyle>,
+}
+
+impl Default for YamlEmitterConfig {
+    fn default() -> YamlEmitterConfig {
+        YamlEmitterConfig {
+            indent: 2,
+            sequence_indent: YamlSequenceIndent::YamlSameAsKey,
+            width: Some(80),
+            canonical: false,
+            unicode: false,
+            break_style: ffi::YamlBreak::YamlAnyBreak,
+            always_quote_scalars: false,
+            strict_plain: false,
+            flow_threshold: 0,
+            multiline_style: None,
         }
     }
 }
 
-pub struct YamlEmitter<'r> {
-    base_emitter: YamlBaseEmitter,
-    writer: &'r mut (Write+'r),
-    io_error: Option<io::Error>,
+// the indents libyaml accepts, falling back to 2 for others
+const MIN_INDENT: usize = 2;
+const MAX_INDENT: usize = 9;
+
+fn is_break(c: char) -> bool {
+    c == '\r' || c == '\n' || c == '\u{85}' || c == '\u{2028}' || c == '\u{2029}'
+}
+
+fn is_printable(c: char) -> bool {
+    match c {
+        '\n' | '\x20'..='\x7e' | '\u{a0}'..='\u{d7ff}' | '\u{10000}'..='\u{10ffff}' => true,
+        '\u{e000}'..='\u{fffd}' => c != '\u{feff}',
+        _ => false
+    }
+}
+
+/// Whether libyaml would quote `value` when asked to write it plain, following the checks of
+/// its `yaml_emitter_analyze_scalar`.
+///
+/// Flow indicators such as `,` and `[` only rule out plain style inside flow collections, and
+/// non-ASCII characters are only written plain by an emitter in unicode mode. An empty value
+/// always needs quoting, since a plain empty scalar reads back as null.
+pub fn needs_quoting(value: &str, in_flow: bool, unicode: bool) -> bool {
+    let chars: Vec<char> = value.chars().collect();
+    let is_blankz = |i: usize| chars.get(i).map_or(true, |&c| c == ' ' || c == '\t' || is_break(c));
+
+    match (chars.first(), chars.last()) {
+        (Some(&first), Some(&last)) => if first == ' ' || is_break(first) || last == ' ' || is_break(last) {
+            return true;
+        },
+        _ => return true
+    }
+    if value.starts_with("---") || value.starts_with("...") {
+        return true;
+    }
+
+    let mut flow_indicators = false;
+    let mut block_indicators = false;
+    for (i, &c) in chars.iter().enumerate() {
+        if !is_printable(c) || is_break(c) || (!c.is_ascii() && !unicode) {
+            return true;
+        }
+
+        let followed_by_blank = is_blankz(i + 1);
+        match c {
+            '#' | ',' | '[' | ']' | '{' | '}' | '&' | '*' | '!' | '|' | '>' | '\'' | '"' | '%' | '@' | '`' if i == 0 => {
+                flow_indicators = true;
+                block_indicators = true;
+            },
+            '-' if i == 0 && followed_by_blank => {
+                flow_indicators = true;
+                block_indicators = true;
+            },
+            '?' | ':' if i == 0 || c == ':' => {
+                flow_indicators = true;
+                block_indicators = block_indicators || followed_by_blank;
+            },
+            ',' | '?' | '[' | ']' | '{' | '}' => flow_indicators = true,
+            '#' if is_blankz(i - 1) => {
+                flow_indicators = true;
+                block_indicators = true;
+            },
+            _ => ()
+        }
+    }
+
+    if in_flow {
+        flow_indicators
+    } else {
+        block_indicators
+    }
+}
+
+/// A rough upper bound on the size of the output for `events`, for sizing a buffer.
+///
+/// Every character of a scalar is counted as if escaped, and every line of it as if indented
+/// by two columns for each open collection, so the estimate is usually several times too high.
+/// The output is counted as UTF-8 unless a STREAM-START event asks for UTF-16, which takes
+/// up to twice the bytes and starts with a byte order mark. Unbalanced collection events are
+/// counted as they come.
+pub fn estimate_emit_size(events: &[YamlEventSpec]) -> usize {
+    let props_size = |anchor: &Option<String>, tag: &Option<String>| {
+        anchor.as_ref().map_or(0, |anchor| anchor.len() + 2) + tag.as_ref().map_or(0, |tag| tag.len() + 4)
+    };
+
+    let mut depth: usize = 0;
+    let mut size = 0;
+    let (mut unit_size, mut bom_size) = (1, 0);
+    for evt in events {
+        let indent = 2 * depth + 4;
+        size += match *evt {
+            YamlEventSpec::YamlStreamStartEvent(encoding) => {
+                if encoding == ffi::YamlEncoding::YamlUtf16LeEncoding || encoding == ffi::YamlEncoding::YamlUtf16BeEncoding {
+                    unit_size = 2;
+                    bom_size = codecs::bom(encoding).len();
+                }
+                0
+            },
+            YamlEventSpec::YamlDocumentStartEvent(ref version, ref tags, _) => {
+                let version_size = version.as_ref().map_or(0, |_| 16);
+                version_size + tags.iter().map(|tag| tag.handle.len() + tag.prefix.len() + 8).sum::<usize>() + 8
+            },
+            YamlEventSpec::YamlDocumentEndEvent(_) => 4,
+            YamlEventSpec::YamlAliasEvent(ref anchor) => anchor.len() + indent + 2,
+            YamlEventSpec::YamlScalarEvent(ref param) => {
+                let lines = param.value.matches('\n').count() + param.value.len() / 40 + 1;
+                4 * param.value.len() + lines * indent + props_size(&param.anchor, &param.tag) + 4
+            },
+            YamlEventSpec::YamlSequenceStartEvent(ref param) | YamlEventSpec::YamlMappingStartEvent(ref param) => {
+                depth += 1;
+                props_size(&param.anchor, &param.tag) + indent
+            },
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => {
+                depth = depth.saturating_sub(1);
+                2
+            },
+            _ => 0
+        };
+    }
+    unit_size * size + bom_size
+}
+
+// The style chosen for each collection start event left to the emitter, by `flow_threshold`.
+fn collection_styles(events: &[YamlEventSpec], threshold: usize) -> Vec<Option<ffi::YamlSequenceStyle>> {
+    let mut styles = vec![None; events.len()];
+    if threshold == 0 {
+        return styles;
+    }
+
+    // open collections: start index, number of child nodes, and whether they all end up in flow style
+    let mut open: Vec<(usize, usize, bool)> = Vec::new();
+    for (i, evt) in events.iter().enumerate() {
+        let flow = match *evt {
+            YamlEventSpec::YamlSequenceStartEvent(_) | YamlEventSpec::YamlMappingStartEvent(_) => {
+                open.push((i, 0, true));
+                continue;
+            },
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => {
+                let (start, children, nested_flow) = match open.pop() {
+                    Some(collection) => collection,
+                    None => continue
+                };
+                let (param, len) = match events[start] {
+                    YamlEventSpec::YamlSequenceStartEvent(ref param) => (param, children),
+                    YamlEventSpec::YamlMappingStartEvent(ref param) => (param, children / 2),
+                    _ => unreachable!()
+                };
+                match param.style {
+                    ffi::YamlSequenceStyle::YamlAnySequenceStyle => {
+                        let flow = nested_flow && len <= threshold;
+                        styles[start] = Some(if flow {
+                            ffi::YamlSequenceStyle::YamlFlowSequenceStyle
+                        } else {
+                            ffi::YamlSequenceStyle::YamlBlockSequenceStyle
+                        });
+                        flow
+                    },
+                    style => style == ffi::YamlSequenceStyle::YamlFlowSequenceStyle
+                }
+            },
+            YamlEventSpec::YamlScalarEvent(_) | YamlEventSpec::YamlAliasEvent(_) => true,
+            _ => continue
+        };
+
+        if let Some(&mut (_, ref mut children, ref mut nested_flow)) = open.last_mut() {
+            *children += 1;
+            *nested_flow = *nested_flow && flow;
+        }
+    }
+    styles
 }
 
 fn to_c_str_opt(s: Option<&str>) -> Result<Option<CString>, YamlError> {
@@ -60,28 +295,153 @@ fn to_c_str(s: &str) -> Result<CString, YamlError> {
     }
 }
 
-impl<'r> YamlEmitter<'r> {
-    pub fn init<'a>(writer: &'a mut Write) -> Box<YamlEmitter<'a>> {
-        unsafe {
-            let mut emitter = Box::new(YamlEmitter {
-                base_emitter: YamlBaseEmitter::new(),
-                writer: writer,
-                io_error: None
-            });
+// libyaml always emits block sequences nested in a mapping at the column of their key,
+// so indented sequences are produced by re-parsing the output and shifting their lines.
+fn indent_block_sequences(output: &[u8], indent: usize) -> Result<String, YamlError> {
+    let text = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut depth = vec![0; lines.len()];
+    // (is_mapping, number of children, first line if the sequence must be shifted)
+    let mut stack: Vec<(bool, usize, Option<usize>)> = Vec::new();
 
-            if ffi::yaml_emitter_initialize(&mut emitter.base_emitter.emitter_mem) == 0 {
-                panic!("failed to initialize yaml_emitter_t");
-            }
+    let parser = YamlByteParser::init(output, ffi::YamlEncoding::YamlUtf8Encoding);
+    for res in parser.parse() {
+        let evt = res?;
+        let is_value = match evt.spec {
+            YamlEventSpec::YamlScalarEvent(_) | YamlEventSpec::YamlAliasEvent(_)
+                | YamlEventSpec::YamlSequenceStartEvent(_) | YamlEventSpec::YamlMappingStartEvent(_) => {
+                match stack.last_mut() {
+                    Some(&mut (is_mapping, ref mut children, _)) => {
+                        *children += 1;
+                        is_mapping && *children % 2 == 0
+                    },
+                    None => false
+                }
+            },
+            _ => false
+        };
 
-            ffi::yaml_emitter_set_output(&mut emitter.base_emitter.emitter_mem, handle_writer_cb, mem::transmute(&mut *emitter));
+        match evt.spec {
+            YamlEventSpec::YamlSequenceStartEvent(ref param) => {
+                let shifted = is_value && param.style == ffi::YamlSequenceStyle::YamlBlockSequenceStyle;
+                stack.push((false, 0, if shifted { Some(evt.start.line) } else { None }));
+            },
+            YamlEventSpec::YamlMappingStartEvent(_) => stack.push((true, 0, None)),
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => {
+                if let Some((_, _, Some(first))) = stack.pop() {
+                    for d in depth.iter_mut().take(evt.start.line).skip(first) {
+                        *d += 1;
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
 
-            emitter
+    let mut result = String::with_capacity(text.len());
+    for (line, d) in lines.iter().zip(depth) {
+        if d > 0 && !line.trim_end_matches(['\r', '\n']).is_empty() {
+            result.extend(std::iter::repeat(' ').take(d * indent));
+        }
+        result.push_str(line);
+    }
+    Ok(result)
+}
+
+impl YamlBaseEmitter {
+    unsafe fn new(config: YamlEmitterConfig) -> YamlBaseEmitter {
+        let buffer = if config.sequence_indent == YamlSequenceIndent::YamlIndented {
+            Some(Vec::new())
+        } else {
+            None
+        };
+
+        YamlBaseEmitter {
+            emitter_mem: MaybeUninit::uninit(),
+            initialized: false,
+            io_error: None,
+            config,
+            buffer,
+            encoding: ffi::YamlEncoding::YamlAnyEncoding,
+            flow_levels: Vec::new()
+        }
+    }
+
+    // Must only be called once the emitter owning `self` has its final address, which is `data`.
+    unsafe fn initialize<E: YamlEmitterBase>(&mut self, data: *mut E) {
+        if ffi::yaml_emitter_initialize(self.emitter_mem.as_mut_ptr()) == 0 {
+            panic!("failed to initialize yaml_emitter_t");
+        }
+        self.initialized = true;
+
+        let width = self.config.width.map_or(-1, |width| width as libc::c_int);
+        ffi::yaml_emitter_set_width(self.emitter_mem.as_mut_ptr(), width);
+        ffi::yaml_emitter_set_indent(self.emitter_mem.as_mut_ptr(), self.indent() as libc::c_int);
+        ffi::yaml_emitter_set_canonical(self.emitter_mem.as_mut_ptr(), self.config.canonical as libc::c_int);
+        ffi::yaml_emitter_set_unicode(self.emitter_mem.as_mut_ptr(), self.config.unicode as libc::c_int);
+        ffi::yaml_emitter_set_break(self.emitter_mem.as_mut_ptr(), self.config.break_style);
+
+        ffi::yaml_emitter_set_output(self.emitter_mem.as_mut_ptr(), handle_writer_cb::<E>, data as *const libc::c_void);
+    }
+
+    fn indent(&self) -> usize {
+        self.config.indent.clamp(MIN_INDENT, MAX_INDENT)
+    }
+
+    fn emit_event(&mut self, spec: &YamlEventSpec) -> Result<(), YamlError> {
+        match *spec {
+            YamlEventSpec::YamlNoEvent => Err(YamlError::new(ffi::YamlErrorType::YAML_EMITTER_ERROR,
+                Some("cannot emit an empty event".to_string()))),
+            YamlEventSpec::YamlStreamStartEvent(encoding) => {
+                // an emitter created for an encoding keeps it for streams that do not choose one
+                if encoding != ffi::YamlEncoding::YamlAnyEncoding {
+                    self.encoding = encoding;
+                }
+                // buffered output is post-processed as UTF-8 and re-encoded when written out
+                if self.buffer.is_some() {
+                    self.emit_stream_start_event(ffi::YamlEncoding::YamlUtf8Encoding)
+                } else {
+                    let encoding = self.encoding;
+                    self.emit_stream_start_event(encoding)
+                }
+            },
+            YamlEventSpec::YamlStreamEndEvent => {
+                self.emit_stream_end_event()?;
+                self.flush()
+            },
+            YamlEventSpec::YamlDocumentStartEvent(version_directive, ref tag_directives, implicit) =>
+                self.emit_document_start_event(version_directive, tag_directives, implicit),
+            YamlEventSpec::YamlDocumentEndEvent(implicit) => self.emit_document_end_event(implicit),
+            YamlEventSpec::YamlAliasEvent(ref anchor) => self.emit_alias_event(anchor),
+            YamlEventSpec::YamlScalarEvent(ref param) =>
+                self.emit_scalar_event(param.anchor.as_ref().map(|s| &s[..]), param.tag.as_ref().map(|s| &s[..]),
+                    &param.value, param.plain_implicit, param.quoted_implicit, param.style),
+            YamlEventSpec::YamlSequenceStartEvent(ref param) =>
+                self.emit_sequence_start_event(param.anchor.as_ref().map(|s| &s[..]), param.tag.as_ref().map(|s| &s[..]),
+                    param.implicit, param.style),
+            YamlEventSpec::YamlSequenceEndEvent => self.emit_sequence_end_event(),
+            YamlEventSpec::YamlMappingStartEvent(ref param) =>
+                self.emit_mapping_start_event(param.anchor.as_ref().map(|s| &s[..]), param.tag.as_ref().map(|s| &s[..]),
+                    param.implicit, param.style),
+            YamlEventSpec::YamlMappingEndEvent => self.emit_mapping_end_event()
         }
     }
 
+    // The buffered output of the stream, post-processed and in the stream's encoding. Output
+    // that does not parse on its own, as when the stream has not ended, is left as it is.
+    fn take_buffer(&mut self) -> Option<Vec<u8>> {
+        let output = match self.buffer {
+            Some(ref buffer) => indent_block_sequences(&buffer[..], self.indent())
+                .unwrap_or_else(|_| String::from_utf8_lossy(&buffer[..]).into_owned()),
+            None => return None
+        };
+        self.buffer = Some(Vec::new());
+        Some(codecs::encode_str(&output[..], self.encoding))
+    }
+
     fn get_error(&mut self) -> YamlError {
-        let emitter_mem = &self.base_emitter.emitter_mem;
         unsafe {
+            let emitter_mem = self.emitter_mem.assume_init_ref();
             let c_problem = CStr::from_ptr(emitter_mem.problem);
             let mut error = YamlError {
                 kind: emitter_mem.error,
@@ -92,28 +452,19 @@ impl<'r> YamlEmitter<'r> {
 
             mem::swap(&mut self.io_error, &mut error.io_error);
 
-            return error;
+            error
         }
     }
 
-    pub fn emit_stream<F>(&mut self, encoding: ffi::YamlEncoding, f: F) -> Result<(), YamlError>
-        where F: Fn(&mut YamlEmitter) -> Result<(), YamlError>
-    {
-        try!(self.emit_stream_start_event(encoding));
-        try!(f(self));
-        try!(self.emit_stream_end_event());
-        self.flush()
-    }
-
     fn emit_stream_start_event(&mut self, encoding: ffi::YamlEncoding) -> Result<(), YamlError> {
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_stream_start_event_initialize(&mut event, encoding) == 0 {
+            if ffi::yaml_stream_start_event_initialize(event.as_mut_ptr(), encoding) == 0 {
                 panic!("yaml_stream_start_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -123,13 +474,13 @@ impl<'r> YamlEmitter<'r> {
 
     fn emit_stream_end_event(&mut self) -> Result<(), YamlError> {
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_stream_end_event_initialize(&mut event) == 0 {
+            if ffi::yaml_stream_end_event_initialize(event.as_mut_ptr()) == 0 {
                 panic!("yaml_stream_end_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -137,18 +488,6 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn emit_document<F>(&mut self,
-            version_directive: Option<YamlVersionDirective>,
-            tag_directives: &[YamlTagDirective],
-            implicit: bool,
-            f: F) -> Result<(), YamlError> where
-        F: Fn(&mut YamlEmitter) -> Result<(), YamlError>
-    {
-        try!(self.emit_document_start_event(version_directive, tag_directives, implicit));
-        try!(f(self));
-        self.emit_document_end_event(implicit)
-    }
-
     fn emit_document_start_event(&mut self,
             version_directive: Option<YamlVersionDirective>,
             tag_directives: &[YamlTagDirective],
@@ -165,25 +504,30 @@ impl<'r> YamlEmitter<'r> {
             None => ptr::null()
         };
 
-        let c_tag_dirs: Vec<ffi::yaml_tag_directive_t> =
-            match tag_directives.iter().map(|tag| tag.to_tag_directive_t()).collect() {
-                Ok(dirs) => dirs,
+        // libyaml copies the directives, so the strings only have to outlive the initialization
+        let c_strings: Vec<(CString, CString)> =
+            match tag_directives.iter().map(|tag| tag.to_c_strings()).collect() {
+                Ok(strings) => strings,
                 Err(_) => return Err(YamlError::new(
                         ffi::YamlErrorType::YAML_EMITTER_ERROR,
                         Some("Nul bytes in tag directives".to_string())
                     ))
             };
+        let c_tag_dirs: Vec<ffi::yaml_tag_directive_t> = c_strings.iter().map(|(handle, prefix)| ffi::yaml_tag_directive_t {
+            handle: handle.as_ptr(),
+            prefix: prefix.as_ptr()
+        }).collect();
         let tag_dir_start = c_tag_dirs.as_ptr();
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
             let tag_dir_end = tag_dir_start.offset(c_tag_dirs.len() as isize);
             let c_implicit = if implicit { 1 } else { 0 };
 
-            if ffi::yaml_document_start_event_initialize(&mut event, c_vsn_dir, tag_dir_start, tag_dir_end, c_implicit) == 0 {
+            if ffi::yaml_document_start_event_initialize(event.as_mut_ptr(), c_vsn_dir, tag_dir_start, tag_dir_end, c_implicit) == 0 {
                 panic!("yaml_document_start_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -194,13 +538,13 @@ impl<'r> YamlEmitter<'r> {
     fn emit_document_end_event(&mut self, implicit: bool) -> Result<(), YamlError> {
         let c_implicit = if implicit { 1 } else { 0 };
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_document_end_event_initialize(&mut event, c_implicit) == 0 {
+            if ffi::yaml_document_end_event_initialize(event.as_mut_ptr(), c_implicit) == 0 {
                 panic!("yaml_stream_end_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -208,18 +552,18 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn emit_alias_event(&mut self, anchor: &str) -> Result<(), YamlError> {
+    fn emit_alias_event(&mut self, anchor: &str) -> Result<(), YamlError> {
         let c_anchor = try!(to_c_str(anchor));
 
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
             let ptr = c_anchor.as_ptr();
-            if ffi::yaml_alias_event_initialize(&mut event, ptr as *const ffi::yaml_char_t) != 0 {
-                panic!("yaml_stream_end_event_initialize failed!")
+            if ffi::yaml_alias_event_initialize(event.as_mut_ptr(), ptr as *const ffi::yaml_char_t) == 0 {
+                panic!("yaml_alias_event_initialize failed!")
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -227,27 +571,64 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn emit_scalar_event(&mut self, anchor: Option<&str>, tag: Option<&str>,
+    fn multiline_block_style(&self, value: &str, style: ffi::YamlScalarStyle) -> Option<ffi::YamlScalarStyle> {
+        let multiline_style = self.config.multiline_style?;
+        if !value.contains('\n') || (style != ffi::YamlScalarStyle::YamlAnyScalarStyle && style != ffi::YamlScalarStyle::YamlPlainScalarStyle) {
+            return None;
+        }
+
+        let folded = match multiline_style {
+            YamlMultilineStyle::YamlLiteralMultiline => false,
+            YamlMultilineStyle::YamlFoldedMultiline => true,
+            YamlMultilineStyle::YamlAutoMultiline =>
+                self.config.width.is_some_and(|width| value.lines().any(|line| line.chars().count() > width))
+        };
+        Some(if folded {
+            ffi::YamlScalarStyle::YamlFoldedScalarStyle
+        } else {
+            ffi::YamlScalarStyle::YamlLiteralScalarStyle
+        })
+    }
+
+    fn emit_scalar_event(&mut self, anchor: Option<&str>, tag: Option<&str>,
         value: &str, plain_implicit: bool, quoted_implicit: bool,
         style: ffi::YamlScalarStyle) -> Result<(), YamlError>
     {
         let c_anchor = try!(to_c_str_opt(anchor));
         let anchor_ptr = match c_anchor {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
         let c_tag = try!(to_c_str_opt(tag));
         let tag_ptr = match c_tag {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
+        if self.config.strict_plain && !self.config.always_quote_scalars && style == ffi::YamlScalarStyle::YamlPlainScalarStyle {
+            let in_flow = self.flow_levels.last() == Some(&true);
+            let unicode = self.config.unicode;
+            // an untagged scalar that is not plain implicit needs a "!" tag, and libyaml quotes it
+            if (tag.is_none() && !plain_implicit) || needs_quoting(value, in_flow, unicode) {
+                return Err(YamlError::new(ffi::YamlErrorType::YAML_EMITTER_ERROR,
+                    Some(format!("cannot emit {:?} as a plain scalar", value))));
+            }
+        }
+
+        let (style, quoted_implicit) = if self.config.always_quote_scalars {
+            // a plain implicit scalar stays untagged rather than gaining a "!" tag
+            (ffi::YamlScalarStyle::YamlDoubleQuotedScalarStyle, plain_implicit || quoted_implicit)
+        } else if let Some(block_style) = self.multiline_block_style(value, style) {
+            (block_style, plain_implicit || quoted_implicit)
+        } else {
+            (style, quoted_implicit)
+        };
         let c_plain_implicit = if plain_implicit { 1 } else { 0 };
         let c_quoted_implicit = if quoted_implicit { 1 } else { 0 };
 
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_scalar_event_initialize(&mut event,
+            if ffi::yaml_scalar_event_initialize(event.as_mut_ptr(),
                     anchor_ptr as *const ffi::yaml_char_t, tag_ptr as *const ffi::yaml_char_t,
                     value.as_ptr(), value.len() as libc::c_int,
                     c_plain_implicit, c_quoted_implicit,
@@ -256,7 +637,7 @@ impl<'r> YamlEmitter<'r> {
                 panic!("yaml_scalar_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -264,42 +645,34 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn emit_sequence<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
-            style: ffi::YamlSequenceStyle,
-            f: F) -> Result<(), YamlError> where
-        F: Fn(&mut YamlEmitter) -> Result<(), YamlError>
-    {
-        try!(self.emit_sequence_start_event(anchor, tag, implicit, style));
-        try!(f(self));
-        self.emit_sequence_end_event()
-    }
-
     fn emit_sequence_start_event(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
         style: ffi::YamlSequenceStyle) -> Result<(), YamlError>
     {
         let c_anchor = try!(to_c_str_opt(anchor));
         let anchor_ptr = match c_anchor {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
         let c_tag = try!(to_c_str_opt(tag));
         let tag_ptr = match c_tag {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
         let c_implicit = if implicit { 1 } else { 0 };
 
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_sequence_start_event_initialize(&mut event,
+            if ffi::yaml_sequence_start_event_initialize(event.as_mut_ptr(),
                     anchor_ptr as *const ffi::yaml_char_t, tag_ptr as *const ffi::yaml_char_t,
                     c_implicit, style) == 0
             {
                 panic!("yaml_sequence_start_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
+                let flow = style == ffi::YamlSequenceStyle::YamlFlowSequenceStyle || self.flow_levels.last() == Some(&true);
+                self.flow_levels.push(flow);
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -309,13 +682,14 @@ impl<'r> YamlEmitter<'r> {
 
     fn emit_sequence_end_event(&mut self) -> Result<(), YamlError> {
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_sequence_end_event_initialize(&mut event) == 0 {
+            if ffi::yaml_sequence_end_event_initialize(event.as_mut_ptr()) == 0 {
                 panic!("yaml_sequence_end_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
+                self.flow_levels.pop();
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -323,42 +697,34 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn emit_mapping<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
-            style: ffi::YamlSequenceStyle,
-            f: F) -> Result<(), YamlError> where
-        F: Fn(&mut YamlEmitter) -> Result<(), YamlError>
-    {
-        try!(self.emit_mapping_start_event(anchor, tag, implicit, style));
-        try!(f(self));
-        self.emit_mapping_end_event()
-    }
-
     fn emit_mapping_start_event(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
         style: ffi::YamlSequenceStyle) -> Result<(), YamlError>
     {
         let c_anchor = try!(to_c_str_opt(anchor));
         let anchor_ptr = match c_anchor {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
         let c_tag = try!(to_c_str_opt(tag));
         let tag_ptr = match c_tag {
-            Some(s) => s.as_ptr(),
+            Some(ref s) => s.as_ptr(),
             None => ptr::null()
         };
         let c_implicit = if implicit { 1 } else { 0 };
 
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_mapping_start_event_initialize(&mut event,
+            if ffi::yaml_mapping_start_event_initialize(event.as_mut_ptr(),
                     anchor_ptr as *const ffi::yaml_char_t, tag_ptr as *const ffi::yaml_char_t,
                     c_implicit, style) == 0
             {
                 panic!("yaml_mapping_start_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
+                let flow = style == ffi::YamlSequenceStyle::YamlFlowSequenceStyle || self.flow_levels.last() == Some(&true);
+                self.flow_levels.push(flow);
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -368,13 +734,14 @@ impl<'r> YamlEmitter<'r> {
 
     fn emit_mapping_end_event(&mut self) -> Result<(), YamlError> {
         unsafe {
-            let mut event = mem::uninitialized();
+            let mut event = MaybeUninit::uninit();
 
-            if ffi::yaml_mapping_end_event_initialize(&mut event) == 0 {
+            if ffi::yaml_mapping_end_event_initialize(event.as_mut_ptr()) == 0 {
                 panic!("yaml_mapping_end_event_initialize failed!");
             }
 
-            if ffi::yaml_emitter_emit(&mut self.base_emitter.emitter_mem, &mut event) != 0 {
+            if ffi::yaml_emitter_emit(self.emitter_mem.as_mut_ptr(), event.as_mut_ptr()) != 0 {
+                self.flow_levels.pop();
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -382,9 +749,9 @@ impl<'r> YamlEmitter<'r> {
         }
     }
 
-    pub fn flush(&mut self) -> Result<(), YamlError> {
+    fn flush(&mut self) -> Result<(), YamlError> {
         unsafe {
-            if ffi::yaml_emitter_flush(&mut self.base_emitter.emitter_mem) != 0 {
+            if ffi::yaml_emitter_flush(self.emitter_mem.as_mut_ptr()) != 0 {
                 Ok(())
             } else {
                 Err(self.get_error())
@@ -393,14 +760,300 @@ impl<'r> YamlEmitter<'r> {
     }
 }
 
-extern fn handle_writer_cb(data: *mut YamlEmitter, buffer: *const u8, size: libc::size_t) -> libc::c_int {
+mod sealed {
+    use std::io;
+    use error::YamlError;
+    use ffi;
+    use super::YamlBaseEmitter;
+
+    /// The output side of `YamlEventEmitter`, which only this crate's emitters implement.
+    pub trait YamlEmitterBase {
+        fn base_emitter_ref(&mut self) -> &mut YamlBaseEmitter;
+        fn write_output(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+        // writes out the output held back for `YamlIndented` sequences
+        fn write_buffer(&mut self) -> Result<(), YamlError> {
+            match self.base_emitter_ref().take_buffer() {
+                Some(output) => self.write_output(&output[..]).map_err(|err| YamlError {
+                    kind: ffi::YamlErrorType::YAML_WRITER_ERROR,
+                    problem: Some("write error".to_string()),
+                    io_error: Some(err),
+                    context: None
+                }),
+                None => Ok(())
+            }
+        }
+    }
+}
+
+use self::sealed::YamlEmitterBase;
+
+/// Implemented by `YamlEmitter` and `YamlByteEmitter`; it is sealed, so other types cannot
+/// implement it.
+pub trait YamlEventEmitter: YamlEmitterBase + Sized {
+    fn emit_stream<F>(&mut self, encoding: ffi::YamlEncoding, f: F) -> Result<(), YamlError>
+        where F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        self.emit_event(&YamlEventSpec::YamlStreamStartEvent(encoding))?;
+        f(self)?;
+        self.emit_event(&YamlEventSpec::YamlStreamEndEvent)
+    }
+
+    /// Emits a list of events, choosing collection styles by `flow_threshold`.
+    fn emit_events(&mut self, events: &[YamlEventSpec]) -> Result<(), YamlError> {
+        let styles = collection_styles(events, self.base_emitter_ref().config.flow_threshold);
+        for (evt, style) in events.iter().zip(styles) {
+            match (evt, style) {
+                (YamlEventSpec::YamlSequenceStartEvent(param), Some(style)) =>
+                    self.emit_event(&YamlEventSpec::YamlSequenceStartEvent(YamlSequenceParam { style, ..param.clone() }))?,
+                (YamlEventSpec::YamlMappingStartEvent(param), Some(style)) =>
+                    self.emit_event(&YamlEventSpec::YamlMappingStartEvent(YamlSequenceParam { style, ..param.clone() }))?,
+                _ => self.emit_event(evt)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Emits each event in turn, as taken from a `YamlEventStream`, stopping at the first error.
+    /// Unlike `emit_events`, the styles of the events are kept. libyaml checks the order, so a
+    /// misplaced event such as a MAPPING-END without a start fails with a `YAML_EMITTER_ERROR`.
+    fn emit_all<I: IntoIterator<Item = YamlEventSpec>>(&mut self, events: I) -> Result<(), YamlError> {
+        for evt in events {
+            self.emit_event(&evt)?;
+        }
+        Ok(())
+    }
+
+    fn emit_event(&mut self, spec: &YamlEventSpec) -> Result<(), YamlError> {
+        self.base_emitter_ref().emit_event(spec)?;
+        if *spec != YamlEventSpec::YamlStreamEndEvent {
+            return Ok(());
+        }
+        self.write_buffer()
+    }
+
+    /// Emits a document around the events of `f`, starting with any `%YAML` and `%TAG`
+    /// directives. A document with directives always gets its `---`, as libyaml makes the start
+    /// explicit whatever `implicit` says, and the previous document its `...` if it needs one.
+    fn emit_document<F>(&mut self,
+            version_directive: Option<YamlVersionDirective>,
+            tag_directives: &[YamlTagDirective],
+            implicit: bool,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        self.base_emitter_ref().emit_document_start_event(version_directive, tag_directives, implicit)?;
+        f(self)?;
+        self.base_emitter_ref().emit_document_end_event(implicit)
+    }
+
+    fn emit_alias_event(&mut self, anchor: &str) -> Result<(), YamlError> {
+        self.base_emitter_ref().emit_alias_event(anchor)
+    }
+
+    fn emit_scalar_event(&mut self, anchor: Option<&str>, tag: Option<&str>,
+        value: &str, plain_implicit: bool, quoted_implicit: bool,
+        style: ffi::YamlScalarStyle) -> Result<(), YamlError>
+    {
+        self.base_emitter_ref().emit_scalar_event(anchor, tag, value, plain_implicit, quoted_implicit, style)
+    }
+
+    fn emit_sequence<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
+            style: ffi::YamlSequenceStyle,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        self.base_emitter_ref().emit_sequence_start_event(anchor, tag, implicit, style)?;
+        f(self)?;
+        self.base_emitter_ref().emit_sequence_end_event()
+    }
+
+    fn emit_mapping<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
+            style: ffi::YamlSequenceStyle,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        self.base_emitter_ref().emit_mapping_start_event(anchor, tag, implicit, style)?;
+        f(self)?;
+        self.base_emitter_ref().emit_mapping_end_event()
+    }
+
+    /// Writes out the output libyaml still holds, which it otherwise only does once its buffer
+    /// fills up or the stream ends. Dropping an emitter finishes it too, ignoring any error.
+    ///
+    /// Output held back for `YamlIndented` sequences is written too, without the extra indent
+    /// if the stream has not ended, as only a whole stream can be post-processed.
+    fn finish(&mut self) -> Result<(), YamlError> {
+        self.base_emitter_ref().flush()?;
+        if self.base_emitter_ref().buffer.as_ref().is_some_and(|buffer| !buffer.is_empty()) {
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+}
+
+/// Emitter writing to a `Write` whenever libyaml flushes its buffer, so a large document is
+/// never held in memory whole. With `YamlIndented` sequences, the output of a stream is held
+/// back until the stream ends, since it is post-processed as a whole.
+pub struct YamlEmitter<'r> {
+    base_emitter: YamlBaseEmitter,
+    writer: &'r mut (dyn Write+'r)
+}
+
+impl<'r> YamlEmitterBase for YamlEmitter<'r> {
+    fn base_emitter_ref(&mut self) -> &mut YamlBaseEmitter {
+        &mut self.base_emitter
+    }
+
+    fn write_output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)
+    }
+}
+
+impl<'r> YamlEventEmitter for YamlEmitter<'r> {}
+
+// The methods of `YamlEventEmitter` that `YamlEmitter` had before the trait, so that they work
+// without importing it.
+impl<'r> YamlEmitter<'r> {
+    pub fn emit_stream<F>(&mut self, encoding: ffi::YamlEncoding, f: F) -> Result<(), YamlError>
+        where F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        YamlEventEmitter::emit_stream(self, encoding, f)
+    }
+
+    pub fn emit_document<F>(&mut self,
+            version_directive: Option<YamlVersionDirective>,
+            tag_directives: &[YamlTagDirective],
+            implicit: bool,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        YamlEventEmitter::emit_document(self, version_directive, tag_directives, implicit, f)
+    }
+
+    pub fn emit_alias_event(&mut self, anchor: &str) -> Result<(), YamlError> {
+        YamlEventEmitter::emit_alias_event(self, anchor)
+    }
+
+    pub fn emit_scalar_event(&mut self, anchor: Option<&str>, tag: Option<&str>,
+        value: &str, plain_implicit: bool, quoted_implicit: bool,
+        style: ffi::YamlScalarStyle) -> Result<(), YamlError>
+    {
+        YamlEventEmitter::emit_scalar_event(self, anchor, tag, value, plain_implicit, quoted_implicit, style)
+    }
+
+    pub fn emit_sequence<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
+            style: ffi::YamlSequenceStyle,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        YamlEventEmitter::emit_sequence(self, anchor, tag, implicit, style, f)
+    }
+
+    pub fn emit_mapping<F>(&mut self, anchor: Option<&str>, tag: Option<&str>, implicit: bool,
+            style: ffi::YamlSequenceStyle,
+            f: F) -> Result<(), YamlError> where
+        F: Fn(&mut Self) -> Result<(), YamlError>
+    {
+        YamlEventEmitter::emit_mapping(self, anchor, tag, implicit, style, f)
+    }
+
+    /// The same as `finish`.
+    pub fn flush(&mut self) -> Result<(), YamlError> {
+        self.finish()
+    }
+}
+
+impl<'r> YamlEmitter<'r> {
+    pub fn init<'a>(writer: &'a mut dyn Write) -> Box<YamlEmitter<'a>> {
+        YamlEmitter::init_with_config(writer, YamlEmitterConfig::default())
+    }
+
+    pub fn init_with_config<'a>(writer: &'a mut dyn Write, config: YamlEmitterConfig) -> Box<YamlEmitter<'a>> {
+        unsafe {
+            let mut emitter = Box::new(YamlEmitter {
+                base_emitter: YamlBaseEmitter::new(config),
+                writer
+            });
+
+            let data: *mut YamlEmitter = &mut *emitter;
+            emitter.base_emitter.initialize(data);
+
+            emitter
+        }
+    }
+}
+
+impl<'r> Drop for YamlEmitter<'r> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// `YamlEmitter` under the name that sets it apart from `YamlByteEmitter`.
+pub type YamlIoEmitter<'r> = YamlEmitter<'r>;
+
+/// Emitter collecting its output in memory, for `into_bytes` to return.
+///
+/// The output is in the encoding given to `init`, unless the STREAM-START event names another.
+pub struct YamlByteEmitter {
+    base_emitter: YamlBaseEmitter,
+    output: Vec<u8>
+}
+
+impl YamlEmitterBase for YamlByteEmitter {
+    fn base_emitter_ref(&mut self) -> &mut YamlBaseEmitter {
+        &mut self.base_emitter
+    }
+
+    fn write_output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+impl YamlEventEmitter for YamlByteEmitter {}
+
+impl YamlByteEmitter {
+    pub fn init(encoding: ffi::YamlEncoding) -> Box<YamlByteEmitter> {
+        YamlByteEmitter::init_with_config(encoding, YamlEmitterConfig::default())
+    }
+
+    pub fn init_with_config(encoding: ffi::YamlEncoding, config: YamlEmitterConfig) -> Box<YamlByteEmitter> {
+        unsafe {
+            let mut emitter = Box::new(YamlByteEmitter {
+                base_emitter: YamlBaseEmitter::new(config),
+                output: Vec::new()
+            });
+            emitter.base_emitter.encoding = encoding;
+
+            let data: *mut YamlByteEmitter = &mut *emitter;
+            emitter.base_emitter.initialize(data);
+
+            emitter
+        }
+    }
+
+    /// The output so far, including whatever libyaml still held in its buffer.
+    pub fn into_bytes(mut self: Box<Self>) -> Vec<u8> {
+        // appending to the Vec cannot fail, so neither can flushing into it
+        let _ = self.finish();
+        mem::take(&mut self.output)
+    }
+}
+
+extern fn handle_writer_cb<E: YamlEmitterBase>(data: *mut libc::c_void, buffer: *const u8, size: libc::size_t) -> libc::c_int {
     unsafe {
         let buf = slice::from_raw_parts(buffer, size as usize);
-        let emitter = &mut *data;
-        match emitter.writer.write_all(buf) {
+        let emitter = &mut *(data as *mut E);
+        if let Some(ref mut buffer) = emitter.base_emitter_ref().buffer {
+            buffer.extend_from_slice(buf);
+            return 1;
+        }
+        match emitter.write_output(buf) {
             Ok(()) => 1,
             Err(err) => {
-                emitter.io_error = Some(err);
+                emitter.base_emitter_ref().io_error = Some(err);
                 0
             }
         }
@@ -409,8 +1062,13 @@ extern fn handle_writer_cb(data: *mut YamlEmitter, buffer: *const u8, size: libc
 
 #[cfg(test)]
 mod test {
-    use emitter::YamlEmitter;
-    use ffi::YamlEncoding::YamlUtf8Encoding;
+    use emitter::{YamlEventEmitter, YamlEmitter, YamlIoEmitter, YamlByteEmitter, YamlEmitterConfig, YamlMultilineStyle, YamlSequenceIndent, estimate_emit_size, needs_quoting};
+    use document::YamlDocument;
+    use ffi;
+    use error::YamlError;
+    use event::{YamlEventSpec, YamlSequenceParam, YamlScalarParam};
+    use parser::{YamlParser, YamlByteParser};
+    use ffi::YamlEncoding::{YamlAnyEncoding, YamlUtf8Encoding, YamlUtf16LeEncoding};
     use ffi::YamlScalarStyle::*;
     use ffi::YamlSequenceStyle::*;
 
@@ -438,7 +1096,7 @@ mod test {
     fn event_emitter_mapping_test() {
         let mut writer = Vec::new();
         {
-            let mut emitter = YamlEmitter::init(&mut writer);
+            let mut emitter = YamlIoEmitter::init(&mut writer);
             emitter.emit_stream(YamlUtf8Encoding, |e| {
                 e.emit_document(None, &[], true, |e| {
                     e.emit_mapping(None, None, true, YamlFlowSequenceStyle, |e| {
@@ -453,4 +1111,724 @@ mod test {
         }
         assert_eq!(&writer[..], b"{a: 1, b: 2}\n");
     }
+
+    #[test]
+    fn event_round_trip_test() {
+        let parse_specs = |data: &[u8]| -> Vec<YamlEventSpec> {
+            let parser = YamlByteParser::init(data, YamlUtf8Encoding);
+            parser.parse().map(|res| res.unwrap().spec).collect()
+        };
+        let events = parse_specs(b"[1, 2, 3]");
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            for evt in events.iter() {
+                emitter.emit_event(evt).unwrap();
+            }
+            emitter.finish().unwrap();
+        }
+        assert_eq!(events, parse_specs(&writer[..]));
+
+        // without the stream end, the output is only written out when the emitter is dropped
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            for evt in events[..events.len() - 1].iter() {
+                emitter.emit_event(evt).unwrap();
+            }
+        }
+        assert_eq!(&writer[..], b"[1, 2, 3]\n");
+    }
+
+    #[test]
+    fn io_emitter_cursor_test() {
+        use std::io::Cursor;
+
+        let mut cursor = Cursor::new(b"# header\n".to_vec());
+        cursor.set_position(9);
+        {
+            let mut emitter = YamlEmitter::init(&mut cursor);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                e.emit_document(None, &[], false, |e| {
+                    e.emit_sequence(Some("s"), None, true, YamlBlockSequenceStyle, |e| {
+                        e.emit_scalar_event(None, None, "a", true, false, YamlPlainScalarStyle)?;
+                        e.emit_alias_event("s")
+                    })
+                })
+            }).unwrap();
+        }
+        assert_eq!(29, cursor.position());
+        assert_eq!(&b"# header\n--- &s\n- a\n- *s\n...\n"[..], &cursor.into_inner()[..]);
+    }
+
+    #[test]
+    fn byte_emitter_test() {
+        let mut emitter = YamlByteEmitter::init(YamlUtf8Encoding);
+        emitter.emit_stream(YamlAnyEncoding, |e| {
+            e.emit_document(None, &[], true, |e| {
+                e.emit_mapping(None, None, true, YamlBlockSequenceStyle, |e| {
+                    e.emit_scalar_event(None, None, "a", true, false, YamlPlainScalarStyle)?;
+                    e.emit_sequence(None, None, true, YamlFlowSequenceStyle, |e| {
+                        e.emit_scalar_event(None, None, "1", true, false, YamlPlainScalarStyle)?;
+                        e.emit_scalar_event(None, None, "two words", true, false, YamlPlainScalarStyle)
+                    })?;
+                    e.emit_scalar_event(None, None, "b", true, false, YamlPlainScalarStyle)?;
+                    e.emit_scalar_event(None, None, "c: d", false, true, YamlDoubleQuotedScalarStyle)
+                })
+            })
+        }).unwrap();
+        let output = emitter.into_bytes();
+        assert_eq!(&b"a: [1, two words]\nb: \"c: d\"\n"[..], &output[..]);
+
+        assert_eq!(::parse_bytes_utf8(b"{a: [1, two words], b: \"c: d\"}"), ::parse_bytes_utf8(&output[..]));
+
+        // the encoding given to init is used unless the stream names one, and unflushed output is kept
+        let mut emitter = YamlByteEmitter::init(YamlUtf16LeEncoding);
+        emitter.emit_event(&YamlEventSpec::YamlStreamStartEvent(YamlAnyEncoding)).unwrap();
+        assert_eq!(b"\xff\xfe", &emitter.into_bytes()[..]);
+        let mut emitter = YamlByteEmitter::init(YamlUtf16LeEncoding);
+        emitter.emit_event(&YamlEventSpec::YamlStreamStartEvent(YamlUtf8Encoding)).unwrap();
+        assert!(emitter.into_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_emit_directives() {
+        use event::{YamlVersionDirective, YamlTagDirective};
+
+        let version = Some(YamlVersionDirective { major: 1, minor: 1 });
+        let tags = vec![YamlTagDirective { handle: "!e!".to_string(), prefix: "tag:example.com,2000:".to_string() }];
+        let mut emitter = YamlByteEmitter::init(YamlUtf8Encoding);
+        emitter.emit_stream(YamlUtf8Encoding, |e| {
+            e.emit_document(version, &tags, true, |e|
+                e.emit_scalar_event(None, Some("tag:example.com,2000:point"), "1, 2", false, false, YamlPlainScalarStyle))?;
+            e.emit_document(None, &[], true, |e| e.emit_scalar_event(None, None, "x", true, false, YamlPlainScalarStyle))
+        }).unwrap();
+        let output = String::from_utf8(emitter.into_bytes()).unwrap();
+        assert_eq!("%YAML 1.1\n%TAG !e! tag:example.com,2000:\n--- !e!point 1, 2\n--- x\n", output);
+
+        let docs: Vec<_> = YamlByteParser::init(output.as_bytes(), YamlUtf8Encoding).load().collect::<Result<_, _>>().unwrap();
+        assert_eq!(output, docs.iter().map(|doc| doc.to_string().unwrap()).collect::<Vec<_>>().concat());
+
+        let bad_handle = vec![YamlTagDirective { handle: "e".to_string(), prefix: "tag:x,2000:".to_string() }];
+        let mut emitter = YamlByteEmitter::init(YamlUtf8Encoding);
+        let err = emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_document(None, &bad_handle, false, |_| Ok(()))).unwrap_err();
+        assert_eq!(Some("tag handle must start with '!'".to_string()), err.problem);
+    }
+
+    fn emit_nested_sequences(sequence_indent: YamlSequenceIndent) -> Vec<u8> {
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { sequence_indent: sequence_indent, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                e.emit_document(None, &[], true, |e| {
+                    e.emit_mapping(None, None, true, YamlBlockSequenceStyle, |e| {
+                        e.emit_scalar_event(None, None, "items", true, false, YamlPlainScalarStyle)?;
+                        e.emit_sequence(None, None, true, YamlBlockSequenceStyle, |e| {
+                            e.emit_scalar_event(None, None, "1", true, false, YamlPlainScalarStyle)?;
+                            e.emit_mapping(None, None, true, YamlBlockSequenceStyle, |e| {
+                                e.emit_scalar_event(None, None, "b", true, false, YamlPlainScalarStyle)?;
+                                e.emit_sequence(None, None, true, YamlBlockSequenceStyle, |e| {
+                                    e.emit_scalar_event(None, None, "2", true, false, YamlPlainScalarStyle)
+                                })
+                            })
+                        })?;
+                        e.emit_scalar_event(None, None, "name", true, false, YamlPlainScalarStyle)?;
+                        e.emit_scalar_event(None, None, "x", true, false, YamlPlainScalarStyle)
+                    })
+                })
+            }).unwrap();
+        }
+        writer
+    }
+
+    #[test]
+    fn sequence_indent_same_as_key_test() {
+        let output = emit_nested_sequences(YamlSequenceIndent::YamlSameAsKey);
+        assert_eq!("items:\n- 1\n- b:\n  - 2\nname: x\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn sequence_indent_indented_test() {
+        let output = emit_nested_sequences(YamlSequenceIndent::YamlIndented);
+        assert_eq!("items:\n  - 1\n  - b:\n      - 2\nname: x\n", String::from_utf8(output.clone()).unwrap());
+
+        let reparsed = ::parse_bytes_utf8(&output[..]);
+        assert_eq!(::parse_bytes_utf8(&emit_nested_sequences(YamlSequenceIndent::YamlSameAsKey)[..]), reparsed);
+
+        // an emitter dropped before the stream ends still writes out what it held back
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { sequence_indent: YamlSequenceIndent::YamlIndented, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_event(&YamlEventSpec::YamlStreamStartEvent(YamlUtf8Encoding)).unwrap();
+            emitter.emit_event(&YamlEventSpec::YamlDocumentStartEvent(None, vec![], true)).unwrap();
+            emitter.emit_scalar_event(None, None, "a", true, false, YamlPlainScalarStyle).unwrap();
+            emitter.emit_event(&YamlEventSpec::YamlDocumentEndEvent(true)).unwrap();
+        }
+        assert_eq!(b"a\n", &writer[..]);
+    }
+
+    #[test]
+    fn sequence_indent_utf16_test() {
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { sequence_indent: YamlSequenceIndent::YamlIndented, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf16LeEncoding, |e| {
+                e.emit_document(None, &[], true, |e| {
+                    e.emit_mapping(None, None, true, YamlBlockSequenceStyle, |e| {
+                        e.emit_scalar_event(None, None, "a", true, false, YamlPlainScalarStyle)?;
+                        e.emit_sequence(None, None, true, YamlBlockSequenceStyle, |e| {
+                            e.emit_scalar_event(None, None, "1", true, false, YamlPlainScalarStyle)
+                        })
+                    })
+                })
+            }).unwrap();
+        }
+        let expected: Vec<u8> = "\u{feff}a:\n  - 1\n".encode_utf16().flat_map(|u| vec![u as u8, (u >> 8) as u8]).collect();
+        assert_eq!(expected, writer);
+    }
+
+    fn emit_long_scalar(width: Option<usize>) -> String {
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { width: width, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                e.emit_document(None, &[], true, |e| {
+                    let value = vec!["lorem ipsum"; 12].join(" ");
+                    e.emit_scalar_event(None, None, &value, true, false, YamlPlainScalarStyle)
+                })
+            }).unwrap();
+        }
+        String::from_utf8(writer).unwrap()
+    }
+
+    fn emit_plain_item(value: &str, style: ffi::YamlSequenceStyle, strict_plain: bool) -> Result<String, YamlError> {
+        let events = vec![
+            YamlEventSpec::YamlDocumentStartEvent(None, vec![], true),
+            YamlEventSpec::YamlSequenceStartEvent(YamlSequenceParam { anchor: None, tag: None, implicit: true, style }),
+            YamlEventSpec::YamlScalarEvent(YamlScalarParam { anchor: None, tag: None, value: value.to_string(),
+                plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle }),
+            YamlEventSpec::YamlSequenceEndEvent,
+            YamlEventSpec::YamlDocumentEndEvent(true)
+        ];
+
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { strict_plain, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                for evt in events.iter() {
+                    e.emit_event(evt)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(String::from_utf8(writer).unwrap())
+    }
+
+    #[test]
+    fn strict_plain_test() {
+        assert_eq!(Ok("- a,b\n".to_string()), emit_plain_item("a,b", YamlBlockSequenceStyle, true));
+        assert_eq!(Ok("- ! 'a: b'\n".to_string()), emit_plain_item("a: b", YamlBlockSequenceStyle, false));
+
+        let err = emit_plain_item("a,b", YamlFlowSequenceStyle, true).unwrap_err();
+        assert_eq!(ffi::YamlErrorType::YAML_EMITTER_ERROR, err.kind);
+        assert_eq!(Some("cannot emit \"a,b\" as a plain scalar".to_string()), err.problem);
+        for value in ["a: b", "- x", "", "x ", "caf\u{e9}", "#c", "a\nb", "---"].iter() {
+            assert!(emit_plain_item(value, YamlBlockSequenceStyle, true).is_err(), "{:?}", value);
+        }
+
+        assert!(!needs_quoting("a:b", false, false));
+        assert!(needs_quoting("a:b", true, false));
+        assert!(needs_quoting("a #b", false, false));
+        assert!(!needs_quoting("a#b", false, false));
+        assert!(!needs_quoting("caf\u{e9}", false, true));
+        assert!(!needs_quoting("-1", true, false));
+    }
+
+    #[test]
+    fn flow_threshold_test() {
+        use constructor::YamlStandardData::*;
+
+        let ints = |n: isize| YamlSequence((1..n + 1).map(YamlInteger).collect());
+        let value = YamlMapping(vec![
+            (YamlString("name".to_string()), YamlString("x".to_string())),
+            (YamlString("tags".to_string()), YamlSequence(vec![YamlString("a".to_string()), YamlString("b".to_string())])),
+            (YamlString("matrix".to_string()), YamlSequence(vec![ints(2), ints(3), ints(1)])),
+            (YamlString("nested".to_string()), YamlMapping(vec![(YamlString("big".to_string()), ints(4))])),
+            (YamlString("empty".to_string()), YamlMapping(vec![]))
+        ]);
+        let doc = YamlDocument::from_value(&value).unwrap();
+
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { flow_threshold: 3, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+        }
+        let output = String::from_utf8(writer).unwrap();
+        assert_eq!("name: x\ntags: [a, b]\nmatrix: [[1, 2], [1, 2, 3], [1]]\nnested:\n  big:\n  - 1\n  - 2\n  - 3\n  - 4\nempty: {}\n", output);
+        assert_eq!(Ok(vec![value]), ::parse_bytes_utf8(output.as_bytes()));
+    }
+
+    #[test]
+    fn width_test() {
+        let unlimited = emit_long_scalar(None);
+        assert_eq!(1, unlimited.lines().count());
+
+        let folded = emit_long_scalar(Some(40));
+        assert_eq!("lorem ipsum lorem ipsum lorem ipsum lorem\n  ipsum lorem ipsum lorem ipsum lorem ipsum\n  \
+                    lorem ipsum lorem ipsum lorem ipsum lorem\n  ipsum lorem ipsum\n", folded);
+        assert_eq!(::parse_bytes_utf8(unlimited.as_bytes()), ::parse_bytes_utf8(folded.as_bytes()));
+
+        assert!(emit_long_scalar(Some(80)).lines().count() > 1);
+    }
+
+    #[test]
+    fn document_round_trip_key_order_test() {
+        let data = "zulu: 1\nalpha: 2\nmike: 3\nbravo: 4\nyankee: 5\ncharlie: 6\nxray: 7\ndelta: 8\n\
+                    nested:\n  whiskey: a\n  echo: b\n  victor: c\nfoxtrot: [golf, hotel]\n\
+                    base: &base {uniform: 9, india: 10}\nref: *base\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+        let events = docs[0].to_events();
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                for evt in events.iter() {
+                    e.emit_event(evt)?;
+                }
+                Ok(())
+            }).unwrap();
+        }
+        assert_eq!(data, String::from_utf8(writer).unwrap());
+
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        // to_events lets the emitter quote an untagged plain scalar without adding a `!` tag
+        let parsed: Vec<YamlEventSpec> = parser.parse().map(|evt| match evt.unwrap().spec {
+            YamlEventSpec::YamlScalarEvent(param) => YamlEventSpec::YamlScalarEvent(YamlScalarParam {
+                quoted_implicit: param.plain_implicit || param.quoted_implicit,
+                ..param
+            }),
+            spec => spec
+        }).collect();
+        assert_eq!(&parsed[1..parsed.len() - 1], &events[..]);
+    }
+
+    #[test]
+    fn document_from_value_test() {
+        use constructor::YamlStandardData::*;
+
+        let value = YamlMapping(vec![
+            (YamlString("name".to_string()), YamlString("x".to_string())),
+            (YamlString("1".to_string()), YamlSequence(vec![YamlInteger(1), YamlFloat(1.0), YamlFloat(-f64::INFINITY)])),
+            (YamlString("flags".to_string()), YamlSequence(vec![YamlBool(true), YamlString("yes".to_string()), YamlNull])),
+            (YamlString("empty".to_string()), YamlMapping(vec![]))
+        ]);
+        let doc = YamlDocument::from_value(&value).unwrap();
+        assert_eq!(15, doc.node_count());
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                for evt in doc.to_events().iter() {
+                    e.emit_event(evt)?;
+                }
+                Ok(())
+            }).unwrap();
+        }
+        let output = String::from_utf8(writer).unwrap();
+        assert_eq!("name: x\n'1':\n- 1\n- 1.0\n- -.inf\nflags:\n- true\n- 'yes'\n- null\nempty: {}\n", output);
+        assert_eq!(Ok(vec![value]), ::parse_bytes_utf8(output.as_bytes()));
+    }
+
+    #[test]
+    fn document_style_round_trip_test() {
+        let data = "name: box\nitems: [a, 'b c']\nnested:\n  inner: {k: v}\nlist:\n- 1\n- [2, 3]\n- {x: \"y\"}\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+
+        let root = doc.root().unwrap();
+        assert_eq!(YamlBlockSequenceStyle, root.as_mapping().unwrap().style());
+        assert_eq!(YamlFlowSequenceStyle, doc.query("items").unwrap().as_sequence().unwrap().style());
+        assert_eq!(YamlFlowSequenceStyle, doc.query("nested.inner").unwrap().as_mapping().unwrap().style());
+        assert_eq!(YamlBlockSequenceStyle, doc.query("list").unwrap().as_sequence().unwrap().style());
+
+        assert_eq!(Ok(data.to_string()), doc.to_string());
+    }
+
+    #[test]
+    fn emit_all_test() {
+        let data = "name: box\nitems: [a, 'b c']\nnested:\n  key: value\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let events = parser.parse().map(|res| match res.unwrap().spec {
+            YamlEventSpec::YamlScalarEvent(param) => YamlEventSpec::YamlScalarEvent(YamlScalarParam {
+                value: param.value.to_uppercase(),
+                ..param
+            }),
+            spec => spec
+        });
+
+        let mut writer = Vec::new();
+        YamlEmitter::init(&mut writer).emit_all(events).unwrap();
+        assert_eq!("NAME: BOX\nITEMS: [A, 'B C']\nNESTED:\n  KEY: VALUE\n", String::from_utf8(writer).unwrap());
+
+        let mut writer = Vec::new();
+        let err = YamlEmitter::init(&mut writer).emit_all(vec![
+            YamlEventSpec::YamlStreamStartEvent(YamlUtf8Encoding),
+            YamlEventSpec::YamlDocumentStartEvent(None, vec![], true),
+            YamlEventSpec::YamlMappingEndEvent
+        ]).unwrap_err();
+        assert_eq!(ffi::YamlErrorType::YAML_EMITTER_ERROR, err.kind);
+    }
+
+    #[test]
+    fn document_builder_test() {
+        use document::YamlDocumentBuilder;
+
+        let mut builder = YamlDocumentBuilder::new().unwrap();
+        let root = builder.add_mapping(YamlBlockSequenceStyle).unwrap();
+        let greeting = builder.add_scalar("greeting", YamlPlainScalarStyle).unwrap();
+        let hello = builder.add_scalar("hello", YamlPlainScalarStyle).unwrap();
+        builder.insert(root, greeting, hello).unwrap();
+        let items_key = builder.add_scalar("items", YamlPlainScalarStyle).unwrap();
+        let items = builder.add_sequence(YamlFlowSequenceStyle).unwrap();
+        for value in ["1", "2"].iter() {
+            let item = builder.add_scalar(value, YamlPlainScalarStyle).unwrap();
+            builder.append(items, item).unwrap();
+        }
+        builder.insert(root, items_key, items).unwrap();
+
+        let err = builder.append(root, hello).unwrap_err();
+        assert_eq!(ffi::YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+        assert_eq!(Some("node 1 is not a YamlSequenceKind".to_string()), err.problem);
+        let mut other = YamlDocumentBuilder::new().unwrap();
+        let foreign = (0..8).map(|_| other.add_scalar("x", YamlPlainScalarStyle).unwrap()).last().unwrap();
+        let err = builder.append(items, foreign).unwrap_err();
+        assert_eq!(Some("node 8 is not in the document".to_string()), err.problem);
+
+        // a collection cannot be put inside itself, but a node may be shared
+        let wrapper = builder.add_sequence(YamlFlowSequenceStyle).unwrap();
+        builder.append(wrapper, items).unwrap();
+        let err = builder.append(items, wrapper).unwrap_err();
+        assert_eq!(ffi::YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+        assert_eq!(Some("node 8 contains node 5".to_string()), err.problem);
+        assert!(builder.append(items, items).is_err());
+        assert!(builder.insert(root, root, hello).is_err());
+        builder.append(wrapper, items).unwrap();
+
+        let doc = builder.build();
+        assert_eq!(Ok("greeting: hello\nitems: [1, 2]\n".to_string()), doc.to_string());
+    }
+
+    #[test]
+    fn binary_round_trip_test() {
+        use document::YamlDocumentBuilder;
+
+        let blobs: Vec<Vec<u8>> = vec![(0..=255).collect(), b"GIF89a".to_vec(), Vec::new()];
+        let mut builder = YamlDocumentBuilder::new().unwrap();
+        let root = builder.add_sequence(YamlBlockSequenceStyle).unwrap();
+        for blob in blobs.iter() {
+            let item = builder.add_binary(blob).unwrap();
+            builder.append(root, item).unwrap();
+        }
+        let output = builder.build().to_string().unwrap();
+        assert!(output.starts_with("- !!binary |\n  AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4\n"));
+
+        let parser = YamlByteParser::init(output.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let decoded: Vec<Option<Vec<u8>>> = doc.root().unwrap().as_sequence().unwrap().values()
+            .map(|node| node.as_scalar().unwrap().as_binary()).collect();
+        assert_eq!(blobs.into_iter().map(Some).collect::<Vec<_>>(), decoded);
+
+        let parser = YamlByteParser::init(b"[!!binary 'R0lG\n  ODlh', R0lGODlh, !!binary '*']", YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let decoded: Vec<Option<Vec<u8>>> = doc.root().unwrap().as_sequence().unwrap().values()
+            .map(|node| node.as_scalar().unwrap().as_binary()).collect();
+        assert_eq!(vec![Some(b"GIF89a".to_vec()), None, None], decoded);
+    }
+
+    #[test]
+    fn radix_round_trip_test() {
+        use constructor::YamlRadix;
+        use constructor::YamlStandardData::*;
+        use constructor::YamlRadix::*;
+
+        let data = "mode: 0o755\nmask: 0xff\nbits: 0b101\nneg: -0x10\nlegacy: 0644\nplain: 16\nquoted: '0x10'\n";
+        let doc = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).load().next().unwrap().unwrap();
+        let radixes: Vec<Option<YamlRadix>> = doc.root().unwrap().as_mapping().unwrap().pairs()
+            .map(|(_, value)| value.as_scalar().unwrap().radix()).collect();
+        assert_eq!(vec![Some(YamlOctalRadix), Some(YamlHexRadix), Some(YamlBinaryRadix), Some(YamlHexRadix),
+            Some(YamlOctalRadix), None, None], radixes);
+
+        let value = doc.to_value().unwrap();
+        assert_eq!(YamlMapping(vec![
+            (YamlString("mode".to_string()), YamlInteger(0o755)),
+            (YamlString("mask".to_string()), YamlInteger(0xff)),
+            (YamlString("bits".to_string()), YamlInteger(0b101)),
+            (YamlString("neg".to_string()), YamlInteger(-0x10)),
+            (YamlString("legacy".to_string()), YamlInteger(0o644)),
+            (YamlString("plain".to_string()), YamlInteger(16)),
+            (YamlString("quoted".to_string()), YamlString("0x10".to_string()))
+        ]), value);
+
+        let radixes = doc.integer_radixes();
+        assert_eq!(Some(&YamlHexRadix), radixes.get(&vec![3]));
+        let output = YamlDocument::from_value_with_radixes(&value, &radixes).unwrap().to_string().unwrap();
+        assert_eq!("mode: 0o755\nmask: 0xff\nbits: 0b101\nneg: -0x10\nlegacy: 0o644\nplain: 16\nquoted: '0x10'\n", output);
+        assert_eq!("mode: 493\n", YamlDocument::from_value(&YamlMapping(vec![
+            (YamlString("mode".to_string()), YamlInteger(0o755))])).unwrap().to_string().unwrap());
+    }
+
+    #[test]
+    fn shared_anchor_test() {
+        use constructor::YamlStandardData::*;
+
+        let limits = YamlMapping(vec![(YamlString("retries".to_string()), YamlInteger(3)), (YamlString("timeout".to_string()), YamlInteger(10))]);
+        let tags = YamlSequence(vec![YamlString("x".to_string()), YamlString("y".to_string())]);
+        let value = YamlMapping(vec![
+            (YamlString("tags".to_string()), tags.clone()),
+            (YamlString("defaults".to_string()), limits.clone()),
+            (YamlString("prod".to_string()), limits.clone()),
+            (YamlString("dev".to_string()), YamlMapping(vec![(YamlString("db".to_string()), limits), (YamlString("tags".to_string()), tags)])),
+            (YamlString("empty".to_string()), YamlSequence(vec![])),
+            (YamlString("none".to_string()), YamlSequence(vec![]))
+        ]);
+
+        let emit = |value| {
+            let doc = YamlDocument::from_value_shared(value).unwrap();
+            let mut writer = Vec::new();
+            {
+                let mut emitter = YamlEmitter::init(&mut writer);
+                emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+            }
+            String::from_utf8(writer).unwrap()
+        };
+        let output = emit(&value);
+        assert_eq!("tags: &a1\n- x\n- y\ndefaults: &a2\n  retries: 3\n  timeout: 10\nprod: *a2\ndev:\n  db: *a2\n  tags: *a1\nempty: []\nnone: []\n", output);
+        assert_eq!(output, emit(&value.clone()));
+        assert_eq!(Ok(vec![value]), ::parse_bytes_utf8(output.as_bytes()));
+    }
+
+    #[test]
+    fn explicit_key_round_trip_test() {
+        let data = "? [a, b]\n: value\n? - c\n  - d\n: block\n? {x: y}\n: flow\nplain: z\n";
+        let doc = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).load().next().unwrap().unwrap();
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+        }
+        let output = String::from_utf8(writer).unwrap();
+        assert_eq!(data, output);
+
+        let value = ::parse_single_document(data.as_bytes(), YamlUtf8Encoding).unwrap();
+        let doc = YamlDocument::from_value(&value).unwrap();
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+        }
+        let output = String::from_utf8(writer).unwrap();
+        assert_eq!("? - a\n  - b\n: value\n? - c\n  - d\n: block\n? x: y\n: flow\nplain: z\n", output);
+        assert_eq!(Ok(value), ::parse_single_document(output.as_bytes(), YamlUtf8Encoding));
+    }
+
+    #[test]
+    fn estimate_emit_size_test() {
+        let sources = [
+            "a: 1\n",
+            "--- !!map\nname: &n app\nlist: [*n, 'x y', \"\\u00e9\\u4e2d\\x01\"]\nnested:\n  deeper:\n    deepest: [1, {k: v}]\n",
+            "--- !local\ntext: |\n  line one\n  line two\n...\n",
+        ];
+        for data in sources.iter() {
+            let events: Vec<YamlEventSpec> = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse()
+                .map(|evt| evt.unwrap().spec).collect();
+            let mut writer = Vec::new();
+            {
+                let mut emitter = YamlEmitter::init(&mut writer);
+                for evt in events.iter() {
+                    emitter.emit_event(evt).unwrap();
+                }
+            }
+            let estimate = estimate_emit_size(&events);
+            assert!(writer.len() <= estimate && estimate <= 10 * writer.len(), "{} for {:?}", estimate, String::from_utf8(writer));
+        }
+
+        let long = "x".repeat(1000);
+        let events = vec![
+            YamlEventSpec::YamlStreamStartEvent(YamlUtf8Encoding),
+            YamlEventSpec::YamlDocumentStartEvent(None, vec![], true),
+            YamlEventSpec::YamlScalarEvent(YamlScalarParam { anchor: None, tag: None, value: long, plain_implicit: true, quoted_implicit: true, style: YamlDoubleQuotedScalarStyle }),
+            YamlEventSpec::YamlDocumentEndEvent(true),
+            YamlEventSpec::YamlStreamEndEvent
+        ];
+        let mut writer = Vec::new();
+        YamlEmitter::init(&mut writer).emit_stream(YamlUtf8Encoding, |e| e.emit_events(&events[1..4])).unwrap();
+        assert!(writer.len() <= estimate_emit_size(&events));
+
+        let mut utf16_events = events.clone();
+        utf16_events[0] = YamlEventSpec::YamlStreamStartEvent(YamlUtf16LeEncoding);
+        let mut writer = Vec::new();
+        YamlEmitter::init(&mut writer).emit_stream(YamlUtf16LeEncoding, |e| e.emit_events(&events[1..4])).unwrap();
+        assert!(writer.starts_with(b"\xff\xfe") && writer.len() <= estimate_emit_size(&utf16_events));
+
+        assert!(estimate_emit_size(&[YamlEventSpec::YamlSequenceEndEvent]) > 0);
+    }
+
+    #[test]
+    fn emitter_config_test() {
+        let emit = |config: YamlEmitterConfig, data: &str| {
+            let events: Vec<YamlEventSpec> = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse()
+                .map(|res| res.unwrap().spec).collect();
+            let mut writer = Vec::new();
+            {
+                let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+                emitter.emit_events(&events).unwrap();
+            }
+            String::from_utf8(writer).unwrap()
+        };
+        let data = "a:\n  b: [1]\n  c:\n  - d\n";
+
+        let config = |indent| YamlEmitterConfig { indent, ..YamlEmitterConfig::default() };
+        assert_eq!("a:\n    b: [1]\n    c:\n    - d\n", emit(config(4), data));
+        assert_eq!(emit(config(2), data), emit(config(0), data));
+        assert_eq!(emit(config(9), data), emit(config(20), data));
+        let indented = YamlEmitterConfig { sequence_indent: YamlSequenceIndent::YamlIndented, ..config(3) };
+        assert_eq!("a:\n   b: [1]\n   c:\n      - d\n", emit(indented, data));
+
+        let canonical = YamlEmitterConfig { canonical: true, ..YamlEmitterConfig::default() };
+        // libyaml only writes the tags that events carry, so a plain scalar gets the "!" tag when quoted
+        assert_eq!("---\n[\n  ! \"x\",\n]\n", emit(canonical, "[x]"));
+
+        let unicode = YamlEmitterConfig { unicode: true, ..YamlEmitterConfig::default() };
+        assert_eq!("\"caf\\xE9\\t\"\n", emit(YamlEmitterConfig::default(), "\"café\\t\""));
+        assert_eq!("\"café\\t\"\n", emit(unicode, "\"café\\t\""));
+
+        let crlf = YamlEmitterConfig { break_style: ffi::YamlBreak::YamlCrLnBreak, ..YamlEmitterConfig::default() };
+        assert_eq!("a:\r\n  b: [1]\r\n  c:\r\n  - d\r\n", emit(crlf, data));
+        #[allow(deprecated)]
+        let old_name: ffi::yaml_break_t = ffi::yaml_break_t::YAML_CRLN_BREAK;
+        assert_eq!(emit(crlf, data), emit(YamlEmitterConfig { break_style: old_name, ..crlf }, data));
+    }
+
+    #[test]
+    fn multiline_style_test() {
+        use constructor::YamlStandardData::*;
+
+        let paragraph = "Folded text joins lines with spaces, so this sentence is long enough to be folded at the width.";
+        let value = YamlMapping(vec![
+            (YamlString("short".to_string()), YamlString("one\ntwo\n".to_string())),
+            (YamlString("long".to_string()), YamlString(format!("{}\n  more indented\n  code\n\nlast line", paragraph))),
+            (YamlString("flow".to_string()), YamlSequence(vec![YamlString("a\nb".to_string())]))
+        ]);
+        let doc = YamlDocument::from_value(&value).unwrap();
+
+        let emit = |multiline_style| {
+            let mut writer = Vec::new();
+            {
+                let config = YamlEmitterConfig { multiline_style, flow_threshold: 1, ..YamlEmitterConfig::default() };
+                let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+                emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+            }
+            let output = String::from_utf8(writer).unwrap();
+            assert_eq!(Ok(vec![value.clone()]), ::parse_bytes_utf8(output.as_bytes()), "{}", output);
+            output
+        };
+
+        let unset = emit(None);
+        assert!(unset.starts_with("short: 'one\n\n  two\n\n  '\n"), "{}", unset);
+
+        let literal = emit(Some(YamlMultilineStyle::YamlLiteralMultiline));
+        assert_eq!(format!("short: |\n  one\n  two\nlong: |-\n  {}\n    more indented\n    code\n\n  last line\nflow: [\"a\\nb\"]\n", paragraph), literal);
+
+        let folded = emit(Some(YamlMultilineStyle::YamlFoldedMultiline));
+        assert!(folded.starts_with("short: >\n  one\n\n  two\nlong: >-\n  Folded text"), "{}", folded);
+        assert!(folded.contains("\n    more indented\n    code\n\n  last line\n"), "{}", folded);
+
+        let auto = emit(Some(YamlMultilineStyle::YamlAutoMultiline));
+        assert!(auto.starts_with("short: |\n") && auto.contains("long: >-\n"), "{}", auto);
+    }
+
+    #[test]
+    fn chomping_round_trip_test() {
+        use document::{YamlChomping, YamlNode};
+        use document::YamlChomping::*;
+
+        let data = "strip: |-\n  text\nclip: >\n  a b\nkeep: |+\n  text\n\nfolded_keep: >+\n  x\n\n\nplain: x\n";
+        let doc = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).load().next().unwrap().unwrap();
+        let chompings: Vec<Option<YamlChomping>> = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping.pairs().map(|(_, value)| match value {
+                YamlNode::YamlScalarNode(scalar) => scalar.chomping(),
+                _ => panic!("expected a scalar")
+            }).collect(),
+            _ => panic!("expected a mapping")
+        };
+        assert_eq!(vec![Some(YamlStripChomping), Some(YamlClipChomping), Some(YamlKeepChomping), Some(YamlKeepChomping), None], chompings);
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| e.emit_events(&doc.to_events())).unwrap();
+        }
+        // after a kept block scalar libyaml ends the document explicitly
+        assert_eq!(format!("{}...\n", data), String::from_utf8(writer).unwrap());
+    }
+
+    #[test]
+    fn null_key_round_trip_test() {
+        let value = ::parse_single_document(b"?\n: x\n? ''\n", YamlUtf8Encoding).unwrap();
+        let doc = YamlDocument::from_value(&value).unwrap();
+
+        let mut writer = Vec::new();
+        {
+            let mut emitter = YamlEmitter::init(&mut writer);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                for evt in doc.to_events().iter() {
+                    e.emit_event(evt)?;
+                }
+                Ok(())
+            }).unwrap();
+        }
+        let output = String::from_utf8(writer).unwrap();
+        assert_eq!("null: x\n'': null\n", output);
+        assert_eq!(Ok(value), ::parse_single_document(output.as_bytes(), YamlUtf8Encoding));
+    }
+
+    #[test]
+    fn always_quote_scalars_test() {
+        let data = "name: x\ncount: 1\nflags: [true, ~, 'single']\nblock: |\n  text\n";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+
+        let mut writer = Vec::new();
+        {
+            let config = YamlEmitterConfig { always_quote_scalars: true, ..YamlEmitterConfig::default() };
+            let mut emitter = YamlEmitter::init_with_config(&mut writer, config);
+            emitter.emit_stream(YamlUtf8Encoding, |e| {
+                for evt in docs[0].to_events().iter() {
+                    e.emit_event(evt)?;
+                }
+                Ok(())
+            }).unwrap();
+        }
+        assert_eq!("\"name\": \"x\"\n\"count\": \"1\"\n\"flags\": [\"true\", \"~\", \"single\"]\n\"block\": \"text\\n\"\n",
+                   String::from_utf8(writer.clone()).unwrap());
+
+        let parser = YamlByteParser::init(&writer[..], YamlUtf8Encoding);
+        for evt in parser.parse() {
+            if let YamlEventSpec::YamlScalarEvent(param) = evt.unwrap().spec {
+                assert_eq!(YamlDoubleQuotedScalarStyle, param.style);
+                assert_eq!(None, param.tag);
+            }
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use std::error::Error;
 use std::io;
 use std::fmt;
+use std::ops::Range;
 use ffi;
 use ffi::YamlErrorType;
 
@@ -49,16 +50,7 @@ impl PartialEq for YamlError {
 
 impl Error for YamlError {
     fn description(&self) -> &str {
-        match self.kind {
-            YamlErrorType::YAML_NO_ERROR => "No error is produced",
-            YamlErrorType::YAML_MEMORY_ERROR => "Cannot allocate or reallocate a block of memory",
-            YamlErrorType::YAML_READER_ERROR => "Cannot read or decode the input stream",
-            YamlErrorType::YAML_SCANNER_ERROR => "Cannot scan the input stream",
-            YamlErrorType::YAML_PARSER_ERROR => "Cannot parse the input stream",
-            YamlErrorType::YAML_COMPOSER_ERROR => "Cannot compose a YAML document",
-            YamlErrorType::YAML_WRITER_ERROR => "Cannot write to the output stream",
-            YamlErrorType::YAML_EMITTER_ERROR => "Cannot emit a YAML stream",
-        }
+        kind_description(self.kind)
     }
 
     fn cause(&self) -> Option<&Error> {
@@ -69,6 +61,22 @@ impl Error for YamlError {
     }
 }
 
+fn kind_description(kind: YamlErrorType) -> &'static str {
+    match kind {
+        YamlErrorType::YAML_NO_ERROR => "No error is produced",
+        YamlErrorType::YAML_MEMORY_ERROR => "Cannot allocate or reallocate a block of memory",
+        YamlErrorType::YAML_READER_ERROR => "Cannot read or decode the input stream",
+        YamlErrorType::YAML_SCANNER_ERROR => "Cannot scan the input stream",
+        YamlErrorType::YAML_PARSER_ERROR => "Cannot parse the input stream",
+        YamlErrorType::YAML_COMPOSER_ERROR => "Cannot compose a YAML document",
+        YamlErrorType::YAML_WRITER_ERROR => "Cannot write to the output stream",
+        YamlErrorType::YAML_EMITTER_ERROR => "Cannot emit a YAML stream",
+        YamlErrorType::YAML_LIMIT_ERROR => "A configured limit on the input was exceeded",
+        YamlErrorType::YAML_VALIDATION_ERROR => "The input does not have the expected structure",
+        YamlErrorType::YAML_INITIALIZATION_ERROR => "Cannot initialize a libyaml parser",
+    }
+}
+
 impl YamlError {
     pub fn new(kind: YamlErrorType, problem: Option<String>) -> YamlError {
         YamlError {
@@ -78,6 +86,22 @@ impl YamlError {
             context: None
         }
     }
+
+    /// Returns the region of the input the error refers to, suitable for highlighting.
+    ///
+    /// The span runs from the context mark up to and including the problem mark. When the
+    /// error carries no context, a single-character span at the problem mark is returned.
+    /// Offsets are in characters, as counted by the `index` of a `YamlMark`.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.context.as_ref().map(|ctx| {
+            let end = ctx.problem_mark.index + 1;
+            if ctx.context.is_some() && ctx.context_mark.index < end {
+                ctx.context_mark.index..end
+            } else {
+                ctx.problem_mark.index..end
+            }
+        })
+    }
 }
 
 impl fmt::Display for YamlError {
@@ -88,3 +112,78 @@ impl fmt::Display for YamlError {
         }
     }
 }
+
+/// Converts to the wrapped `io_error` when there is one. Otherwise the result is an
+/// `InvalidData` error whose message is the problem followed by the one-based line and column
+/// of the problem mark, if any.
+impl From<YamlError> for io::Error {
+    fn from(err: YamlError) -> io::Error {
+        if let Some(io_error) = err.io_error {
+            return io_error;
+        }
+        let mut message = match err.problem {
+            Some(ref problem) => problem.clone(),
+            None => kind_description(err.kind).to_string()
+        };
+        if let Some(ref ctx) = err.context {
+            message.push_str(&format!(" at line {}, column {}", ctx.problem_mark.line + 1, ctx.problem_mark.column + 1));
+        }
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use parser::{YamlParser, YamlByteParser};
+    use ffi::YamlEncoding::YamlUtf8Encoding;
+    use ffi::YamlErrorType;
+    use error::{YamlError, YamlErrorContext, YamlMark};
+
+    #[test]
+    fn test_span_with_context() {
+        let data = "[1, \"ab";
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+
+        match parser.parse().find(|res| res.is_err()) {
+            Some(Err(err)) => assert_eq!(Some(4..8), err.span()),
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_span_without_context() {
+        let mark = YamlMark { index: 7, line: 1, column: 2 };
+        let err = YamlError {
+            kind: YamlErrorType::YAML_PARSER_ERROR,
+            problem: Some("problem".to_string()),
+            io_error: None,
+            context: Some(YamlErrorContext {
+                byte_offset: 7,
+                problem_mark: mark,
+                context: None,
+                context_mark: YamlMark { index: 0, line: 0, column: 0 }
+            })
+        };
+
+        assert_eq!(Some(7..8), err.span());
+        assert_eq!(None, YamlError::new(YamlErrorType::YAML_EMITTER_ERROR, None).span());
+    }
+
+    #[test]
+    fn test_into_io_error() {
+        let parser = YamlByteParser::init(b"a: [1", YamlUtf8Encoding);
+        let err = match parser.parse().find(|res| res.is_err()) {
+            Some(Err(err)) => io::Error::from(err),
+            res => panic!("unexpected result: {:?}", res)
+        };
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("did not find expected ',' or ']' at line 2, column 1", err.to_string());
+
+        let mut err = YamlError::new(YamlErrorType::YAML_READER_ERROR, Some("cannot read".to_string()));
+        err.io_error = Some(io::Error::new(io::ErrorKind::TimedOut, "slow reader"));
+        let err: io::Error = err.into();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert_eq!("slow reader", err.to_string());
+    }
+}
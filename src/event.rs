@@ -4,6 +4,8 @@ use ffi::yaml_event_type_t::*;
 use std::ffi::{CString, NulError};
 use std::mem;
 use std::ptr;
+use std::slice;
+use std::str;
 
 use codecs;
 use ::error::YamlMark;
@@ -14,24 +16,23 @@ pub struct YamlVersionDirective {
     pub minor: isize,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct YamlTagDirective {
     pub handle: String,
     pub prefix: String,
 }
 
 impl YamlTagDirective {
-    pub fn to_tag_directive_t(&self) -> Result<ffi::yaml_tag_directive_t, NulError> {
+    /// The handle and prefix as C strings, which a `yaml_tag_directive_t` may point into for
+    /// as long as they live.
+    pub fn to_c_strings(&self) -> Result<(CString, CString), NulError> {
         let handle = try!(CString::new(self.handle.as_bytes()));
         let prefix = try!(CString::new(self.prefix.as_bytes()));
-        Ok(ffi::yaml_tag_directive_t {
-            handle: handle.as_ptr(),
-            prefix: prefix.as_ptr()
-        })
+        Ok((handle, prefix))
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct YamlSequenceParam {
     pub anchor: Option<String>,
     pub tag: Option<String>,
@@ -39,7 +40,14 @@ pub struct YamlSequenceParam {
     pub style: YamlSequenceStyle
 }
 
-#[derive(Debug, PartialEq)]
+impl YamlSequenceParam {
+    /// The tag split into handle and suffix by `codecs::split_tag`.
+    pub fn tag_parts(&self) -> Option<(Option<String>, String)> {
+        self.tag.as_ref().map(|tag| codecs::split_tag(tag))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct YamlScalarParam {
     pub anchor: Option<String>,
     pub tag: Option<String>,
@@ -49,7 +57,14 @@ pub struct YamlScalarParam {
     pub style: YamlScalarStyle
 }
 
-#[derive(Debug, PartialEq)]
+impl YamlScalarParam {
+    /// The tag split into handle and suffix by `codecs::split_tag`.
+    pub fn tag_parts(&self) -> Option<(Option<String>, String)> {
+        self.tag.as_ref().map(|tag| codecs::split_tag(tag))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum YamlEventSpec {
     YamlNoEvent,
     YamlStreamStartEvent(YamlEncoding),
@@ -64,10 +79,12 @@ pub enum YamlEventSpec {
     YamlMappingEndEvent,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct YamlEvent {
     pub spec: YamlEventSpec,
+    /// Where the event starts in the input, as libyaml reports it in `start_mark`.
     pub start: YamlMark,
+    /// Where the event ends in the input, just past its last character.
     pub end: YamlMark
 }
 
@@ -159,3 +176,53 @@ impl YamlEvent {
         }
     }
 }
+
+/// An event read in place from libyaml's buffers, valid until its stream moves to the next one.
+pub struct YamlBorrowedEvent<'a> {
+    event: &'a ffi::yaml_event_t
+}
+
+impl<'a> YamlBorrowedEvent<'a> {
+    pub(crate) fn new(event: &'a ffi::yaml_event_t) -> YamlBorrowedEvent<'a> {
+        YamlBorrowedEvent { event }
+    }
+
+    pub fn event_type(&self) -> ffi::yaml_event_type_t {
+        self.event.event_type
+    }
+
+    pub fn start(&self) -> YamlMark {
+        YamlMark::conv(&self.event.start_mark)
+    }
+
+    pub fn end(&self) -> YamlMark {
+        YamlMark::conv(&self.event.end_mark)
+    }
+
+    /// The value of a scalar event, or `None` for other events.
+    pub fn scalar_bytes(&self) -> Option<&'a [u8]> {
+        if self.event.event_type != YAML_SCALAR_EVENT {
+            return None;
+        }
+        unsafe {
+            let evt_data: &ffi::yaml_scalar_event_t = mem::transmute(&self.event.data);
+            if evt_data.length == 0 {
+                Some(&[])
+            } else {
+                Some(slice::from_raw_parts(evt_data.value, evt_data.length as usize))
+            }
+        }
+    }
+
+    /// Like `scalar_bytes`; libyaml gives values in UTF-8 whatever the input encoding.
+    pub fn scalar_str(&self) -> Option<&'a str> {
+        self.scalar_bytes().and_then(|bytes| str::from_utf8(bytes).ok())
+    }
+
+    /// Copies the event into an owned `YamlEvent`.
+    pub fn to_event(&self) -> YamlEvent {
+        unsafe {
+            YamlEvent::load(self.event)
+        }
+    }
+}
@@ -1,16 +1,45 @@
 pub use type_size::*;
-use libc::{c_char, c_uchar, c_int, c_void, size_t};
+use libc::{c_char, c_uchar, c_int, c_void, size_t, FILE};
 use parser::YamlIoParser;
-use emitter::YamlEmitter;
+use error::YamlError;
+
+use std::str::FromStr;
 
 #[allow(non_camel_case_types)]
 pub type yaml_char_t = c_uchar;
 
+/** The tag @c !!null with the only possible value: @c null. */
+pub const YAML_NULL_TAG: &str = "tag:yaml.org,2002:null";
+/** The tag @c !!bool with the values: @c true and @c false. */
+pub const YAML_BOOL_TAG: &str = "tag:yaml.org,2002:bool";
+/** The tag @c !!str for string values. */
+pub const YAML_STR_TAG: &str = "tag:yaml.org,2002:str";
+/** The tag @c !!int for integer values. */
+pub const YAML_INT_TAG: &str = "tag:yaml.org,2002:int";
+/** The tag @c !!float for float values. */
+pub const YAML_FLOAT_TAG: &str = "tag:yaml.org,2002:float";
+/** The tag @c !!timestamp for date and time values. */
+pub const YAML_TIMESTAMP_TAG: &str = "tag:yaml.org,2002:timestamp";
+/** The tag @c !!binary for base64 encoded bytes. */
+pub const YAML_BINARY_TAG: &str = "tag:yaml.org,2002:binary";
+
+/** The tag @c !!seq is used to denote sequences. */
+pub const YAML_SEQ_TAG: &str = "tag:yaml.org,2002:seq";
+/** The tag @c !!map is used to denote mapping. */
+pub const YAML_MAP_TAG: &str = "tag:yaml.org,2002:map";
+
+/** The default scalar tag is @c !!str. */
+pub const YAML_DEFAULT_SCALAR_TAG: &str = YAML_STR_TAG;
+/** The default sequence tag is @c !!seq. */
+pub const YAML_DEFAULT_SEQUENCE_TAG: &str = YAML_SEQ_TAG;
+/** The default mapping tag is @c !!map. */
+pub const YAML_DEFAULT_MAPPING_TAG: &str = YAML_MAP_TAG;
+
 #[allow(non_camel_case_types)]
 pub type yaml_read_handler_t = extern fn(data: *mut YamlIoParser, buffer: *mut u8, size: size_t, size_read: *mut size_t) -> c_int;
 
 #[allow(non_camel_case_types)]
-pub type yaml_write_handler_t = extern fn(data: *mut YamlEmitter, buffer: *const u8, size: size_t) -> c_int;
+pub type yaml_write_handler_t = extern fn(data: *mut c_void, buffer: *const u8, size: size_t) -> c_int;
 
 #[repr(C)]
 #[allow(non_camel_case_types)]
@@ -34,7 +63,14 @@ pub enum YamlErrorType {
     /** Cannot write to the output stream. */
     YAML_WRITER_ERROR,
     /** Cannot emit a YAML stream. */
-    YAML_EMITTER_ERROR
+    YAML_EMITTER_ERROR,
+
+    /** A configured limit on the input was exceeded. Never produced by libyaml. */
+    YAML_LIMIT_ERROR,
+    /** The input does not have the expected structure. Never produced by libyaml. */
+    YAML_VALIDATION_ERROR,
+    /** Cannot initialize a libyaml parser. Never stored by libyaml. */
+    YAML_INITIALIZATION_ERROR
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -69,6 +105,54 @@ pub enum YamlScalarStyle {
     YamlFoldedScalarStyle
 }
 
+const SEQUENCE_STYLE_NAMES: [(YamlSequenceStyle, &str); 3] = [
+    (YamlSequenceStyle::YamlAnySequenceStyle, "any"),
+    (YamlSequenceStyle::YamlBlockSequenceStyle, "block"),
+    (YamlSequenceStyle::YamlFlowSequenceStyle, "flow"),
+];
+
+const SCALAR_STYLE_NAMES: [(YamlScalarStyle, &str); 6] = [
+    (YamlScalarStyle::YamlAnyScalarStyle, "any"),
+    (YamlScalarStyle::YamlPlainScalarStyle, "plain"),
+    (YamlScalarStyle::YamlSingleQuotedScalarStyle, "single-quoted"),
+    (YamlScalarStyle::YamlDoubleQuotedScalarStyle, "double-quoted"),
+    (YamlScalarStyle::YamlLiteralScalarStyle, "literal"),
+    (YamlScalarStyle::YamlFoldedScalarStyle, "folded"),
+];
+
+impl YamlSequenceStyle {
+    /// The name read back by `from_str`: `any`, `block` or `flow`.
+    pub fn as_str(&self) -> &'static str {
+        SEQUENCE_STYLE_NAMES.iter().find(|&&(style, _)| style == *self).unwrap().1
+    }
+}
+
+impl FromStr for YamlSequenceStyle {
+    type Err = YamlError;
+
+    fn from_str(s: &str) -> Result<YamlSequenceStyle, YamlError> {
+        SEQUENCE_STYLE_NAMES.iter().find(|&&(_, name)| name == s).map(|&(style, _)| style)
+            .ok_or_else(|| YamlError::new(YamlErrorType::YAML_VALIDATION_ERROR, Some(format!("unknown sequence style {:?}", s))))
+    }
+}
+
+impl YamlScalarStyle {
+    /// The name read back by `from_str`: `any`, `plain`, `single-quoted`, `double-quoted`,
+    /// `literal` or `folded`.
+    pub fn as_str(&self) -> &'static str {
+        SCALAR_STYLE_NAMES.iter().find(|&&(style, _)| style == *self).unwrap().1
+    }
+}
+
+impl FromStr for YamlScalarStyle {
+    type Err = YamlError;
+
+    fn from_str(s: &str) -> Result<YamlScalarStyle, YamlError> {
+        SCALAR_STYLE_NAMES.iter().find(|&&(_, name)| name == s).map(|&(style, _)| style)
+            .ok_or_else(|| YamlError::new(YamlErrorType::YAML_VALIDATION_ERROR, Some(format!("unknown scalar style {:?}", s))))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(C)]
 pub enum YamlEncoding {
@@ -224,18 +308,34 @@ pub struct yaml_parser_t {
     pub document: *const yaml_document_t,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 #[repr(C)]
-#[allow(non_camel_case_types)]
-pub enum yaml_break_t {
+pub enum YamlBreak {
     /** Let the parser choose the break type. */
-    YAML_ANY_BREAK,
+    YamlAnyBreak,
     /** Use CR for line breaks (Mac style). */
-    YAML_CR_BREAK,
+    YamlCrBreak,
     /** Use LN for line breaks (Unix style). */
-    YAML_LN_BREAK,
+    YamlLnBreak,
     /** Use CR LN for line breaks (DOS style). */
-    YAML_CRLN_BREAK
+    YamlCrLnBreak
+}
+
+/// The name `YamlBreak` had before, kept so existing code keeps building.
+#[allow(non_camel_case_types)]
+#[deprecated(note = "renamed to `YamlBreak`")]
+pub type yaml_break_t = YamlBreak;
+
+// the variants under their old names, so paths such as `yaml_break_t::YAML_LN_BREAK` still work
+impl YamlBreak {
+    #[deprecated(note = "renamed to `YamlBreak::YamlAnyBreak`")]
+    pub const YAML_ANY_BREAK: YamlBreak = YamlBreak::YamlAnyBreak;
+    #[deprecated(note = "renamed to `YamlBreak::YamlCrBreak`")]
+    pub const YAML_CR_BREAK: YamlBreak = YamlBreak::YamlCrBreak;
+    #[deprecated(note = "renamed to `YamlBreak::YamlLnBreak`")]
+    pub const YAML_LN_BREAK: YamlBreak = YamlBreak::YamlLnBreak;
+    #[deprecated(note = "renamed to `YamlBreak::YamlCrLnBreak`")]
+    pub const YAML_CRLN_BREAK: YamlBreak = YamlBreak::YamlCrLnBreak;
 }
 
 #[repr(C)]
@@ -256,7 +356,7 @@ pub struct yaml_emitter_t {
     pub best_indent: c_int,
     pub best_width: c_int,
     pub unicode: c_int,
-    pub line_break: yaml_break_t,
+    pub line_break: YamlBreak,
 
     pub states: yaml_stack_t,
     pub state: c_int,
@@ -439,11 +539,16 @@ extern {
         tag: *const yaml_char_t, style: YamlSequenceStyle) -> c_int;
     pub fn yaml_document_add_mapping(document: *mut yaml_document_t,
         tag: *const yaml_char_t, style: YamlSequenceStyle) -> c_int;
+    pub fn yaml_document_append_sequence_item(document: *mut yaml_document_t,
+        sequence: c_int, item: c_int) -> c_int;
+    pub fn yaml_document_append_mapping_pair(document: *mut yaml_document_t,
+        mapping: c_int, key: c_int, value: c_int) -> c_int;
     pub fn yaml_parser_initialize(parser: *mut yaml_parser_t) -> c_int;
     pub fn yaml_parser_set_encoding(parser: *mut yaml_parser_t, encoding: YamlEncoding) -> c_void;
     pub fn yaml_parser_delete(parser: *mut yaml_parser_t) -> c_void;
     pub fn yaml_parser_set_input_string(parser: *mut yaml_parser_t, input: *const yaml_char_t, size: size_t) -> c_void;
     pub fn yaml_parser_set_input(parser: *mut yaml_parser_t, handler: yaml_read_handler_t, data: *const c_void) -> c_void;
+    pub fn yaml_parser_set_input_file(parser: *mut yaml_parser_t, file: *mut FILE) -> c_void;
     pub fn yaml_parser_parse(parser: *mut yaml_parser_t, event: *mut yaml_event_t) -> c_int;
     pub fn yaml_parser_load(parser: *mut yaml_parser_t, document: *mut yaml_document_t) -> c_int;
     pub fn yaml_emitter_initialize(emitter: *mut yaml_emitter_t) -> c_int;
@@ -451,6 +556,11 @@ extern {
     pub fn yaml_emitter_delete(emitter: *mut yaml_emitter_t) -> c_void;
     pub fn yaml_emitter_set_output(emitter: *mut yaml_emitter_t, handler: yaml_write_handler_t, data: *const c_void) -> c_void;
     pub fn yaml_emitter_flush(emitter: *mut yaml_emitter_t) -> c_int;
+    pub fn yaml_emitter_set_width(emitter: *mut yaml_emitter_t, width: c_int) -> c_void;
+    pub fn yaml_emitter_set_indent(emitter: *mut yaml_emitter_t, indent: c_int) -> c_void;
+    pub fn yaml_emitter_set_canonical(emitter: *mut yaml_emitter_t, canonical: c_int) -> c_void;
+    pub fn yaml_emitter_set_unicode(emitter: *mut yaml_emitter_t, unicode: c_int) -> c_void;
+    pub fn yaml_emitter_set_break(emitter: *mut yaml_emitter_t, line_break: YamlBreak) -> c_void;
     pub fn yaml_stream_start_event_initialize(event: *mut yaml_event_t, encoding: YamlEncoding) -> c_int;
     pub fn yaml_stream_end_event_initialize(event: *mut yaml_event_t) -> c_int;
     pub fn yaml_document_start_event_initialize(event: *mut yaml_event_t,
@@ -474,3 +584,25 @@ extern {
         style: YamlSequenceStyle) -> c_int;
     pub fn yaml_mapping_end_event_initialize(event: *mut yaml_event_t) -> c_int;
 }
+
+#[cfg(test)]
+mod test {
+    use super::{YamlScalarStyle, YamlSequenceStyle, SCALAR_STYLE_NAMES, SEQUENCE_STYLE_NAMES};
+
+    #[test]
+    fn test_style_names() {
+        for &(style, name) in SCALAR_STYLE_NAMES.iter() {
+            assert_eq!(name, style.as_str());
+            assert_eq!(Ok(style), name.parse::<YamlScalarStyle>());
+        }
+        for &(style, name) in SEQUENCE_STYLE_NAMES.iter() {
+            assert_eq!(name, style.as_str());
+            assert_eq!(Ok(style), name.parse::<YamlSequenceStyle>());
+        }
+
+        assert_eq!("double-quoted", YamlScalarStyle::YamlDoubleQuotedScalarStyle.as_str());
+        let err = "Double-Quoted".parse::<YamlScalarStyle>().unwrap_err();
+        assert_eq!(Some("unknown scalar style \"Double-Quoted\"".to_string()), err.problem);
+        assert!("".parse::<YamlSequenceStyle>().is_err());
+    }
+}
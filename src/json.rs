@@ -0,0 +1,89 @@
+use document::{YamlDocument, YamlNode, YamlScalarData};
+
+use serde_json::{Map, Number, Value};
+
+/// Converts a node to JSON, resolving scalars as the `as_*` accessors of `YamlScalarData` do,
+/// so `true` is a boolean while `yes` stays a string.
+///
+/// Mapping keys become strings: a scalar key keeps its source text, so `1: a` and `true: a`
+/// give the keys `"1"` and `"true"`, while a collection key is written as compact JSON. When
+/// keys collide, the last entry wins. `.inf`, `-.inf` and `.nan` have no JSON form and become
+/// `null`, like a YAML null does. A decimal integer too large for an `isize` resolves to a float,
+/// as it matches the float pattern, while other oversized integers such as hex ones are kept as
+/// strings.
+pub fn to_json_value(node: &YamlNode) -> Value {
+    match *node {
+        YamlNode::YamlScalarNode(ref scalar) => from_scalar(scalar),
+        YamlNode::YamlSequenceNode(ref sequence) =>
+            Value::Array(sequence.values().map(|value| to_json_value(&value)).collect()),
+        YamlNode::YamlMappingNode(ref mapping) => {
+            let mut object = Map::new();
+            for (key, value) in mapping.pairs() {
+                let key = match key {
+                    YamlNode::YamlScalarNode(ref scalar) => scalar.get_value(),
+                    ref key => to_json_value(key).to_string()
+                };
+                object.insert(key, to_json_value(&value));
+            }
+            Value::Object(object)
+        }
+    }
+}
+
+impl YamlDocument {
+    /// The root node as JSON, see `to_json_value`; an empty document gives `null`.
+    pub fn to_json_value(&self) -> Value {
+        self.root().map_or(Value::Null, |root| to_json_value(&root))
+    }
+}
+
+// A scalar that does not fit its tag, such as `!!int x`, is kept as text.
+fn from_scalar(scalar: &YamlScalarData) -> Value {
+    if scalar.is_null() {
+        Value::Null
+    } else if let Some(b) = scalar.as_bool() {
+        Value::Bool(b)
+    } else if let Some(n) = scalar.as_i64() {
+        Value::Number(Number::from(n))
+    } else if let Some(f) = scalar.as_f64() {
+        Number::from_f64(f).map_or(Value::Null, Value::Number)
+    } else {
+        Value::String(scalar.get_value())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use json::to_json_value;
+    use parser::{YamlParser, YamlByteParser};
+    use ffi::YamlEncoding::YamlUtf8Encoding;
+    use document::YamlDocument;
+    use constructor::YamlStandardData;
+
+    fn convert(data: &str) -> String {
+        let parser = YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        to_json_value(&doc.root().unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        assert_eq!(r#"{"a":[1,-2.5,"x","yes",true,null],"b":"1"}"#, convert("a: [1, -2.5, x, yes, true, ~]\nb: '1'\n"));
+        assert_eq!(r#"[false,3,"x"]"#, convert("[!!bool no, !!int '3', !!int x]"));
+        assert_eq!("[0,16,null,null,null]", convert("[0, 0x10, .inf, -.inf, .nan]"));
+        assert_eq!(r#"{"1":"a","null":"b","true":"c"}"#, convert("1: a\nnull: b\ntrue: c\n"));
+        assert_eq!(r#"{"[1,2]":"a","{\"k\":null}":"b"}"#, convert("? [1, 2]\n: a\n? {k: }\n: b\n"));
+        assert_eq!(r#"{"a":2}"#, convert("{a: 1, a: 2}"));
+        assert_eq!(r#""multi\nline\n""#, convert("|\n  multi\n  line\n"));
+    }
+
+    #[test]
+    fn test_document_to_json_value() {
+        let parser = YamlByteParser::init(b"a: 1\n--- [99999999999999999999, 0x10000000000000000, -.inf]\n", YamlUtf8Encoding);
+        let json: Vec<String> = parser.load().map(|doc| doc.unwrap().to_json_value().to_string()).collect();
+        assert_eq!(vec![r#"{"a":1}"#.to_string(), r#"[1e+20,"0x10000000000000000",null]"#.to_string()], json);
+
+        let doc = YamlDocument::from_value(&YamlStandardData::YamlMapping(vec![])).unwrap();
+        assert_eq!("{}", doc.to_json_value().to_string());
+    }
+}
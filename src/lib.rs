@@ -5,13 +5,27 @@ extern crate libc;
 
 extern crate regex;
 
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+
 use std::str;
 use std::ffi::CStr;
 use std::io::Read;
 
-use parser::YamlParser;
+use parser::{YamlParser, YamlByteParser, YamlEventStream};
+use document::YamlDocument;
 use constructor::{YamlStandardData, YamlStandardConstructor, YamlConstructor};
-use error::YamlError;
+use error::{YamlError, YamlErrorContext};
+use event::YamlEventSpec;
+use comment::YamlSourceCursor;
 
 pub mod ffi;
 pub mod error;
@@ -20,7 +34,21 @@ pub mod parser;
 pub mod emitter;
 pub mod document;
 pub mod codecs;
+pub mod comment;
 pub mod constructor;
+pub mod shape;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "serde")]
+pub mod ser;
+
+pub use shape::{load_string_map, load_string_list};
+#[cfg(feature = "serde")]
+pub use de::{from_str, from_slice};
+#[cfg(feature = "serde")]
+pub use ser::{to_string, to_writer};
 
 mod type_size;
 
@@ -46,10 +74,28 @@ pub fn version() -> (isize, isize, isize) {
     (c_major as isize, c_minor as isize, c_patch as isize)
 }
 
+/// Parses every document of a UTF-8 stream. Input holding only whitespace and comments has no
+/// documents and gives an empty `Vec`, while an explicit empty document such as `---` gives
+/// `YamlNull`.
 pub fn parse_bytes_utf8(bytes: &[u8]) -> Result<Vec<YamlStandardData>, YamlError> {
     parse_bytes(bytes, ffi::YamlEncoding::YamlUtf8Encoding)
 }
 
+/// The events of a string, which is parsed as UTF-8.
+pub fn parse_str(input: &str) -> YamlEventStream<YamlByteParser<'_>> {
+    YamlByteParser::init(input.as_bytes(), ffi::YamlEncoding::YamlUtf8Encoding).parse()
+}
+
+/// Loads every document of a string without constructing them, unlike `parse_bytes_utf8`.
+pub fn load_str(input: &str) -> Result<Vec<Box<YamlDocument>>, YamlError> {
+    YamlByteParser::init(input.as_bytes(), ffi::YamlEncoding::YamlUtf8Encoding).load().collect()
+}
+
+/// Loads the only document of a string, with the errors of `parse_single_document`.
+pub fn load_single_str(input: &str) -> Result<Box<YamlDocument>, YamlError> {
+    load_single(input.as_bytes(), ffi::YamlEncoding::YamlUtf8Encoding)
+}
+
 pub fn parse_bytes(bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<Vec<YamlStandardData>, YamlError> {
     let parser = parser::YamlByteParser::init(bytes, encoding);
     let ctor = YamlStandardConstructor::new();
@@ -59,6 +105,64 @@ pub fn parse_bytes(bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<Vec<Yaml
     ).collect()
 }
 
+/// Parses input that must contain exactly one document.
+///
+/// Empty input, including input holding only whitespace and comments, and streams of several
+/// documents are rejected with a `YAML_COMPOSER_ERROR`.
+pub fn parse_single_document(bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<YamlStandardData, YamlError> {
+    let doc = load_single(bytes, encoding)?;
+    YamlStandardConstructor::new().construct(doc.root().unwrap())
+}
+
+fn load_single(bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<Box<YamlDocument>, YamlError> {
+    let parser = parser::YamlByteParser::init(bytes, encoding);
+    let mut docs = parser.load();
+    let doc = match docs.next() {
+        Some(res) => res?,
+        None => return Err(YamlError::new(ffi::YamlErrorType::YAML_COMPOSER_ERROR, Some("expected a single document but the input is empty".to_string())))
+    };
+    if let Some(res) = docs.next() {
+        res?;
+        return Err(YamlError::new(ffi::YamlErrorType::YAML_COMPOSER_ERROR, Some("expected a single document but found another".to_string())));
+    }
+
+    Ok(doc)
+}
+
+/// Like `parse_single_document`, but only whitespace and comments may follow the document.
+///
+/// Anything else after it, including another `---` or a stray `...` marker, is rejected with
+/// a `YAML_COMPOSER_ERROR` at the position where the trailing content starts.
+pub fn parse_single_document_strict(bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<YamlStandardData, YamlError> {
+    let parser = parser::YamlByteParser::init(bytes, encoding);
+    let mut events = parser.parse();
+    let stream_encoding = events.encoding()?;
+
+    for res in events {
+        let evt = res?;
+        if let YamlEventSpec::YamlDocumentEndEvent(_) = evt.spec {
+            let mut cursor = YamlSourceCursor::new(comment::decode_source(bytes, stream_encoding));
+            cursor.skip_to(evt.end.index);
+            if let Some(mark) = cursor.find_content() {
+                return Err(YamlError {
+                    kind: ffi::YamlErrorType::YAML_COMPOSER_ERROR,
+                    problem: Some("found trailing content after the document".to_string()),
+                    io_error: None,
+                    context: Some(YamlErrorContext {
+                        byte_offset: mark.index,
+                        problem_mark: mark,
+                        context: None,
+                        context_mark: mark
+                    })
+                });
+            }
+            break;
+        }
+    }
+
+    parse_single_document(bytes, encoding)
+}
+
 pub fn parse_io_utf8(reader: &mut Read) -> Result<Vec<YamlStandardData>, YamlError> {
     parse_io(reader, ffi::YamlEncoding::YamlUtf8Encoding)
 }
@@ -75,7 +179,8 @@ pub fn parse_io(reader: &mut Read, encoding: ffi::YamlEncoding) -> Result<Vec<Ya
 #[cfg(test)]
 mod test {
     use std::mem;
-    use std::io::BufReader;
+    use std::io;
+    use std::io::{BufReader, Read};
     use constructor::YamlStandardData::*;
 
     #[test]
@@ -127,4 +232,118 @@ mod test {
         let mut reader = BufReader::new(data.as_bytes());
         assert_eq!(Ok(vec![YamlSequence(vec![YamlInteger(1), YamlInteger(2), YamlInteger(3)])]), super::parse_io_utf8(&mut reader))
     }
+
+    // hands out one byte per read, so UTF-16 code units and surrogate pairs arrive split
+    struct ByteReader {
+        data: Vec<u8>,
+        pos: usize
+    }
+
+    impl Read for ByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pos == self.data.len() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.data[self.pos];
+            self.pos += 1;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_parse_utf16() {
+        use ffi::YamlEncoding::*;
+        use codecs;
+        use parser::{YamlParser, YamlByteParser};
+
+        let expected = Ok(vec![YamlSequence(vec![YamlInteger(1), YamlInteger(2), YamlInteger(3)])]);
+        let le: Vec<u8> = "[1, 2, 3]".encode_utf16().flat_map(|u| vec![u as u8, (u >> 8) as u8]).collect();
+        assert_eq!(expected, super::parse_bytes(&le, YamlUtf16LeEncoding));
+        for &encoding in [YamlUtf16LeEncoding, YamlUtf16BeEncoding].iter() {
+            let with_bom = codecs::encode_str("[1, 2, 3]", encoding);
+            assert_eq!(expected, super::parse_bytes(&with_bom, encoding));
+            assert_eq!(expected, super::parse_bytes(&with_bom, YamlAnyEncoding));
+        }
+
+        // libyaml hands out values as UTF-8 whatever the input encoding, and counts marks in characters
+        let data = codecs::encode_str("a: \u{1f600}x\nb: 'caf\u{e9}'\n", YamlUtf16LeEncoding);
+        let value = Ok(vec![YamlMapping(vec![(YamlString("a".to_string()), YamlString("\u{1f600}x".to_string())),
+                                             (YamlString("b".to_string()), YamlString("caf\u{e9}".to_string()))])]);
+        assert_eq!(value, super::parse_bytes(&data, YamlAnyEncoding));
+        assert_eq!(value, super::parse_io(&mut ByteReader { data: data.clone(), pos: 0 }, YamlAnyEncoding));
+
+        let mut events = YamlByteParser::init(&data, YamlAnyEncoding).parse_borrowed();
+        let mut scalars = Vec::new();
+        while let Some(res) = events.next_event() {
+            let evt = res.unwrap();
+            if let Some(value) = evt.scalar_str() {
+                scalars.push((value.to_string(), evt.start().index, evt.end().index));
+            }
+        }
+        assert_eq!(vec![("a".to_string(), 0, 1), ("\u{1f600}x".to_string(), 3, 5), ("b".to_string(), 6, 7),
+                        ("caf\u{e9}".to_string(), 9, 15)], scalars);
+    }
+
+    #[test]
+    fn test_parse_empty_input() {
+        use ffi::YamlEncoding::YamlUtf8Encoding;
+
+        for data in ["", "  \n\n", "# only a comment\n"].iter() {
+            assert_eq!(Ok(vec![]), super::parse_bytes_utf8(data.as_bytes()));
+            assert_eq!(Ok(vec![]), super::parse_io_utf8(&mut BufReader::new(data.as_bytes())));
+
+            let err = super::parse_single_document(data.as_bytes(), YamlUtf8Encoding).unwrap_err();
+            assert_eq!(super::ffi::YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+            assert_eq!(Some("expected a single document but the input is empty".to_string()), err.problem);
+            assert_eq!(err, super::parse_single_document_strict(data.as_bytes(), YamlUtf8Encoding).unwrap_err());
+            assert_eq!(err, data.parse::<super::YamlStandardData>().unwrap_err());
+        }
+
+        assert_eq!(Ok(vec![YamlNull]), super::parse_bytes_utf8(b"---\n"));
+        assert_eq!(Ok(YamlNull), super::parse_single_document(b"--- # empty\n", YamlUtf8Encoding));
+    }
+
+    #[test]
+    fn test_load_str() {
+        use document::YamlNode;
+        use event::YamlEventSpec::YamlScalarEvent;
+
+        let scalars: Vec<String> = super::parse_str("a: 1").filter_map(|res| match res.unwrap().spec {
+            YamlScalarEvent(param) => Some(param.value),
+            _ => None
+        }).collect();
+        assert_eq!(vec!["a".to_string(), "1".to_string()], scalars);
+
+        let docs = super::load_str("a: 1\n--- [b]\n").unwrap();
+        assert_eq!(2, docs.len());
+        assert!(match docs[1].root() { Some(YamlNode::YamlSequenceNode(_)) => true, _ => false });
+        assert_eq!(0, super::load_str("# empty\n").unwrap().len());
+        assert!(super::load_str("a: [").is_err());
+
+        assert!(super::load_single_str("a: 1\n").is_ok());
+        for &(data, problem) in [("", "expected a single document but the input is empty"),
+                                 ("a\n--- b\n", "expected a single document but found another")].iter() {
+            match super::load_single_str(data) {
+                Err(err) => assert_eq!(Some(problem.to_string()), err.problem),
+                Ok(_) => panic!("expected an error for {:?}", data)
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_single_document_strict() {
+        use ffi::YamlEncoding::YamlUtf8Encoding;
+
+        for data in ["a: 1\n", "a: 1\n...\n# done\n", "# head\n--- [1]\n... # tail\n"].iter() {
+            assert!(super::parse_single_document_strict(data.as_bytes(), YamlUtf8Encoding).is_ok(), "{:?}", data);
+        }
+
+        let marks: Vec<(usize, usize)> = ["a: 1\n...\n...\n", "a: 1\n--- b\n", "a: 1\n...\n  # c\n  ---\n"].iter().map(|data| {
+            let err = super::parse_single_document_strict(data.as_bytes(), YamlUtf8Encoding).unwrap_err();
+            assert_eq!(Some("found trailing content after the document".to_string()), err.problem);
+            let mark = err.context.unwrap().problem_mark;
+            (mark.line, mark.column)
+        }).collect();
+        assert_eq!(vec![(2, 0), (1, 0), (3, 2)], marks);
+    }
 }
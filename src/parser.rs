@@ -1,60 +1,349 @@
 use libc;
 
 use ffi;
+use ffi::yaml_event_type_t::*;
 use error::{YamlError, YamlErrorContext, YamlMark};
-use event::{YamlEvent, YamlEventSpec};
-use document::{YamlDocument};
+use event::{YamlBorrowedEvent, YamlEvent, YamlEventSpec};
+use document::{YamlDocument, YamlNode, YamlNodeData};
+use comment::YamlCommentedEventStream;
+use constructor::{YamlStandardData, YamlStandardConstructor, YamlTagResolver};
 use codecs;
 
 use std::mem;
+use std::mem::MaybeUninit;
+use std::collections::HashMap;
 use std::io;
 use std::io::Read;
+use std::iter::FusedIterator;
+use std::ffi::CString;
+use std::ops::Range;
+use std::path::Path;
+use std::ptr;
 use std::slice;
-use std::marker::PhantomData;
+use std::str;
 
+/// Events of the input, ending after STREAM-END or the first error.
+///
+/// libyaml leaves the parser in an error state once it fails, so after an error the stream
+/// only returns `None` rather than polling it again.
 pub struct YamlEventStream<P> {
     parser: Box<P>,
+    encoding: Option<ffi::YamlEncoding>,
+    finished: bool,
+}
+
+impl<P:YamlParser> YamlEventStream<P> {
+    /// Whether a byte order mark at the start of the input was skipped.
+    ///
+    /// A `YamlIoParser` does not read its input before the first event, so this is only
+    /// meaningful once the STREAM-START event has been returned.
+    pub fn bom_consumed(&self) -> bool {
+        self.parser.bom_consumed()
+    }
+
+    /// Returns the encoding reported by the STREAM-START event.
+    ///
+    /// If the stream has not been started yet, the STREAM-START event is consumed
+    /// and iteration continues from the event that follows it.
+    pub fn encoding(&mut self) -> Result<ffi::YamlEncoding, YamlError> {
+        if let Some(encoding) = self.encoding {
+            return Ok(encoding);
+        }
+
+        match self.next() {
+            Some(Err(err)) => Err(err),
+            _ => self.encoding.ok_or_else(|| YamlError::new(
+                    ffi::YamlErrorType::YAML_PARSER_ERROR,
+                    Some("did not find expected <stream-start>".to_string())
+                ))
+        }
+    }
 }
 
 impl<P:YamlParser> Iterator for YamlEventStream<P> {
     type Item = Result<YamlEvent, YamlError>;
 
     fn next(&mut self) -> Option<Result<YamlEvent, YamlError>> {
+        if self.finished {
+            return None;
+        }
         unsafe {
             match self.parser.parse_event() {
                 Some(evt) => match evt.spec {
-                    YamlEventSpec::YamlNoEvent => None,
+                    YamlEventSpec::YamlNoEvent => {
+                        self.finished = true;
+                        None
+                    },
+                    YamlEventSpec::YamlStreamStartEvent(encoding) => {
+                        self.encoding = Some(encoding);
+                        Some(Ok(evt))
+                    },
                     _ => Some(Ok(evt))
                 },
+                None => {
+                    self.finished = true;
+                    Some(Err(self.parser.get_error()))
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            (0, Some(0))
+        } else {
+            (0, None)
+        }
+    }
+}
+
+impl<P:YamlParser> FusedIterator for YamlEventStream<P> {}
+
+impl<P:YamlParser> YamlEventStream<P> {
+    /// The alias, scalar, sequence and mapping events and any errors, without the stream and
+    /// document events around them.
+    pub fn content_events(self) -> impl Iterator<Item = Result<YamlEvent, YamlError>> {
+        self.filter(|res| match *res {
+            Ok(ref evt) => !matches!(evt.spec, YamlEventSpec::YamlStreamStartEvent(..)
+                | YamlEventSpec::YamlStreamEndEvent
+                | YamlEventSpec::YamlDocumentStartEvent(..)
+                | YamlEventSpec::YamlDocumentEndEvent(..)),
+            Err(_) => true
+        })
+    }
+
+    pub fn into_peekable(self) -> YamlPeekableEventStream<P> {
+        YamlPeekableEventStream {
+            stream: self,
+            peeked: None
+        }
+    }
+}
+
+/// Event stream with one event of lookahead.
+///
+/// Unlike `std::iter::Peekable`, `peek` hands out the cached `Result`, so an error seen while
+/// peeking is still returned by the following `next`.
+pub struct YamlPeekableEventStream<P> {
+    stream: YamlEventStream<P>,
+    peeked: Option<Option<Result<YamlEvent, YamlError>>>
+}
+
+impl<P:YamlParser> YamlPeekableEventStream<P> {
+    pub fn peek(&mut self) -> Option<&Result<YamlEvent, YamlError>> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.stream.next());
+        }
+        self.peeked.as_ref().and_then(|peeked| peeked.as_ref())
+    }
+
+    pub fn bom_consumed(&self) -> bool {
+        self.stream.bom_consumed()
+    }
+}
+
+impl<P:YamlParser> Iterator for YamlPeekableEventStream<P> {
+    type Item = Result<YamlEvent, YamlError>;
+
+    fn next(&mut self) -> Option<Result<YamlEvent, YamlError>> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.stream.next()
+        }
+    }
+}
+
+/// Events borrowed from the parser, so that scalar values are not copied into `String`s.
+///
+/// Each event is only valid until the following call to `next_event`, which is why this is not
+/// an `Iterator`:
+///
+/// ```ignore
+/// let mut events = parser.parse_borrowed();
+/// while let Some(res) = events.next_event() {
+///     if let Some(value) = res?.scalar_str() {
+///         total += value.len();
+///     }
+/// }
+/// ```
+pub struct YamlBorrowedEventStream<P> {
+    parser: Box<P>,
+    event: Option<InternalEvent>
+}
+
+impl<P:YamlParser> YamlBorrowedEventStream<P> {
+    pub fn next_event(&mut self) -> Option<Result<YamlBorrowedEvent<'_>, YamlError>> {
+        self.event = None;
+        unsafe {
+            match self.parser.parse_internal_event() {
+                Some(event) => if event.event_mem.event_type == ffi::yaml_event_type_t::YAML_NO_EVENT {
+                    None
+                } else {
+                    let event = self.event.insert(event);
+                    Some(Ok(YamlBorrowedEvent::new(&event.event_mem)))
+                },
                 None => Some(Err(self.parser.get_error()))
             }
         }
     }
 }
 
+/// Documents of the input loaded one at a time, ending after the last one or the first parse
+/// error.
+///
+/// Each document holds its own `yaml_document_t`, which is deleted when the document is dropped,
+/// so a caller that drops every document before asking for the next never holds more than one.
+/// As with `YamlEventStream`, the parser is not polled again after it fails, but a document
+/// failing the checks of `with_limits` or `reject_duplicate_keys` does not end the stream.
 pub struct YamlDocumentStream<P> {
     parser: Box<P>,
+    limits: Option<YamlParserLimits>,
+    reject_duplicate_keys: bool,
+    finished: bool
 }
 
 impl<P:YamlParser> Iterator for YamlDocumentStream<P> {
     type Item = Result<Box<YamlDocument>, YamlError>;
 
     fn next(&mut self) -> Option<Result<Box<YamlDocument>, YamlError>> {
+        if self.finished {
+            return None;
+        }
         unsafe {
-            match YamlDocument::parser_load(&mut self.parser.base_parser_ref().parser_mem) {
+            match self.parser.base_parser_ref().load_document() {
                 Some(doc) => if doc.is_empty() {
+                    self.finished = true;
                     None
                 } else {
-                    Some(Ok(doc))
+                    Some(self.check(&doc).map(|_| doc))
                 },
-                None => Some(Err(self.parser.get_error()))
+                None => {
+                    self.finished = true;
+                    Some(Err(self.parser.get_error()))
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.finished {
+            (0, Some(0))
+        } else {
+            (0, None)
+        }
+    }
+}
+
+impl<P:YamlParser> FusedIterator for YamlDocumentStream<P> {}
+
+impl<P:YamlParser> YamlDocumentStream<P> {
+    /// Checks every document against `limits` with `YamlDocument::check_limits`, yielding the
+    /// error in place of a document that exceeds them. Later documents are still loaded.
+    pub fn with_limits(mut self, limits: YamlParserLimits) -> YamlDocumentStream<P> {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Yields the error of `YamlDocument::check_duplicate_keys` in place of a document where a
+    /// mapping holds a key twice.
+    pub fn reject_duplicate_keys(mut self, reject: bool) -> YamlDocumentStream<P> {
+        self.reject_duplicate_keys = reject;
+        self
+    }
+
+    fn check(&self, doc: &YamlDocument) -> Result<(), YamlError> {
+        if let Some(ref limits) = self.limits {
+            doc.check_limits(limits)?;
+        }
+        if self.reject_duplicate_keys {
+            doc.check_duplicate_keys()?;
+        }
+        Ok(())
+    }
+
+    /// Yields documents until one matches `pred`, which is consumed but not yielded. Nothing
+    /// after that sentinel document is read, so the stream may continue with other content.
+    pub fn take_until<F>(self, pred: F) -> YamlDocumentsUntil<P, F>
+        where F: Fn(&YamlDocument) -> bool
+    {
+        YamlDocumentsUntil {
+            documents: self,
+            pred,
+            sentinel_end: None,
+            done: false
+        }
+    }
+
+    /// Calls `cb` with each document as it is loaded, before it is yielded. Documents that
+    /// fail to load are yielded as errors without calling it.
+    pub fn on_document<F>(self, cb: F) -> YamlDocumentsInspected<P, F>
+        where F: FnMut(&YamlDocument)
+    {
+        YamlDocumentsInspected {
+            documents: self,
+            cb
+        }
+    }
+}
+
+pub struct YamlDocumentsInspected<P, F> {
+    documents: YamlDocumentStream<P>,
+    cb: F,
+}
+
+impl<P:YamlParser, F:FnMut(&YamlDocument)> Iterator for YamlDocumentsInspected<P, F> {
+    type Item = Result<Box<YamlDocument>, YamlError>;
+
+    fn next(&mut self) -> Option<Result<Box<YamlDocument>, YamlError>> {
+        let res = self.documents.next();
+        if let Some(Ok(ref doc)) = res {
+            (self.cb)(doc);
+        }
+        res
+    }
+}
+
+pub struct YamlDocumentsUntil<P, F> {
+    documents: YamlDocumentStream<P>,
+    pred: F,
+    sentinel_end: Option<YamlMark>,
+    done: bool,
+}
+
+impl<P:YamlParser, F> YamlDocumentsUntil<P, F> {
+    /// The end of the sentinel document, once it has been found. Its `index` counts characters
+    /// from the start of the input, so it equals a byte offset only for ASCII input.
+    pub fn sentinel_end(&self) -> Option<YamlMark> {
+        self.sentinel_end
+    }
+}
+
+impl<P:YamlParser, F:Fn(&YamlDocument) -> bool> Iterator for YamlDocumentsUntil<P, F> {
+    type Item = Result<Box<YamlDocument>, YamlError>;
+
+    fn next(&mut self) -> Option<Result<Box<YamlDocument>, YamlError>> {
+        if self.done {
+            return None;
+        }
+
+        match self.documents.next() {
+            Some(Ok(doc)) => if (self.pred)(&doc) {
+                self.sentinel_end = Some(doc.end_mark());
+                self.done = true;
+                None
+            } else {
+                Some(Ok(doc))
+            },
+            res => {
+                self.done = true;
+                res
             }
         }
     }
 }
 
 pub struct InternalEvent {
-    event_mem: ffi::yaml_event_t
+    pub(crate) event_mem: ffi::yaml_event_t
 }
 
 impl Drop for InternalEvent {
@@ -65,32 +354,102 @@ impl Drop for InternalEvent {
     }
 }
 
-pub trait YamlParser: Sized {
-    unsafe fn base_parser_ref<'r>(&'r mut self) -> &'r mut YamlBaseParser;
-    unsafe fn get_error(&mut self) -> YamlError;
+mod sealed {
+    use error::YamlError;
+    use event::YamlEvent;
+    use super::{YamlBaseParser, InternalEvent};
 
-    unsafe fn parse_event(&mut self) -> Option<YamlEvent> {
-        let mut event = InternalEvent {
-            event_mem: mem::uninitialized()
-        };
+    use std::mem::MaybeUninit;
 
-        if !self.base_parser_ref().parse(&mut event.event_mem) {
-            None
-        } else {
-            Some(YamlEvent::load(&event.event_mem))
+    /// The unsafe part of `YamlParser`, which only this crate's parsers implement.
+    pub trait YamlParserBase {
+        unsafe fn base_parser_ref(&mut self) -> &mut YamlBaseParser;
+        unsafe fn get_error(&mut self) -> YamlError;
+
+        unsafe fn parse_internal_event(&mut self) -> Option<InternalEvent> {
+            let mut event_mem = MaybeUninit::uninit();
+
+            if !self.base_parser_ref().parse(event_mem.as_mut_ptr()) {
+                None
+            } else {
+                Some(InternalEvent {
+                    event_mem: event_mem.assume_init()
+                })
+            }
         }
+
+        unsafe fn parse_event(&mut self) -> Option<YamlEvent> {
+            self.parse_internal_event().map(|event| YamlEvent::load(&event.event_mem))
+        }
+    }
+}
+
+use self::sealed::YamlParserBase;
+
+/// Implemented by `YamlByteParser`, `YamlOwnedParser`, `YamlFileParser` and `YamlIoParser`; it is
+/// sealed, so other types cannot implement it, and its event-level access is only exposed through
+/// `parse` and `load`.
+pub trait YamlParser: YamlParserBase + Sized {
+    /// Whether a byte order mark at the start of the input was skipped. The default is `false`,
+    /// for parsers that do not check for one.
+    fn bom_consumed(&self) -> bool {
+        false
     }
 
     fn parse(self: Box<Self>) -> YamlEventStream<Self> {
         YamlEventStream {
             parser: self,
+            encoding: None,
+            finished: false,
+        }
+    }
+
+    /// Like `parse`, but scalar values are borrowed from the parser until the next event.
+    fn parse_borrowed(self: Box<Self>) -> YamlBorrowedEventStream<Self> {
+        YamlBorrowedEventStream {
+            parser: self,
+            event: None
+        }
+    }
+
+    /// Makes `parse` and `parse_borrowed` fail with a `YAML_LIMIT_ERROR` at the first collection
+    /// nested more than `max_depth` deep, after which they end. `load` leaves composing documents
+    /// to libyaml, so use `YamlDocumentStream::with_limits` to limit the depth of documents.
+    fn with_max_depth(mut self: Box<Self>, max_depth: usize) -> Box<Self> {
+        unsafe {
+            self.base_parser_ref().max_depth = Some(max_depth);
+        }
+        self
+    }
+
+    /// Tags the untagged scalars of documents from `load` with `resolver`, for example to read
+    /// every plain scalar as a string or to recognize an application type. Events from `parse`
+    /// keep the tags of the source.
+    fn with_tag_resolver<R: YamlTagResolver + 'static>(mut self: Box<Self>, resolver: R) -> Box<Self> {
+        unsafe {
+            self.base_parser_ref().tag_resolver = Some(Box::new(resolver));
         }
+        self
     }
 
     fn load(self: Box<Self>) -> YamlDocumentStream<Self> {
         YamlDocumentStream {
             parser: self,
+            limits: None,
+            reject_duplicate_keys: false,
+            finished: false
+        }
+    }
+
+    /// Calls `f` with each document, dropping it before the next one is loaded, and stops at
+    /// the first error.
+    fn for_each_document<F>(self: Box<Self>, mut f: F) -> Result<(), YamlError>
+        where F: FnMut(&YamlDocument)
+    {
+        for doc in self.load() {
+            f(&*doc?);
         }
+        Ok(())
     }
 }
 
@@ -98,7 +457,19 @@ extern fn handle_reader_cb(data: *mut YamlIoParser, buffer: *mut u8, size: libc:
     unsafe {
         let buf = slice::from_raw_parts_mut(buffer, size as usize);
         let parser = &mut *data;
-        match parser.reader.read(buf) {
+        let result = if parser.head_read {
+            // an interrupted read is retried, as io::Read::read_exact does
+            loop {
+                match parser.reader.read(buf) {
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                    result => break result
+                }
+            }
+        } else {
+            parser.head_read = true;
+            parser.read_head(buf)
+        };
+        match result {
             Ok(size) => {
                 *size_read = size as libc::size_t;
                 return 1;
@@ -112,39 +483,115 @@ extern fn handle_reader_cb(data: *mut YamlIoParser, buffer: *mut u8, size: libc:
 }
 
 pub struct YamlBaseParser {
-    parser_mem: ffi::yaml_parser_t,
+    parser_mem: MaybeUninit<ffi::yaml_parser_t>,
+    // whether yaml_parser_initialize succeeded, so that dropping may delete the parser
+    initialized: bool,
+    bom_consumed: bool,
+    max_depth: Option<usize>,
+    depth: usize,
+    // where the collection nested too deep starts, once one has
+    depth_exceeded: Option<YamlMark>,
+    tag_resolver: Option<Box<dyn YamlTagResolver>>,
 }
 
 impl YamlBaseParser {
     unsafe fn new() -> YamlBaseParser {
         YamlBaseParser {
-            parser_mem: mem::uninitialized()
+            parser_mem: MaybeUninit::uninit(),
+            initialized: false,
+            bom_consumed: false,
+            max_depth: None,
+            depth: 0,
+            depth_exceeded: None,
+            tag_resolver: None
+        }
+    }
+
+    unsafe fn initialize(&mut self) -> Result<(), YamlError> {
+        self.initialized = ffi::yaml_parser_initialize(self.parser_mem.as_mut_ptr()) != 0;
+        if self.initialized {
+            Ok(())
+        } else {
+            Err(YamlError::new(ffi::YamlErrorType::YAML_INITIALIZATION_ERROR,
+                               Some("failed to initialize yaml_parser_t".to_string())))
         }
     }
 
-    unsafe fn initialize(&mut self) -> bool {
-        ffi::yaml_parser_initialize(&mut self.parser_mem) != 0
+    // Only valid once `initialize` has succeeded.
+    unsafe fn parser_mem(&mut self) -> &mut ffi::yaml_parser_t {
+        self.parser_mem.assume_init_mut()
+    }
+
+    unsafe fn load_document(&mut self) -> Option<Box<YamlDocument>> {
+        YamlDocument::parser_load(self.parser_mem.assume_init_mut(), self.tag_resolver.as_deref())
     }
 
     unsafe fn set_input_string(&mut self, input: *const u8, size: usize) {
-        ffi::yaml_parser_set_input_string(&mut self.parser_mem, input, size as libc::size_t);
+        ffi::yaml_parser_set_input_string(self.parser_mem(), input, size as libc::size_t);
+    }
+
+    // Initializes the parser to read `bytes` in place, which must outlive it.
+    unsafe fn init_bytes(&mut self, bytes: &[u8], encoding: ffi::YamlEncoding) -> Result<(), YamlError> {
+        self.initialize()?;
+
+        ffi::yaml_parser_set_encoding(self.parser_mem(), encoding);
+
+        // libyaml only skips a BOM while detecting the encoding itself, so with an explicit
+        // encoding it would be read as a character and shift the first line by a column
+        let bom = codecs::bom(encoding);
+        let input = if !bom.is_empty() && bytes.starts_with(bom) {
+            self.parser_mem().offset = bom.len() as libc::size_t;
+            self.bom_consumed = true;
+            &bytes[bom.len()..]
+        } else {
+            self.bom_consumed = encoding == ffi::YamlEncoding::YamlAnyEncoding
+                && codecs::detect_bom(bytes).is_some();
+            bytes
+        };
+        self.set_input_string(input.as_ptr(), input.len());
+        Ok(())
     }
 
-    unsafe fn parse(&mut self, event: &mut ffi::yaml_event_t) -> bool {
-        ffi::yaml_parser_parse(&mut self.parser_mem, event) != 0
+    unsafe fn parse(&mut self, event: *mut ffi::yaml_event_t) -> bool {
+        // like libyaml after an error, give empty events once the depth error was reported
+        if self.depth_exceeded.is_some() {
+            ptr::write_bytes(event, 0, 1);
+            return true;
+        }
+        if ffi::yaml_parser_parse(self.parser_mem(), event) == 0 {
+            return false;
+        }
+
+        match (*event).event_type {
+            YAML_SEQUENCE_START_EVENT | YAML_MAPPING_START_EVENT => {
+                self.depth += 1;
+                if self.max_depth.is_some_and(|max_depth| self.depth > max_depth) {
+                    self.depth_exceeded = Some(YamlMark::conv(&(*event).start_mark));
+                    (*event).delete();
+                    return false;
+                }
+            },
+            YAML_SEQUENCE_END_EVENT | YAML_MAPPING_END_EVENT => self.depth -= 1,
+            _ => ()
+        }
+        true
     }
 
     unsafe fn build_error(&self) -> YamlError {
+        if let (Some(max_depth), Some(mark)) = (self.max_depth, self.depth_exceeded) {
+            return limit_error(format!("input exceeds the maximum nesting depth of {}", max_depth), &mark);
+        }
+        let parser_mem = self.parser_mem.assume_init_ref();
         let context = YamlErrorContext {
-            byte_offset: self.parser_mem.problem_offset as usize,
-            problem_mark: YamlMark::conv(&self.parser_mem.problem_mark),
-            context: codecs::decode_c_str(self.parser_mem.context as *const ffi::yaml_char_t),
-            context_mark: YamlMark::conv(&self.parser_mem.context_mark),
+            byte_offset: parser_mem.problem_offset,
+            problem_mark: YamlMark::conv(&parser_mem.problem_mark),
+            context: codecs::decode_c_str(parser_mem.context as *const ffi::yaml_char_t),
+            context_mark: YamlMark::conv(&parser_mem.context_mark),
         };
 
         YamlError {
-            kind: self.parser_mem.error,
-            problem: codecs::decode_c_str(self.parser_mem.problem as *const ffi::yaml_char_t),
+            kind: parser_mem.error,
+            problem: codecs::decode_c_str(parser_mem.problem as *const ffi::yaml_char_t),
             io_error: None,
             context: Some(context)
         }
@@ -153,18 +600,32 @@ impl YamlBaseParser {
 
 impl Drop for YamlBaseParser {
     fn drop(&mut self) {
-        unsafe {
-            ffi::yaml_parser_delete(&mut self.parser_mem);
+        if self.initialized {
+            unsafe {
+                ffi::yaml_parser_delete(self.parser_mem.as_mut_ptr());
+            }
         }
     }
 }
 
+/// Parser over a borrowed byte slice, which libyaml reads in place without copying.
+///
+/// Any slice works, including a memory-mapped file. Parsed events and documents own their
+/// data, so the mapping only has to outlive the parser:
+///
+/// ```ignore
+/// let file = File::open("large.yml")?;
+/// let mmap = unsafe { memmap2::Mmap::map(&file)? };
+/// let parser = YamlByteParser::init(&mmap[..], YamlEncoding::YamlAnyEncoding);
+/// let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>()?;
+/// drop(mmap);
+/// ```
 pub struct YamlByteParser<'r> {
     base_parser: YamlBaseParser,
-    data: PhantomData<&'r [u8]>
+    data: &'r [u8]
 }
 
-impl<'r> YamlParser for YamlByteParser<'r> {
+impl<'r> YamlParserBase for YamlByteParser<'r> {
     unsafe fn base_parser_ref<'a>(&'a mut self) -> &'a mut YamlBaseParser {
         &mut self.base_parser
     }
@@ -172,24 +633,179 @@ impl<'r> YamlParser for YamlByteParser<'r> {
     unsafe fn get_error(&mut self) -> YamlError {
         self.base_parser.build_error()
     }
+
+}
+
+impl<'r> YamlParser for YamlByteParser<'r> {
+    fn bom_consumed(&self) -> bool {
+        self.base_parser.bom_consumed
+    }
 }
 
 impl<'r> YamlByteParser<'r> {
+    /// Panics where `try_init` would fail.
     pub fn init(bytes: &'r [u8], encoding: ffi::YamlEncoding) -> Box<YamlByteParser<'r>> {
+        match YamlByteParser::try_init(bytes, encoding) {
+            Ok(parser) => parser,
+            Err(_) => panic!("failed to initialize yaml_parser_t")
+        }
+    }
+
+    /// Fails with a `YAML_INITIALIZATION_ERROR` when libyaml cannot allocate the parser.
+    pub fn try_init(bytes: &'r [u8], encoding: ffi::YamlEncoding) -> Result<Box<YamlByteParser<'r>>, YamlError> {
         unsafe {
             let mut parser = Box::new(YamlByteParser {
                 base_parser: YamlBaseParser::new(),
-                data: PhantomData
+                data: bytes
+            });
+
+            parser.base_parser.init_bytes(bytes, encoding)?;
+
+            Ok(parser)
+        }
+    }
+
+    /// Parses `bytes` in the encoding its byte order mark names, or as UTF-8 if it has none.
+    /// The mark is skipped as `init` skips it, so marks of the content do not count it.
+    pub fn init_autodetect(bytes: &'r [u8]) -> Box<YamlByteParser<'r>> {
+        let encoding = codecs::detect_bom(bytes).unwrap_or(ffi::YamlEncoding::YamlUtf8Encoding);
+        YamlByteParser::init(bytes, encoding)
+    }
+
+    pub fn parse_with_comments(self: Box<Self>) -> YamlCommentedEventStream<'r> {
+        let bytes = self.data;
+        YamlCommentedEventStream::new(self, bytes)
+    }
+}
+
+/// Parser that owns its input, for bytes that would not outlive a `YamlByteParser`, such as a
+/// `String` built for the call.
+pub struct YamlOwnedParser {
+    base_parser: YamlBaseParser,
+    // libyaml keeps a pointer into this buffer, which a boxed slice never reallocates
+    data: Box<[u8]>
+}
+
+impl YamlParserBase for YamlOwnedParser {
+    unsafe fn base_parser_ref(&mut self) -> &mut YamlBaseParser {
+        &mut self.base_parser
+    }
+
+    unsafe fn get_error(&mut self) -> YamlError {
+        self.base_parser.build_error()
+    }
+}
+
+impl YamlParser for YamlOwnedParser {
+    fn bom_consumed(&self) -> bool {
+        self.base_parser.bom_consumed
+    }
+}
+
+impl YamlOwnedParser {
+    /// Panics where `try_init` would fail.
+    pub fn init(bytes: Vec<u8>, encoding: ffi::YamlEncoding) -> Box<YamlOwnedParser> {
+        match YamlOwnedParser::try_init(bytes, encoding) {
+            Ok(parser) => parser,
+            Err(_) => panic!("failed to initialize yaml_parser_t")
+        }
+    }
+
+    /// Fails with a `YAML_INITIALIZATION_ERROR` when libyaml cannot allocate the parser.
+    pub fn try_init(bytes: Vec<u8>, encoding: ffi::YamlEncoding) -> Result<Box<YamlOwnedParser>, YamlError> {
+        unsafe {
+            let mut parser = Box::new(YamlOwnedParser {
+                base_parser: YamlBaseParser::new(),
+                data: bytes.into_boxed_slice()
+            });
+
+            let parser_ref = &mut *parser;
+            parser_ref.base_parser.init_bytes(&parser_ref.data, encoding)?;
+
+            Ok(parser)
+        }
+    }
+}
+
+/// Parser reading a file through libyaml's own `FILE*` input, without a Rust read callback.
+///
+/// Like a `YamlIoParser`, it skips a byte order mark naming the given encoding, which libyaml
+/// would otherwise read as a character.
+pub struct YamlFileParser {
+    base_parser: YamlBaseParser,
+    file: *mut libc::FILE
+}
+
+impl YamlParserBase for YamlFileParser {
+    unsafe fn base_parser_ref(&mut self) -> &mut YamlBaseParser {
+        &mut self.base_parser
+    }
+
+    unsafe fn get_error(&mut self) -> YamlError {
+        self.base_parser.build_error()
+    }
+}
+
+impl YamlParser for YamlFileParser {
+    fn bom_consumed(&self) -> bool {
+        self.base_parser.bom_consumed
+    }
+}
+
+impl YamlFileParser {
+    /// Opens `path` for reading, failing with a `YAML_READER_ERROR` holding the `io_error`, or
+    /// with a `YAML_INITIALIZATION_ERROR` when libyaml cannot allocate the parser.
+    pub fn init(path: &Path, encoding: ffi::YamlEncoding) -> Result<Box<YamlFileParser>, YamlError> {
+        let open_error = |err: io::Error| YamlError {
+            kind: ffi::YamlErrorType::YAML_READER_ERROR,
+            problem: Some(format!("cannot open {}", path.display())),
+            io_error: Some(err),
+            context: None
+        };
+        let c_path = path.to_str().and_then(|path| CString::new(path).ok())
+            .ok_or_else(|| open_error(io::Error::new(io::ErrorKind::InvalidInput, "path is not valid UTF-8 without NUL bytes")))?;
+
+        unsafe {
+            let file = libc::fopen(c_path.as_ptr(), b"rb\0".as_ptr() as *const libc::c_char);
+            if file.is_null() {
+                return Err(open_error(io::Error::last_os_error()));
+            }
+            let mut parser = Box::new(YamlFileParser {
+                base_parser: YamlBaseParser::new(),
+                file
             });
 
-            if !parser.base_parser.initialize() {
-                panic!("failed to initialize yaml_parser_t");
+            parser.base_parser.initialize()?;
+            ffi::yaml_parser_set_encoding(parser.base_parser.parser_mem(), encoding);
+
+            // peek at the head for a BOM, then start over or just past the one to skip
+            let mut head = [0u8; 3];
+            let read = libc::fread(head.as_mut_ptr() as *mut libc::c_void, 1, head.len(), file);
+            let head = &head[..read];
+            let bom = codecs::bom(encoding);
+            let skip = if !bom.is_empty() && head.starts_with(bom) {
+                parser.base_parser.parser_mem().offset = bom.len() as libc::size_t;
+                parser.base_parser.bom_consumed = true;
+                bom.len()
+            } else {
+                parser.base_parser.bom_consumed = encoding == ffi::YamlEncoding::YamlAnyEncoding
+                    && codecs::detect_bom(head).is_some();
+                0
+            };
+            if libc::fseek(file, skip as libc::c_long, libc::SEEK_SET) != 0 {
+                return Err(open_error(io::Error::last_os_error()));
             }
+            ffi::yaml_parser_set_input_file(parser.base_parser.parser_mem(), file);
 
-            ffi::yaml_parser_set_encoding(&mut parser.base_parser.parser_mem, encoding);
-            parser.base_parser.set_input_string(bytes.as_ptr(), bytes.len());
+            Ok(parser)
+        }
+    }
+}
 
-            parser
+impl Drop for YamlFileParser {
+    fn drop(&mut self) {
+        unsafe {
+            libc::fclose(self.file);
         }
     }
 }
@@ -198,9 +814,12 @@ pub struct YamlIoParser<'r> {
     base_parser: YamlBaseParser,
     reader: &'r mut (Read+'r),
     io_error: Option<io::Error>,
+    encoding: ffi::YamlEncoding,
+    // whether the first read, which checks for a BOM as in YamlByteParser::init, is done
+    head_read: bool,
 }
 
-impl<'r> YamlParser for YamlIoParser<'r> {
+impl<'r> YamlParserBase for YamlIoParser<'r> {
     unsafe fn base_parser_ref<'a>(&'a mut self) -> &'a mut YamlBaseParser {
         &mut self.base_parser
     }
@@ -210,55 +829,856 @@ impl<'r> YamlParser for YamlIoParser<'r> {
         mem::swap(&mut (error.io_error), &mut (self.io_error));
         return error;
     }
+
+}
+
+impl<'r> YamlParser for YamlIoParser<'r> {
+    fn bom_consumed(&self) -> bool {
+        self.base_parser.bom_consumed
+    }
 }
 
 impl<'r> YamlIoParser<'r> {
+    /// Panics where `try_init` would fail.
     pub fn init<'a>(reader: &'a mut Read, encoding: ffi::YamlEncoding) -> Box<YamlIoParser<'a>> {
+        match YamlIoParser::try_init(reader, encoding) {
+            Ok(parser) => parser,
+            Err(_) => panic!("failed to initialize yaml_parser_t")
+        }
+    }
+
+    /// Fails with a `YAML_INITIALIZATION_ERROR` when libyaml cannot allocate the parser.
+    pub fn try_init<'a>(reader: &'a mut dyn Read, encoding: ffi::YamlEncoding) -> Result<Box<YamlIoParser<'a>>, YamlError> {
         unsafe {
             let mut parser = Box::new(YamlIoParser {
                 base_parser: YamlBaseParser::new(),
                 reader: reader,
-                io_error: None
+                io_error: None,
+                encoding,
+                head_read: false
             });
 
-            if !parser.base_parser.initialize() {
-                panic!("failed to initialize yaml_parser_t");
-            }
+            parser.base_parser.initialize()?;
+
+            ffi::yaml_parser_set_encoding(parser.base_parser.parser_mem(), encoding);
 
-            ffi::yaml_parser_set_encoding(&mut parser.base_parser.parser_mem, encoding);
+            ffi::yaml_parser_set_input(parser.base_parser.parser_mem(), handle_reader_cb, mem::transmute(&mut *parser));
+
+            Ok(parser)
+        }
+    }
 
-            ffi::yaml_parser_set_input(&mut parser.base_parser.parser_mem, handle_reader_cb, mem::transmute(&mut *parser));
+    fn read_head(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // enough for the longest BOM
+        let mut size = 0;
+        while size < 3 {
+            match self.reader.read(&mut buf[size..]) {
+                Ok(0) => break,
+                Ok(n) => size += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err)
+            }
+        }
 
-            parser
+        let bom = codecs::bom(self.encoding);
+        if !bom.is_empty() && buf[..size].starts_with(bom) {
+            buf.copy_within(bom.len()..size, 0);
+            unsafe {
+                self.base_parser.parser_mem().offset += bom.len() as libc::size_t;
+            }
+            self.base_parser.bom_consumed = true;
+            Ok(size - bom.len())
+        } else {
+            self.base_parser.bom_consumed = self.encoding == ffi::YamlEncoding::YamlAnyEncoding
+                && codecs::detect_bom(&buf[..size]).is_some();
+            Ok(size)
         }
     }
 } 
 
-#[cfg(test)]
-mod test {
-    use event::{YamlEventSpec, YamlSequenceParam, YamlScalarParam};
-    use event::YamlEventSpec::*;
-    use document::{YamlDocument, YamlNode};
-    use parser;
-    use parser::YamlParser;
-    use error::YamlError;
-    use ffi::YamlErrorType;
-    use ffi::YamlEncoding::*;
-    use ffi::YamlScalarStyle::*;
-    use ffi::YamlSequenceStyle::*;
-    use std::io::BufReader;
+/// Parser for a reader that may return `WouldBlock`, such as one wrapping a non-blocking socket.
+///
+/// libyaml pulls its input through a callback that cannot be suspended, so this parser keeps
+/// the bytes read since the last document it returned and parses them again with a
+/// `YamlByteParser` on each `resume`. Until the reader reaches its end, only the input up to
+/// its last line break is parsed, and events are only released up to the end of the last
+/// document that is known to be complete: one ended by a `...` line or followed by another
+/// document. The input of released documents is then dropped, so the cost of each `resume`
+/// grows with the document being read rather than with the whole stream.
+pub struct YamlResumableParser<'r> {
+    reader: &'r mut (dyn Read+'r),
+    encoding: ffi::YamlEncoding,
+    // the input from the line where the first document not yet released starts
+    buffer: Vec<u8>,
+    // the length of the `RESUME_PREFIX` that stands in for the input dropped from `buffer`
+    prefix: usize,
+    // where the input kept after the prefix starts in the whole input, in characters and lines,
+    // and in bytes
+    start: YamlMark,
+    start_offset: usize,
+    eof: bool,
+    // events of `buffer` already returned by `resume`
+    released: usize,
+    // the error that ends the stream, returned once the events before it are released
+    error: Option<YamlError>,
+    finished: bool,
+}
 
-    #[test]
-    fn test_byte_parser() {
-        let data = "[1, 2, 3]";
-        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
-        let expected = Ok(vec![
-            YamlStreamStartEvent(YamlUtf8Encoding),
-            YamlDocumentStartEvent(None, vec![], true),
-            YamlSequenceStartEvent(YamlSequenceParam{anchor: None, tag: None, implicit: true, style: YamlFlowSequenceStyle}),
-            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "1".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
-            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "2".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
-            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "3".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
+impl<'r> YamlResumableParser<'r> {
+    pub fn init<'a>(reader: &'a mut dyn Read, encoding: ffi::YamlEncoding) -> YamlResumableParser<'a> {
+        YamlResumableParser {
+            reader,
+            encoding,
+            buffer: Vec::new(),
+            prefix: 0,
+            start: YamlMark { index: 0, line: 0, column: 0 },
+            start_offset: 0,
+            eof: false,
+            released: 0,
+            error: None,
+            finished: false
+        }
+    }
+
+    /// Whether every event has been returned, or the error that ended the stream.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Reads until the reader returns `WouldBlock` or reaches its end, and returns the events
+    /// that became available, which may be none. Any other read error, or a parse error, is
+    /// returned after the events before it, and the reader is not read again.
+    ///
+    /// A parse error at the end of input that may still grow, such as a flow sequence that is
+    /// not closed yet, is only returned once the reader reaches its end.
+    pub fn resume(&mut self) -> Result<Vec<YamlEvent>, YamlError> {
+        if let Some(err) = self.error.take() {
+            self.finished = true;
+            return Err(err);
+        }
+        if self.finished {
+            return Ok(Vec::new());
+        }
+
+        let mut chunk = [0; 4096];
+        while !self.eof && self.error.is_none() {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => {
+                    self.error = Some(YamlError {
+                        kind: ffi::YamlErrorType::YAML_READER_ERROR,
+                        problem: Some("input error".to_string()),
+                        io_error: Some(err),
+                        context: None
+                    });
+                }
+            }
+        }
+
+        let encoding = self.detected_encoding();
+        let input = if self.eof {
+            &self.buffer[..]
+        } else {
+            &self.buffer[..self.complete_lines_len()]
+        };
+        let mut events = Vec::new();
+        let mut complete = 0;
+        for res in YamlByteParser::init(input, self.encoding).parse() {
+            let evt = match res {
+                Ok(evt) => evt,
+                Err(err) => {
+                    // an error where the input ends may only be that the rest is still missing
+                    let at_end = match err.context {
+                        Some(ref context) => {
+                            let content = &input[self.bom_len(encoding)..];
+                            char_offset(content, encoding, context.problem_mark.index + 1).is_none()
+                        },
+                        None => true
+                    };
+                    if self.error.is_none() && (self.eof || !at_end) {
+                        complete = events.len();
+                        self.error = Some(self.shift_error(err));
+                    }
+                    break;
+                }
+            };
+
+            match evt.spec {
+                YamlEventSpec::YamlDocumentEndEvent(false) => complete = events.len() + 1,
+                YamlEventSpec::YamlDocumentStartEvent(..)
+                    if events.last().is_some_and(|last: &YamlEvent| matches!(last.spec, YamlEventSpec::YamlDocumentEndEvent(_))) =>
+                    complete = events.len(),
+                _ => ()
+            }
+            events.push(evt);
+            if self.eof {
+                complete = events.len();
+            }
+        }
+
+        if self.eof && self.error.is_none() {
+            self.finished = true;
+        }
+        let released = self.released;
+        if complete <= released {
+            return match self.error.take() {
+                Some(err) => {
+                    self.finished = true;
+                    Err(err)
+                },
+                None => Ok(Vec::new())
+            };
+        }
+
+        let mut batch: Vec<YamlEvent> = events.drain(released..complete).collect();
+        let last_end = batch[batch.len() - 1].end;
+        for evt in batch.iter_mut() {
+            evt.start = self.shift_mark(evt.start);
+            evt.end = self.shift_mark(evt.end);
+        }
+        if self.finished || self.error.is_some() {
+            self.released = complete;
+        } else {
+            self.trim(last_end, encoding);
+        }
+        Ok(batch)
+    }
+
+    // The encoding of the input, which is UTF-8 unless `encoding` or a BOM names another.
+    fn detected_encoding(&self) -> ffi::YamlEncoding {
+        match self.encoding {
+            ffi::YamlEncoding::YamlAnyEncoding =>
+                codecs::detect_bom(&self.buffer[..]).unwrap_or(ffi::YamlEncoding::YamlUtf8Encoding),
+            encoding => encoding
+        }
+    }
+
+    // Drops the input before the line of `end`, the end of the last event released, where the
+    // next document starts or the `...` of the last one is. The `RESUME_PREFIX` put in its place
+    // leaves libyaml expecting a document that follows another, as it did at that point of the
+    // whole input, and its events count as released.
+    fn trim(&mut self, end: YamlMark, encoding: ffi::YamlEncoding) {
+        let skipped = self.bom_len(encoding);
+        let line_start = end.index - end.column;
+        let offset = skipped + char_offset(&self.buffer[skipped..], encoding, line_start).unwrap_or(self.buffer.len() - skipped);
+        self.start = self.shift_mark(YamlMark { index: line_start, line: end.line, column: 0 });
+        self.start_offset = self.shift_offset(offset);
+
+        let prefix: Vec<u8> = RESUME_PREFIX.bytes().flat_map(|b| match encoding {
+            ffi::YamlEncoding::YamlUtf16LeEncoding => vec![b, 0],
+            ffi::YamlEncoding::YamlUtf16BeEncoding => vec![0, b],
+            _ => vec![b]
+        }).collect();
+        self.prefix = prefix.len();
+        self.buffer.splice(..offset, prefix);
+        // the BOM is gone with the first line, so the encoding can no longer be detected
+        self.encoding = encoding;
+        self.released = 4;
+    }
+
+    // The length of the BOM at the start of the input, which is not counted in marks.
+    fn bom_len(&self, encoding: ffi::YamlEncoding) -> usize {
+        let bom = codecs::bom(encoding);
+        if self.buffer.starts_with(bom) { bom.len() } else { 0 }
+    }
+
+    // Maps a mark in `buffer` to the whole input. The kept input starts at a line start, so
+    // only the index and line of a mark change.
+    fn shift_mark(&self, mark: YamlMark) -> YamlMark {
+        let (chars, lines) = if self.prefix == 0 { (0, 0) } else { (RESUME_PREFIX.len(), 2) };
+        YamlMark {
+            index: self.start.index + mark.index - chars,
+            line: self.start.line + mark.line - lines,
+            column: mark.column
+        }
+    }
+
+    fn shift_offset(&self, offset: usize) -> usize {
+        self.start_offset + offset - self.prefix
+    }
+
+    fn shift_error(&self, mut err: YamlError) -> YamlError {
+        let reader_error = err.kind == ffi::YamlErrorType::YAML_READER_ERROR;
+        if let Some(ref mut context) = err.context {
+            // libyaml only sets the offset of an error in decoding the input
+            if reader_error {
+                context.byte_offset = self.shift_offset(context.byte_offset);
+            }
+            context.problem_mark = self.shift_mark(context.problem_mark);
+            context.context_mark = self.shift_mark(context.context_mark);
+        }
+        err
+    }
+
+    // The length of the input read so far up to and including its last line break.
+    fn complete_lines_len(&self) -> usize {
+        let line_break: &[u8] = match self.detected_encoding() {
+            ffi::YamlEncoding::YamlUtf16LeEncoding => b"\n\x00",
+            ffi::YamlEncoding::YamlUtf16BeEncoding => b"\x00\n",
+            _ => b"\n"
+        };
+        let unit = line_break.len();
+        (0..self.buffer.len() / unit).rev()
+            .find(|&i| &self.buffer[i * unit..(i + 1) * unit] == line_break)
+            .map_or(0, |i| (i + 1) * unit)
+    }
+}
+
+// A document of two lines that `YamlResumableParser` parses in place of the input it dropped.
+const RESUME_PREFIX: &str = "---\n...\n";
+
+// The byte offset of the character at `index`, as libyaml counts them in marks, or `None` if
+// `bytes` holds fewer characters. It is the length of `bytes` for an index just past the end.
+pub(crate) fn char_offset(bytes: &[u8], encoding: ffi::YamlEncoding, index: usize) -> Option<usize> {
+    let mut chars = 0;
+    let mut offset = 0;
+    while chars < index {
+        if offset >= bytes.len() {
+            return None;
+        }
+        offset += match encoding {
+            ffi::YamlEncoding::YamlUtf16LeEncoding | ffi::YamlEncoding::YamlUtf16BeEncoding => {
+                if offset + 1 >= bytes.len() {
+                    return None;
+                }
+                let lead = if encoding == ffi::YamlEncoding::YamlUtf16LeEncoding { bytes[offset + 1] } else { bytes[offset] };
+                if (0xd8..0xdc).contains(&lead) { 4 } else { 2 }
+            },
+            _ => match bytes[offset] {
+                0xf0..=0xff => 4,
+                0xe0..=0xef => 3,
+                0xc0..=0xdf => 2,
+                _ => 1
+            }
+        };
+        chars += 1;
+    }
+    if offset <= bytes.len() { Some(offset) } else { None }
+}
+
+/// Limits enforced by `parse_untrusted_with_config`, which checks them as it reads the events,
+/// before any node is built.
+///
+/// The defaults are 1 MiB of input, 128 aliases, scalars of at most 64 KiB and the default
+/// `YamlParserLimits`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct YamlUntrustedConfig {
+    pub max_bytes: usize,
+    pub max_aliases: usize,
+    pub max_scalar_len: usize,
+    pub limits: YamlParserLimits,
+}
+
+impl Default for YamlUntrustedConfig {
+    fn default() -> YamlUntrustedConfig {
+        YamlUntrustedConfig {
+            max_bytes: 1 << 20,
+            max_aliases: 128,
+            max_scalar_len: 64 << 10,
+            limits: YamlParserLimits::default(),
+        }
+    }
+}
+
+/// Limits on the size of a document, checked by `YamlDocument::check_limits` and
+/// `parse_untrusted_with_config`.
+///
+/// Aliases share their anchored node in a loaded document, but a constructor copies it for
+/// each of them, so a small document such as `&a [x, x]`, `&b [*a, *a]`, `&c [*b, *b]`, ...
+/// can grow exponentially once constructed. The defaults are a nesting depth of 64 and at
+/// most 100,000 nodes, both before and after aliases are expanded.
+///
+/// `check_limits`, and so `YamlDocumentStream::with_limits`, only runs once libyaml has
+/// composed the whole document, so the nodes themselves are built whatever their number.
+/// Constructing a document that was never checked, as `to_value` does, applies no limit, and
+/// neither does the serde deserializer, except that it stops at the default
+/// `max_alias_expansions`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct YamlParserLimits {
+    /// Collections that may be nested in each other, counting those reached through aliases.
+    pub max_depth: usize,
+    /// Nodes the document may have once every alias is expanded into a copy of its node.
+    pub max_alias_expansions: usize,
+    /// Distinct nodes in the document, as counted by `YamlDocument::node_count`.
+    pub max_document_nodes: usize,
+}
+
+impl Default for YamlParserLimits {
+    fn default() -> YamlParserLimits {
+        YamlParserLimits {
+            max_depth: 64,
+            max_alias_expansions: 100_000,
+            max_document_nodes: 100_000,
+        }
+    }
+}
+
+pub(crate) fn limit_error(problem: String, mark: &YamlMark) -> YamlError {
+    let context = YamlErrorContext {
+        byte_offset: mark.index,
+        problem_mark: *mark,
+        context: None,
+        context_mark: *mark,
+    };
+
+    YamlError {
+        kind: ffi::YamlErrorType::YAML_LIMIT_ERROR,
+        problem: Some(problem),
+        io_error: None,
+        context: Some(context)
+    }
+}
+
+fn check_untrusted_events(bytes: &[u8], config: &YamlUntrustedConfig) -> Result<(), YamlError> {
+    // anchored node sizes, and the (anchor, expanded size) of every open collection
+    let mut anchor_sizes: HashMap<String, usize> = HashMap::new();
+    let mut stack: Vec<(Option<String>, usize)> = Vec::new();
+    let limits = &config.limits;
+    let mut aliases = 0;
+    let mut expanded = 0;
+    let mut nodes = 0;
+
+    let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    for res in parser.parse() {
+        let evt = res?;
+        if let YamlEventSpec::YamlScalarEvent(_) | YamlEventSpec::YamlSequenceStartEvent(_) | YamlEventSpec::YamlMappingStartEvent(_) = evt.spec {
+            nodes += 1;
+            if nodes > limits.max_document_nodes {
+                return Err(limit_error(format!("document exceeds the maximum of {} nodes", limits.max_document_nodes), &evt.start));
+            }
+        }
+        let node = match evt.spec {
+            YamlEventSpec::YamlScalarEvent(param) => {
+                if param.value.len() > config.max_scalar_len {
+                    return Err(limit_error(format!("scalar exceeds the maximum length of {} bytes", config.max_scalar_len), &evt.start));
+                }
+                Some((param.anchor, 1))
+            },
+            YamlEventSpec::YamlAliasEvent(anchor) => {
+                aliases += 1;
+                if aliases > config.max_aliases {
+                    return Err(limit_error(format!("document exceeds the maximum of {} aliases", config.max_aliases), &evt.start));
+                }
+                Some((None, anchor_sizes.get(&anchor).cloned().unwrap_or(1)))
+            },
+            YamlEventSpec::YamlSequenceStartEvent(param) | YamlEventSpec::YamlMappingStartEvent(param) => {
+                if stack.len() >= limits.max_depth {
+                    return Err(limit_error(format!("document exceeds the maximum nesting depth of {}", limits.max_depth), &evt.start));
+                }
+                stack.push((param.anchor, 1));
+                None
+            },
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => stack.pop(),
+            _ => None
+        };
+
+        if let Some((anchor, size)) = node {
+            if let Some(anchor) = anchor {
+                anchor_sizes.insert(anchor, size);
+            }
+            match stack.last_mut() {
+                Some(&mut (_, ref mut parent_size)) => *parent_size = parent_size.saturating_add(size),
+                None => expanded = size
+            }
+            if expanded > limits.max_alias_expansions || stack.iter().any(|&(_, n)| n > limits.max_alias_expansions) {
+                return Err(limit_error(format!("document exceeds the maximum of {} nodes after alias expansion", limits.max_alias_expansions), &evt.start));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a whole UTF-8 stream into its events, from STREAM-START to STREAM-END.
+///
+/// This gives the same events as collecting `parse()` into a `Result<Vec<_>, _>`, without the
+/// marks and without wrapping each event in a `Result`. Parsing stops at the first error.
+pub fn parse_all_events(bytes: &[u8]) -> Result<Vec<YamlEventSpec>, YamlError> {
+    let mut parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    let mut events = Vec::new();
+
+    loop {
+        match unsafe { parser.parse_event() } {
+            Some(evt) => {
+                let done = evt.spec == YamlEventSpec::YamlStreamEndEvent;
+                events.push(evt.spec);
+                if done {
+                    return Ok(events);
+                }
+            },
+            None => return Err(unsafe { parser.get_error() })
+        }
+    }
+}
+
+/// Formats the events of a UTF-8 stream in the notation of the yaml-test-suite `test.event`
+/// files, one event per line, such as `+DOC ---`, `+MAP {} &a` or `=VAL <tag:yaml.org,2002:str> 'x`.
+///
+/// Parsing stops at the first error, which is returned instead of the events before it.
+pub fn dump_events(bytes: &[u8]) -> Result<String, YamlError> {
+    let mut dump = String::new();
+    for spec in parse_all_events(bytes)? {
+        dump.push_str(&suite_event_line(&spec));
+        dump.push('\n');
+    }
+    Ok(dump)
+}
+
+fn suite_event_line(spec: &YamlEventSpec) -> String {
+    let node_props = |anchor: &Option<String>, tag: &Option<String>| {
+        let mut props = String::new();
+        if let Some(ref anchor) = *anchor {
+            props.push_str(&format!(" &{}", anchor));
+        }
+        if let Some(ref tag) = *tag {
+            props.push_str(&format!(" <{}>", tag));
+        }
+        props
+    };
+
+    match *spec {
+        YamlEventSpec::YamlNoEvent => String::new(),
+        YamlEventSpec::YamlStreamStartEvent(_) => "+STR".to_string(),
+        YamlEventSpec::YamlStreamEndEvent => "-STR".to_string(),
+        YamlEventSpec::YamlDocumentStartEvent(_, _, implicit) => if implicit { "+DOC" } else { "+DOC ---" }.to_string(),
+        YamlEventSpec::YamlDocumentEndEvent(implicit) => if implicit { "-DOC" } else { "-DOC ..." }.to_string(),
+        YamlEventSpec::YamlAliasEvent(ref anchor) => format!("=ALI *{}", anchor),
+        YamlEventSpec::YamlSequenceStartEvent(ref param) => {
+            let flow = if param.style == ffi::YamlSequenceStyle::YamlFlowSequenceStyle { " []" } else { "" };
+            format!("+SEQ{}{}", flow, node_props(&param.anchor, &param.tag))
+        },
+        YamlEventSpec::YamlMappingStartEvent(ref param) => {
+            let flow = if param.style == ffi::YamlSequenceStyle::YamlFlowSequenceStyle { " {}" } else { "" };
+            format!("+MAP{}{}", flow, node_props(&param.anchor, &param.tag))
+        },
+        YamlEventSpec::YamlSequenceEndEvent => "-SEQ".to_string(),
+        YamlEventSpec::YamlMappingEndEvent => "-MAP".to_string(),
+        YamlEventSpec::YamlScalarEvent(ref param) => {
+            let style = match param.style {
+                ffi::YamlScalarStyle::YamlSingleQuotedScalarStyle => '\'',
+                ffi::YamlScalarStyle::YamlDoubleQuotedScalarStyle => '"',
+                ffi::YamlScalarStyle::YamlLiteralScalarStyle => '|',
+                ffi::YamlScalarStyle::YamlFoldedScalarStyle => '>',
+                _ => ':'
+            };
+            let mut value = String::new();
+            for c in param.value.chars() {
+                match c {
+                    '\\' => value.push_str("\\\\"),
+                    '\0' => value.push_str("\\0"),
+                    '\x08' => value.push_str("\\b"),
+                    '\t' => value.push_str("\\t"),
+                    '\n' => value.push_str("\\n"),
+                    '\r' => value.push_str("\\r"),
+                    c => value.push(c)
+                }
+            }
+            format!("=VAL{} {}{}", node_props(&param.anchor, &param.tag), style, value)
+        }
+    }
+}
+
+// A node event with its tag replaced by the one it resolves to and its anchor by a number.
+#[derive(PartialEq)]
+enum YamlResolvedEvent {
+    Document,
+    Scalar(Option<usize>, String, String),
+    Start(Option<usize>, String),
+    End,
+    Alias(Option<usize>)
+}
+
+fn resolve_events(bytes: &[u8]) -> Result<Vec<YamlResolvedEvent>, YamlError> {
+    let ctor = YamlStandardConstructor::new();
+    let mut anchors: Vec<String> = Vec::new();
+    let mut resolved = Vec::new();
+
+    for spec in parse_all_events(bytes)? {
+        let evt = match spec {
+            YamlEventSpec::YamlDocumentStartEvent(..) => {
+                anchors.clear();
+                YamlResolvedEvent::Document
+            },
+            YamlEventSpec::YamlScalarEvent(param) => {
+                let tag = match param.tag {
+                    Some(ref tag) if tag != "!" => tag.clone(),
+                    None if param.style == ffi::YamlScalarStyle::YamlPlainScalarStyle =>
+                        resolved_tag(&ctor.resolve_plain(param.value.clone())).to_string(),
+                    _ => ffi::YAML_STR_TAG.to_string()
+                };
+                YamlResolvedEvent::Scalar(anchor_number(&mut anchors, param.anchor), tag, param.value)
+            },
+            YamlEventSpec::YamlSequenceStartEvent(param) => {
+                let tag = param.tag.clone().unwrap_or_else(|| ffi::YAML_SEQ_TAG.to_string());
+                YamlResolvedEvent::Start(anchor_number(&mut anchors, param.anchor), tag)
+            },
+            YamlEventSpec::YamlMappingStartEvent(param) => {
+                let tag = param.tag.clone().unwrap_or_else(|| ffi::YAML_MAP_TAG.to_string());
+                YamlResolvedEvent::Start(anchor_number(&mut anchors, param.anchor), tag)
+            },
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => YamlResolvedEvent::End,
+            YamlEventSpec::YamlAliasEvent(name) => YamlResolvedEvent::Alias(anchors.iter().position(|anchor| *anchor == name)),
+            _ => continue
+        };
+        resolved.push(evt);
+    }
+    Ok(resolved)
+}
+
+fn anchor_number(anchors: &mut Vec<String>, anchor: Option<String>) -> Option<usize> {
+    anchor.map(|name| {
+        anchors.push(name);
+        anchors.len() - 1
+    })
+}
+
+fn resolved_tag(data: &YamlStandardData) -> &'static str {
+    match *data {
+        YamlStandardData::YamlNull => ffi::YAML_NULL_TAG,
+        YamlStandardData::YamlBool(_) => ffi::YAML_BOOL_TAG,
+        YamlStandardData::YamlInteger(_) => ffi::YAML_INT_TAG,
+        YamlStandardData::YamlFloat(_) => ffi::YAML_FLOAT_TAG,
+        _ => ffi::YAML_STR_TAG
+    }
+}
+
+/// Compares two UTF-8 streams document by document, ignoring tags that resolution would infer.
+///
+/// An untagged plain scalar counts as carrying the tag the default schema resolves it to and
+/// any other untagged node as `!!str`, `!!seq` or `!!map`, so `!!int 1` equals `1` and
+/// `!!str a` equals `'a'`, while `!!str 1` does not equal `1`. Scalar values, styles aside,
+/// must be the same, and anchors are matched by position rather than by name.
+pub fn eq_ignoring_tags(a: &[u8], b: &[u8]) -> Result<bool, YamlError> {
+    Ok(resolve_events(a)? == resolve_events(b)?)
+}
+
+/// Returns the first problem in a UTF-8 stream, or `None` if every document in it is valid.
+///
+/// Events are dropped as soon as they are checked, so only anchor names and marks are kept in
+/// memory. Besides parser errors this reports aliases to undefined anchors and anchors defined
+/// twice in a document, as loading would.
+pub fn first_error(bytes: &[u8]) -> Option<YamlError> {
+    let mut anchors: HashMap<String, YamlMark> = HashMap::new();
+
+    let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    for res in parser.parse() {
+        let evt = match res {
+            Ok(evt) => evt,
+            Err(err) => return Some(err)
+        };
+        let anchor = match evt.spec {
+            YamlEventSpec::YamlDocumentStartEvent(..) => {
+                anchors.clear();
+                None
+            },
+            YamlEventSpec::YamlAliasEvent(anchor) => {
+                if !anchors.contains_key(&anchor) {
+                    return Some(YamlError {
+                        kind: ffi::YamlErrorType::YAML_COMPOSER_ERROR,
+                        problem: Some("found undefined alias".to_string()),
+                        io_error: None,
+                        context: Some(YamlErrorContext {
+                            byte_offset: evt.start.index,
+                            problem_mark: evt.start,
+                            context: None,
+                            context_mark: evt.start
+                        })
+                    });
+                }
+                None
+            },
+            YamlEventSpec::YamlScalarEvent(param) => param.anchor,
+            YamlEventSpec::YamlSequenceStartEvent(param) | YamlEventSpec::YamlMappingStartEvent(param) => param.anchor,
+            _ => None
+        };
+        if let Some(anchor) = anchor {
+            if let Some(&first) = anchors.get(&anchor) {
+                return Some(YamlError {
+                    kind: ffi::YamlErrorType::YAML_COMPOSER_ERROR,
+                    problem: Some("second occurrence".to_string()),
+                    io_error: None,
+                    context: Some(YamlErrorContext {
+                        byte_offset: evt.start.index,
+                        problem_mark: evt.start,
+                        context: Some("found duplicate anchor; first occurrence".to_string()),
+                        context_mark: first
+                    })
+                });
+            }
+            anchors.insert(anchor, evt.start);
+        }
+    }
+
+    None
+}
+
+fn flatten_error(problem: &str, mark: &YamlMark) -> YamlError {
+    YamlError {
+        kind: ffi::YamlErrorType::YAML_VALIDATION_ERROR,
+        problem: Some(problem.to_string()),
+        io_error: None,
+        context: Some(YamlErrorContext {
+            byte_offset: mark.index,
+            problem_mark: *mark,
+            context: None,
+            context_mark: *mark
+        })
+    }
+}
+
+fn flatten_node(node: YamlNode, path: &str, open: &mut Vec<*const ffi::yaml_node_t>,
+        entries: &mut Vec<(String, String)>) -> Result<(), YamlError> {
+    let join = |segment: &str| if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    };
+
+    let (ptr, mark) = match node {
+        YamlNode::YamlScalarNode(scalar) => {
+            entries.push((path.to_string(), scalar.get_value()));
+            return Ok(());
+        },
+        YamlNode::YamlSequenceNode(ref sequence) => (unsafe { sequence.internal_node() as *const _ }, sequence.start_mark()),
+        YamlNode::YamlMappingNode(ref mapping) => (unsafe { mapping.internal_node() as *const _ }, mapping.start_mark())
+    };
+    if open.contains(&ptr) {
+        return Err(flatten_error("cannot flatten a recursive alias", &mark));
+    }
+
+    open.push(ptr);
+    match node {
+        YamlNode::YamlSequenceNode(sequence) => {
+            for (i, item) in sequence.values().enumerate() {
+                flatten_node(item, &join(&i.to_string()), open, entries)?;
+            }
+        },
+        YamlNode::YamlMappingNode(mapping) => {
+            for (key, value) in mapping.pairs() {
+                let key = match key {
+                    YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
+                    YamlNode::YamlSequenceNode(sequence) => return Err(flatten_error("cannot flatten a sequence key", &sequence.start_mark())),
+                    YamlNode::YamlMappingNode(mapping) => return Err(flatten_error("cannot flatten a mapping key", &mapping.start_mark()))
+                };
+                flatten_node(value, &join(&key), open, entries)?;
+            }
+        },
+        YamlNode::YamlScalarNode(_) => ()
+    }
+    open.pop();
+    Ok(())
+}
+
+/// Flattens every document of a UTF-8 stream into `(path, value)` pairs, in document order.
+///
+/// A path joins the mapping keys and sequence indices leading to a scalar with `.`, so
+/// `server: {ports: [80]}` gives `("server.ports.0", "80")`. A scalar at the root has the
+/// empty path. Keys are not escaped, so a key containing `.` produces an ambiguous path.
+/// Values are the scalar text as written, without type resolution, and empty collections
+/// produce no entries. Collection keys and recursive aliases are rejected with a
+/// `YAML_VALIDATION_ERROR`.
+pub fn parse_flat(bytes: &[u8]) -> Result<Vec<(String, String)>, YamlError> {
+    let mut entries = Vec::new();
+
+    let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    for doc in parser.load() {
+        let doc = doc?;
+        flatten_node(doc.root().unwrap(), "", &mut Vec::new(), &mut entries)?;
+    }
+    Ok(entries)
+}
+
+/// Parses a single UTF-8 document from an untrusted source with the default `YamlUntrustedConfig`.
+pub fn parse_untrusted(bytes: &[u8]) -> Result<YamlStandardData, YamlError> {
+    parse_untrusted_with_config(bytes, &YamlUntrustedConfig::default())
+}
+
+/// Parses a single UTF-8 document, rejecting input that exceeds any of the given limits
+/// with a `YAML_LIMIT_ERROR`. The input must contain exactly one document.
+pub fn parse_untrusted_with_config(bytes: &[u8], config: &YamlUntrustedConfig) -> Result<YamlStandardData, YamlError> {
+    if bytes.len() > config.max_bytes {
+        let mark = YamlMark { index: 0, line: 0, column: 0 };
+        return Err(limit_error(format!("input exceeds the maximum of {} bytes", config.max_bytes), &mark));
+    }
+
+    check_untrusted_events(bytes, config)?;
+
+    ::parse_single_document(bytes, ffi::YamlEncoding::YamlUtf8Encoding)
+}
+
+/// Finds the byte range of every document in a UTF-8 stream, for `parse_document_range`.
+///
+/// Nodes are not built, so this only costs a pass over the events. A range runs from the
+/// document's first directive or `---`, or its content if it has neither, to the end of its
+/// `...`, or to where the next document starts.
+pub fn index_documents(bytes: &[u8]) -> Result<Vec<Range<usize>>, YamlError> {
+    let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    let mut marks = Vec::new();
+    let mut start = 0;
+    for res in parser.parse() {
+        let evt = res?;
+        match evt.spec {
+            YamlEventSpec::YamlDocumentStartEvent(..) => start = evt.start.index,
+            YamlEventSpec::YamlDocumentEndEvent(_) => marks.push(start..evt.end.index),
+            _ => ()
+        }
+    }
+
+    // marks count characters after any BOM; the input is valid UTF-8 since it parsed
+    let bom = codecs::bom(ffi::YamlEncoding::YamlUtf8Encoding);
+    let skipped = if bytes.starts_with(bom) { bom.len() } else { 0 };
+    let text = str::from_utf8(&bytes[skipped..]).unwrap();
+    let (mut chars, mut offset) = (0, 0);
+    let mut offset_of = |index: usize| {
+        while chars < index {
+            offset += text[offset..].chars().next().unwrap().len_utf8();
+            chars += 1;
+        }
+        skipped + offset
+    };
+    Ok(marks.into_iter().map(|range| offset_of(range.start)..offset_of(range.end)).collect())
+}
+
+/// Parses the document at `range`, one of the ranges found by `index_documents`. Marks in an
+/// error count from the start of the range.
+pub fn parse_document_range(bytes: &[u8], range: Range<usize>) -> Result<YamlStandardData, YamlError> {
+    match bytes.get(range) {
+        Some(document) => ::parse_single_document(document, ffi::YamlEncoding::YamlUtf8Encoding),
+        None => Err(YamlError::new(ffi::YamlErrorType::YAML_READER_ERROR, Some("document range is out of bounds".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use event::{YamlEvent, YamlEventSpec, YamlSequenceParam, YamlScalarParam};
+    use event::YamlEventSpec::*;
+    use document::{YamlDocument, YamlNode, YamlNodeData, YamlNodeKind};
+    use parser;
+    use parser::YamlParser;
+    use error::YamlError;
+    use ffi::YamlErrorType;
+    use ffi::YamlEncoding::*;
+    use ffi::YamlScalarStyle::*;
+    use ffi::YamlSequenceStyle::*;
+    use constructor::YamlStandardData::*;
+    use std::io;
+    use std::io::BufReader;
+    use codecs;
+
+    #[test]
+    fn test_byte_parser() {
+        let data = "[1, 2, 3]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let expected = Ok(vec![
+            YamlStreamStartEvent(YamlUtf8Encoding),
+            YamlDocumentStartEvent(None, vec![], true),
+            YamlSequenceStartEvent(YamlSequenceParam{anchor: None, tag: None, implicit: true, style: YamlFlowSequenceStyle}),
+            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "1".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
+            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "2".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
+            YamlScalarEvent(YamlScalarParam{anchor: None, tag: None, value: "3".to_string(), plain_implicit: true, quoted_implicit: false, style: YamlPlainScalarStyle}),
             YamlSequenceEndEvent,
             YamlDocumentEndEvent(true),
             YamlStreamEndEvent
@@ -333,54 +1753,1334 @@ mod test {
     }
 
     #[test]
-    fn test_document() {
+    fn test_stream_encoding() {
         let data = "[1, 2, 3]";
         let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
-        let docs_res:Result<Vec<Box<YamlDocument>>, YamlError> = parser.load().collect();
+        let mut stream = parser.parse();
 
-        match docs_res {
-            Err(e) => panic!("unexpected result: {:?}", e),
-            Ok(docs) => match docs[..].first().and_then(|doc| doc.root()) {
-                Some(YamlNode::YamlSequenceNode(seq)) => {
-                    let values:Vec<String> = seq.values().map(|node| {
-                        match node {
-                            YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
-                            _ => panic!("unexpected scalar")
-                        }
-                    }).collect();
-                    assert_eq!(vec!["1".to_string(), "2".to_string(), "3".to_string()], values)
-                },
-                _ => panic!("unexpected result")
-            }
+        assert_eq!(Ok(YamlUtf8Encoding), stream.encoding());
+        assert_eq!(Ok(YamlUtf8Encoding), stream.encoding());
+
+        match stream.next() {
+            Some(Ok(evt)) => assert_eq!(YamlDocumentStartEvent(None, vec![], true), evt.spec),
+            res => panic!("unexpected result: {:?}", res)
         }
     }
 
     #[test]
-    fn test_mapping_document() {
-        let data = "{\"a\": 1, \"b\": 2}";
-        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
-        let docs_res:Result<Vec<Box<YamlDocument>>, YamlError> = parser.load().collect();
+    fn test_stream_encoding_detected() {
+        let data = b"\xff\xfe[\x001\x00]\x00";
+        let parser = parser::YamlByteParser::init(data, YamlAnyEncoding);
+        let mut stream = parser.parse();
 
-        match docs_res {
-            Err(e) => panic!("unexpected result: {:?}", e),
-            Ok(docs) => match docs[..].first().and_then(|doc| doc.root()) {
-                Some(YamlNode::YamlMappingNode(seq)) => {
-                    let values:Vec<(String, String)> = seq.pairs().map(|(key, value)| {
-                        (
-                            match key {
-                                YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
-                                _ => panic!("unexpected scalar")
-                            },
-                            match value {
-                                YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
-                                _ => panic!("unexpected scalar")
-                            }
-                        )
-                    }).collect();
-                    assert_eq!(vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())], values)
-                },
-                _ => panic!("unexpected result")
+        assert_eq!(Ok(YamlUtf16LeEncoding), stream.encoding());
+    }
+
+    #[test]
+    fn test_document_accept() {
+        use document::{YamlVisitor, YamlScalarData, YamlSequenceData, YamlMappingData};
+
+        #[derive(Default)]
+        struct Counter {
+            scalars: usize,
+            keys: Vec<String>,
+            trace: String
+        }
+
+        impl YamlVisitor for Counter {
+            fn visit_scalar(&mut self, scalar: &YamlScalarData) {
+                self.scalars += 1;
+                self.trace.push_str(&scalar.get_value());
+            }
+            fn visit_sequence_start(&mut self, _sequence: &YamlSequenceData) { self.trace.push('['); }
+            fn visit_sequence_end(&mut self, _sequence: &YamlSequenceData) { self.trace.push(']'); }
+            fn visit_mapping_start(&mut self, _mapping: &YamlMappingData) { self.trace.push('{'); }
+            fn visit_mapping_end(&mut self, _mapping: &YamlMappingData) { self.trace.push('}'); }
+            fn visit_key(&mut self, key: &YamlNode) {
+                if let YamlNode::YamlScalarNode(ref scalar) = *key {
+                    self.keys.push(scalar.get_value());
+                }
+                self.trace.push_str("k:");
             }
         }
+
+        let visit = |data: &str| -> Counter {
+            let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let mut counter = Counter::default();
+            parser.load().next().unwrap().unwrap().accept(&mut counter);
+            counter
+        };
+
+        let counter = visit("{a: [1,2], b: 3}");
+        assert_eq!(3, counter.scalars);
+        assert_eq!(vec!["a".to_string(), "b".to_string()], counter.keys);
+        assert_eq!("{k:[12]k:3}", counter.trace);
+
+        assert_eq!("[[x][x]]", visit("[&a [x], *a]").trace);
+    }
+
+    #[test]
+    fn test_node_predicates() {
+        let data = "{a: 1, b: [x, {c: y}], d: &s z, e: *s}";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let root = doc.root().unwrap();
+        let node = |path: &str| doc.query(path).unwrap();
+
+        assert!(root.is_mapping() && !root.is_sequence() && !root.is_scalar());
+        assert!(node("a").is_scalar() && !node("a").is_mapping());
+        assert!(node("b").is_sequence() && !node("b").is_scalar());
+        assert!(node("e").is_scalar());
+
+        assert_eq!(Some("1".to_string()), node("a").as_scalar().map(|scalar| scalar.get_value()));
+        assert_eq!(Some(2), node("b").as_sequence().map(|seq| seq.values().count()));
+        assert!(node("b.1").as_mapping().and_then(|map| map.get("c")).is_some());
+        assert!(root.as_scalar().is_none() && node("a").as_sequence().is_none() && node("b").as_mapping().is_none());
+
+        assert_eq!("{\"a\": \"1\", \"b\": [\"x\", {\"c\": \"y\"}], \"d\": \"z\", \"e\": \"z\"}", format!("{:?}", root));
+        assert_eq!("[\n    \"x\",\n    {\n        \"c\": \"y\",\n    },\n]", format!("{:#?}", node("b")));
+    }
+
+    #[test]
+    fn test_scalar_get_str() {
+        let data = "- plain\n- ''\n- \"caf\\u00e9\"\n- |\n  two\n  lines\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let borrowed: Vec<&str> = doc.root().unwrap().as_sequence().unwrap().values()
+            .map(|node| node.as_scalar().unwrap().get_str()).collect();
+        let owned: Vec<String> = doc.root().unwrap().as_sequence().unwrap().values()
+            .map(|node| node.as_scalar().unwrap().get_value()).collect();
+
+        assert_eq!(vec!["plain", "", "caf\u{e9}", "two\nlines\n"], borrowed);
+        assert_eq!(owned, borrowed);
+    }
+
+    #[test]
+    fn test_mapping_typed_getters() {
+        let data = "port: 8080\nhost: example.com\ndebug: true\nquoted: \"12\"\nweight: 1.5\ntags: [a, b]\ndb: {user: admin}\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let root = doc.root().unwrap();
+        let config = root.as_mapping().unwrap();
+
+        assert_eq!(Some(8080), config.get_i64("port"));
+        assert_eq!(Some("example.com"), config.get_str("host"));
+        assert_eq!(Some(true), config.get_bool("debug"));
+        assert_eq!(Some("8080"), config.get_str("port"));
+        assert_eq!(Some(2), config.get_seq("tags").map(|tags| tags.len()));
+        assert_eq!(Some("admin"), config.get_map("db").and_then(|db| db.get_str("user")));
+
+        assert_eq!(None, config.get_i64("missing"));
+        assert_eq!(None, config.get_i64("host"));
+        assert_eq!(None, config.get_i64("quoted"));
+        assert_eq!(None, config.get_i64("weight"));
+        assert_eq!(None, config.get_bool("port"));
+        assert_eq!(None, config.get_str("tags"));
+        assert!(config.get_seq("db").is_none() && config.get_map("tags").is_none());
+
+        assert_eq!(Ok(Some(8080)), config.try_get_i64("port"));
+        assert_eq!(Ok(None), config.try_get_i64("missing"));
+        let err = config.try_get_i64("host").unwrap_err();
+        assert_eq!(Some(1), err.context.map(|ctx| ctx.problem_mark.line));
+    }
+
+    #[test]
+    fn test_collection_len() {
+        let data = "{empty_seq: [], empty_map: {}, seq: [a, [b, c], {d: e}], map: {x: 1, y: 2}}";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let seq_len = |path: &str| doc.query(path).and_then(|node| node.as_sequence().map(|seq| (seq.len(), seq.is_empty())));
+        let map_len = |path: &str| doc.query(path).and_then(|node| node.as_mapping().map(|map| (map.len(), map.is_empty())));
+
+        assert_eq!(Some((0, true)), seq_len("empty_seq"));
+        assert_eq!(Some((0, true)), map_len("empty_map"));
+        assert_eq!(Some((3, false)), seq_len("seq"));
+        assert_eq!(Some((2, false)), seq_len("seq.1"));
+        assert_eq!(Some((2, false)), map_len("map"));
+        assert_eq!(Some((4, false)), map_len(""));
+    }
+
+    #[test]
+    fn test_document_query() {
+        let data = "servers:\n  - host: &h alpha\n    ports: [80, 443]\n  - host: *h\n\"0\": zero\nempty: []\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let value = |path: &str| match doc.query(path) {
+            Some(YamlNode::YamlScalarNode(scalar)) => Some(scalar.get_value()),
+            Some(_) => Some("<collection>".to_string()),
+            None => None
+        };
+
+        assert_eq!(Some("alpha".to_string()), value("servers.0.host"));
+        assert_eq!(Some("443".to_string()), value("servers[0].ports[1]"));
+        assert_eq!(Some("443".to_string()), value("servers.0.ports.1"));
+        assert_eq!(Some("alpha".to_string()), value("servers[1].host"));
+        assert_eq!(Some("zero".to_string()), value("0"));
+        assert_eq!(Some("<collection>".to_string()), value(""));
+        assert_eq!(None, value("servers.2.host"));
+        assert_eq!(None, value("servers.0.missing"));
+        assert_eq!(None, value("servers.host"));
+        assert_eq!(None, value("servers.0[0]"));
+        assert_eq!(None, value("servers.0.host.x"));
+        assert_eq!(None, value("empty[0]"));
+        assert_eq!(None, value("servers[x]"));
+        assert_eq!(None, value("servers..0"));
+    }
+
+    #[test]
+    fn test_node_tags() {
+        let data = "[!!str 123, 123, \"123\", !foo x, ! y, !!map {}, []]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let items: Vec<YamlNode> = match doc.root() {
+            Some(YamlNode::YamlSequenceNode(seq)) => seq.values().collect(),
+            _ => panic!("expected a sequence")
+        };
+        let tags: Vec<(Option<String>, Option<String>)> = items.iter().map(|node| match *node {
+            YamlNode::YamlScalarNode(ref scalar) => (scalar.tag(), scalar.explicit_tag()),
+            YamlNode::YamlSequenceNode(ref seq) => (seq.tag(), seq.explicit_tag()),
+            YamlNode::YamlMappingNode(ref map) => (map.tag(), map.explicit_tag())
+        }).collect();
+        let str_tag = Some("tag:yaml.org,2002:str".to_string());
+        assert_eq!(vec![(str_tag.clone(), str_tag.clone()), (str_tag.clone(), None), (str_tag.clone(), None),
+                        (Some("!foo".to_string()), Some("!foo".to_string())), (str_tag.clone(), str_tag.clone()),
+                        (Some("tag:yaml.org,2002:map".to_string()), Some("tag:yaml.org,2002:map".to_string())),
+                        (Some("tag:yaml.org,2002:seq".to_string()), None)], tags);
+
+        let styles: Vec<::ffi::YamlScalarStyle> = items.iter().filter_map(|node| match *node {
+            YamlNode::YamlScalarNode(ref scalar) => Some(scalar.style()),
+            _ => None
+        }).collect();
+        assert_eq!(vec![YamlPlainScalarStyle, YamlPlainScalarStyle, YamlDoubleQuotedScalarStyle, YamlPlainScalarStyle, YamlPlainScalarStyle], styles);
+    }
+
+    #[test]
+    fn test_document_start_directives() {
+        use event::{YamlVersionDirective, YamlTagDirective};
+
+        let data = "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n---\n!e!foo bar\n--- baz\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let starts: Vec<YamlEventSpec> = parser.parse().map(|res| res.unwrap().spec)
+            .filter(|spec| matches!(spec, YamlDocumentStartEvent(..))).collect();
+        let tag_directive = YamlTagDirective { handle: "!e!".to_string(), prefix: "tag:example.com,2000:".to_string() };
+        assert_eq!(vec![YamlDocumentStartEvent(Some(YamlVersionDirective { major: 1, minor: 1 }), vec![tag_directive], false),
+                        YamlDocumentStartEvent(None, vec![], false)], starts);
+
+        let mut events = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse_borrowed();
+        events.next_event();
+        assert_eq!(starts[0], events.next_event().unwrap().unwrap().to_event().spec);
+    }
+
+    #[test]
+    fn test_init_autodetect() {
+        let scalars = |bytes: &[u8], encoding| -> Vec<(String, usize)> {
+            let mut events = parser::YamlByteParser::init_autodetect(bytes).parse();
+            assert_eq!(Ok(encoding), events.encoding());
+            let scalars = events.by_ref().filter_map(|res| match res.unwrap() {
+                YamlEvent { spec: YamlScalarEvent(param), start, .. } => Some((param.value, start.column)),
+                _ => None
+            }).collect();
+            assert_eq!(!codecs::bom(encoding).is_empty() && bytes.starts_with(codecs::bom(encoding)), events.bom_consumed());
+            scalars
+        };
+        let expected = vec![("1".to_string(), 1), ("x".to_string(), 4)];
+
+        let mut utf8 = codecs::bom(YamlUtf8Encoding).to_vec();
+        utf8.extend_from_slice(b"[1, x]");
+        assert_eq!(expected, scalars(&utf8, YamlUtf8Encoding));
+        assert_eq!(expected, scalars(b"[1, x]", YamlUtf8Encoding));
+        for &encoding in [YamlUtf16LeEncoding, YamlUtf16BeEncoding].iter() {
+            assert_eq!(expected, scalars(&codecs::encode_str("[1, x]", encoding), encoding));
+        }
+
+        // without a BOM, UTF-16 is taken for UTF-8 and its zero bytes are rejected
+        let err = parser::YamlByteParser::init_autodetect(b"[\x001\x00]\x00").parse().encoding().unwrap_err();
+        assert_eq!(Some("control characters are not allowed".to_string()), err.problem);
+    }
+
+    #[test]
+    fn test_fused_after_error() {
+        let parser = parser::YamlByteParser::init(b"[a", YamlUtf8Encoding);
+        let mut events = parser.parse();
+        while let Some(Ok(_)) = events.next() {}
+        assert_eq!((0, Some(0)), events.size_hint());
+        assert!(events.next().is_none());
+        assert!(events.next().is_none());
+
+        let parser = parser::YamlByteParser::init(b"--- a\n--- [b", YamlUtf8Encoding);
+        let mut docs = parser.load();
+        assert!(docs.next().unwrap().is_ok());
+        assert!(docs.next().unwrap().is_err());
+        assert!(docs.next().is_none());
+        assert!(docs.next().is_none());
+
+        let parser = parser::YamlByteParser::init(b"--- {a: 1, a: 2}\n--- b", YamlUtf8Encoding);
+        let results: Vec<bool> = parser.load().reject_duplicate_keys(true).map(|res| res.is_ok()).collect();
+        assert_eq!(vec![false, true], results);
+    }
+
+    #[test]
+    fn test_content_events() {
+        let parser = parser::YamlByteParser::init(b"[1,2,3]", YamlUtf8Encoding);
+        let specs: Vec<YamlEventSpec> = parser.parse().content_events().map(|res| res.unwrap().spec).collect();
+        match &specs[..] {
+            &[YamlSequenceStartEvent(..), YamlScalarEvent(ref a), YamlScalarEvent(ref b), YamlScalarEvent(ref c), YamlSequenceEndEvent] =>
+                assert_eq!(("1", "2", "3"), (&a.value[..], &b.value[..], &c.value[..])),
+            specs => panic!("unexpected events: {:?}", specs)
+        }
+
+        let parser = parser::YamlByteParser::init(b"--- a\n--- [b", YamlUtf8Encoding);
+        let results: Vec<bool> = parser.parse().content_events().map(|res| res.is_ok()).collect();
+        assert_eq!(vec![true, true, true, false], results);
+    }
+
+    #[test]
+    fn test_peekable_event_stream() {
+        let parser = parser::YamlByteParser::init(b"a", YamlUtf8Encoding);
+        let mut events = parser.parse().into_peekable();
+
+        match events.peek() {
+            Some(&Ok(YamlEvent { spec: YamlStreamStartEvent(YamlUtf8Encoding), .. })) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+        assert!(events.peek().is_some());
+        match events.next() {
+            Some(Ok(YamlEvent { spec: YamlStreamStartEvent(YamlUtf8Encoding), .. })) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+        match events.next() {
+            Some(Ok(YamlEvent { spec: YamlDocumentStartEvent(..), .. })) => (),
+            res => panic!("unexpected result: {:?}", res)
+        }
+
+        let parser = parser::YamlByteParser::init(b"[a", YamlUtf8Encoding);
+        let mut events = parser.parse().into_peekable();
+        while let Some(&Ok(_)) = events.peek() {
+            events.next();
+        }
+        assert!(events.peek().map_or(false, |res| res.is_err()));
+        assert!(events.next().map_or(false, |res| res.is_err()));
+    }
+
+    #[test]
+    fn test_try_init() {
+        fn values<P: parser::YamlParser>(events: parser::YamlEventStream<P>) -> Vec<String> {
+            events.filter_map(|res| match res.unwrap() {
+                YamlEvent { spec: YamlScalarEvent(param), .. } => Some(param.value),
+                _ => None
+            }).collect()
+        }
+        let expected = vec!["a".to_string(), "b".to_string()];
+
+        let data = b"[a, b]";
+        assert_eq!(expected, values(parser::YamlByteParser::try_init(data, YamlUtf8Encoding).unwrap().parse()));
+        assert_eq!(expected, values(parser::YamlOwnedParser::try_init(data.to_vec(), YamlUtf8Encoding).unwrap().parse()));
+        let mut reader: &[u8] = data;
+        assert_eq!(expected, values(parser::YamlIoParser::try_init(&mut reader, YamlUtf8Encoding).unwrap().parse()));
+    }
+
+    #[test]
+    fn test_parse_borrowed() {
+        for data in ["a: [1, '', \"x\\ty\"]\n--- &a !t b\n...\n", "{a: *b"].iter() {
+            let owned: Vec<Result<YamlEvent, YamlError>> = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse().collect();
+            let mut events = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse_borrowed();
+            let mut count = 0;
+            while let Some(res) = events.next_event() {
+                match (res, &owned[count]) {
+                    (Ok(evt), &Ok(ref expected)) => {
+                        let value = match expected.spec {
+                            YamlScalarEvent(ref param) => Some(&param.value[..]),
+                            _ => None
+                        };
+                        assert_eq!(value, evt.scalar_str());
+                        assert_eq!(value.map(|value| value.as_bytes()), evt.scalar_bytes());
+                        assert_eq!((expected.start, expected.end), (evt.start(), evt.end()));
+                        assert_eq!(expected.spec, evt.to_event().spec);
+                    },
+                    (Err(err), &Err(ref expected)) => {
+                        assert_eq!(*expected, err);
+                        count += 1;
+                        break;
+                    },
+                    (res, expected) => panic!("{:?} does not match {:?}", res.map(|evt| evt.to_event().spec), expected)
+                }
+                count += 1;
+            }
+            assert_eq!(owned.len(), count);
+        }
+    }
+
+    #[test]
+    fn test_document_to_value() {
+        let data = "a: [1, 2.5, {b: ~, c: [yes, 'x']}]\n[k]: {}\n";
+        let value = {
+            let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let doc = parser.load().next().unwrap().unwrap();
+            doc.to_value().unwrap()
+        };
+        let expected = YamlMapping(vec![
+            (YamlString("a".to_string()), YamlSequence(vec![YamlInteger(1), YamlFloat(2.5), YamlMapping(vec![
+                (YamlString("b".to_string()), YamlNull),
+                (YamlString("c".to_string()), YamlSequence(vec![YamlBool(true), YamlString("x".to_string())]))
+            ])])),
+            (YamlSequence(vec![YamlString("k".to_string())]), YamlMapping(vec![]))
+        ]);
+        assert_eq!(expected, value);
+
+        let value = ::std::thread::spawn(move || YamlDocument::from_value(&value).unwrap().to_value().unwrap()).join().unwrap();
+        assert_eq!(expected, value);
+        assert_eq!(Ok(YamlNull), YamlDocument::from_value(&YamlNull).unwrap().to_value());
+    }
+
+    #[test]
+    fn test_owned_parser() {
+        let parser = parser::YamlOwnedParser::init(format!("- {}\n- {}\n", 1, "x").into_bytes(), YamlUtf8Encoding);
+        let specs: Vec<YamlEventSpec> = parser.parse().map(|res| res.unwrap().spec).collect();
+        assert_eq!(specs, parser::parse_all_events(b"- 1\n- x\n").unwrap());
+
+        let data = [&b"\xef\xbb\xbf"[..], &b"a: 1\n--- b\n"[..]].concat();
+        let parser = parser::YamlOwnedParser::init(data, YamlUtf8Encoding);
+        assert_eq!(2, parser.load().count());
+    }
+
+    #[test]
+    fn test_event_marks() {
+        let data = "- a\n- bc\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let marks: Vec<((usize, usize, usize), (usize, usize, usize))> = parser.parse().filter_map(|res| {
+            let evt = res.unwrap();
+            match evt.spec {
+                YamlScalarEvent(_) => Some(((evt.start.line, evt.start.column, evt.start.index), (evt.end.line, evt.end.column, evt.end.index))),
+                _ => None
+            }
+        }).collect();
+        assert_eq!(vec![((0, 2, 2), (0, 3, 3)), ((1, 2, 6), (1, 4, 8))], marks);
+    }
+
+    #[test]
+    fn test_explicit_encoding_bom() {
+        let data = b"\xef\xbb\xbfa: 1\nb: 2\n";
+        let utf16: Vec<u8> = b"\xfe\xff".iter().cloned()
+            .chain("a: 1\nb: 2\n".encode_utf16().flat_map(|u| vec![(u >> 8) as u8, u as u8])).collect();
+
+        for &(ref bytes, encoding) in [(data.to_vec(), YamlUtf8Encoding), (utf16, YamlUtf16BeEncoding)].iter() {
+            let parser = parser::YamlByteParser::init(&bytes[..], encoding);
+            let events: Vec<YamlEvent> = parser.parse().collect::<Result<_, _>>().unwrap();
+            assert_eq!(10, events.len());
+            assert_eq!(0, events[5].start.column);
+
+            let mut reader = BufReader::new(&bytes[..]);
+            let parser = parser::YamlIoParser::init(&mut reader, encoding);
+            assert_eq!(10, parser.parse().collect::<Result<Vec<YamlEvent>, YamlError>>().unwrap().len());
+        }
+
+        // error offsets still count the BOM, as when libyaml detects the encoding
+        let offsets: Vec<usize> = [YamlAnyEncoding, YamlUtf8Encoding].iter().map(|&encoding| {
+            let parser = parser::YamlByteParser::init(b"\xef\xbb\xbfa: \xff", encoding);
+            match parser.parse().last() {
+                Some(Err(err)) => err.context.unwrap().byte_offset,
+                res => panic!("unexpected result: {:?}", res)
+            }
+        }).collect();
+        assert_eq!(vec![6, 6], offsets);
+    }
+
+    // Returns at most four bytes per read, with a `WouldBlock` before each.
+    struct TrickleReader {
+        data: Vec<u8>,
+        pos: usize,
+        blocked: bool
+    }
+
+    impl io::Read for TrickleReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "no data yet"));
+            }
+            let size = buf.len().min(4).min(self.data.len() - self.pos);
+            buf[..size].copy_from_slice(&self.data[self.pos..self.pos + size]);
+            self.pos += size;
+            Ok(size)
+        }
+    }
+
+    #[test]
+    fn test_resumable_parser() {
+        let data = "a: 1\n---\n- [b, c]\n...\n%YAML 1.1\n--- d\n";
+        let mut reader = TrickleReader { data: data.as_bytes().to_vec(), pos: 0, blocked: false };
+        let mut parser = parser::YamlResumableParser::init(&mut reader, YamlUtf8Encoding);
+
+        let mut batches = Vec::new();
+        while !parser.is_finished() {
+            let events = parser.resume().unwrap();
+            if !events.is_empty() {
+                batches.push(events.into_iter().map(|evt| evt.spec).collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(vec![7, 8, 4], batches.iter().map(|batch| batch.len()).collect::<Vec<_>>());
+        assert_eq!(Some(&YamlDocumentEndEvent(true)), batches[0].last());
+        assert_eq!(Some(&YamlDocumentEndEvent(false)), batches[1].last());
+
+        let expected: Vec<YamlEventSpec> = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).parse()
+            .map(|res| res.unwrap().spec).collect();
+        assert_eq!(expected, batches.concat());
+        assert_eq!(0, parser.resume().unwrap().len());
+
+        let utf16 = codecs::encode_str(data, YamlUtf16LeEncoding);
+        let mut reader = TrickleReader { data: utf16, pos: 0, blocked: false };
+        let mut parser = parser::YamlResumableParser::init(&mut reader, YamlAnyEncoding);
+        let mut sizes = Vec::new();
+        while !parser.is_finished() {
+            let events = parser.resume().unwrap();
+            if !events.is_empty() {
+                sizes.push(events.len());
+            }
+        }
+        assert_eq!(vec![7, 8, 4], sizes);
+
+        // once the input is complete, the events before an error are released as a YamlIoParser yields them
+        let data = b"a: 1\n---\n[b\n";
+        let mut reader = TrickleReader { data: data.to_vec(), pos: 0, blocked: false };
+        let mut parser = parser::YamlResumableParser::init(&mut reader, YamlUtf8Encoding);
+        let mut events = Vec::new();
+        let err = loop {
+            match parser.resume() {
+                Ok(batch) => events.extend(batch),
+                Err(err) => break err
+            }
+        };
+        assert_eq!(YamlErrorType::YAML_PARSER_ERROR, err.kind);
+        let expected: Vec<YamlEventSpec> = parser::YamlByteParser::init(data, YamlUtf8Encoding).parse()
+            .take_while(|res| res.is_ok()).map(|res| res.unwrap().spec).collect();
+        assert_eq!(expected, events.into_iter().map(|evt| evt.spec).collect::<Vec<_>>());
+        assert!(parser.is_finished());
+    }
+
+    #[test]
+    fn test_resumable_parser_trims_input() {
+        let data: String = (0..200).map(|i| format!("--- {{k: \u{e9}{}}}\n{}", i, if i % 2 == 0 { "...\n" } else { "" })).collect();
+        for &encoding in [YamlUtf8Encoding, YamlUtf16BeEncoding].iter() {
+            let bytes = codecs::encode_str(&data, encoding);
+            let mut reader = TrickleReader { data: bytes.clone(), pos: 0, blocked: false };
+            let mut parser = parser::YamlResumableParser::init(&mut reader, YamlAnyEncoding);
+            let mut events = Vec::new();
+            while !parser.is_finished() {
+                events.extend(parser.resume().unwrap());
+                assert!(parser.buffer.len() < 128, "{} bytes kept", parser.buffer.len());
+            }
+
+            // marks count from the start of the whole input
+            let expected: Vec<YamlEvent> = parser::YamlByteParser::init(&bytes[..], YamlAnyEncoding).parse()
+                .map(|res| res.unwrap()).collect();
+            assert_eq!(expected.len(), events.len());
+            for (evt, expected) in events.iter().zip(expected.iter()) {
+                assert_eq!((&expected.spec, expected.start, expected.end), (&evt.spec, evt.start, evt.end));
+            }
+        }
+    }
+
+    #[test]
+    fn test_resumable_parser_errors() {
+        struct StalledReader {
+            data: &'static [u8],
+            result: Option<io::Error>,
+            reads: usize
+        }
+
+        impl io::Read for StalledReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                if !self.data.is_empty() {
+                    let size = buf.len().min(self.data.len());
+                    buf[..size].copy_from_slice(&self.data[..size]);
+                    self.data = &self.data[size..];
+                    return Ok(size);
+                }
+                Err(self.result.take().unwrap_or_else(|| io::Error::new(io::ErrorKind::WouldBlock, "no data yet")))
+            }
+        }
+
+        // an error before the end of the input read so far is returned without waiting for the rest
+        let mut reader = StalledReader { data: b"--- a\n---\nb: 1\n c: 2\n", result: None, reads: 0 };
+        let mut parser = parser::YamlResumableParser::init(&mut reader, YamlUtf8Encoding);
+        assert_eq!(8, parser.resume().unwrap().len());
+        let err = parser.resume().unwrap_err();
+        assert_eq!(YamlErrorType::YAML_SCANNER_ERROR, err.kind);
+        let context = err.context.unwrap();
+        assert_eq!((3, 2), (context.problem_mark.line, context.problem_mark.column));
+        assert!(parser.is_finished());
+        assert_eq!(0, parser.resume().unwrap().len());
+
+        // a flow sequence that is not closed yet may still be completed
+        let mut reader = StalledReader { data: b"[a,\n", result: None, reads: 0 };
+        let mut parser = parser::YamlResumableParser::init(&mut reader, YamlUtf8Encoding);
+        assert_eq!(0, parser.resume().unwrap().len());
+        assert!(!parser.is_finished());
+
+        // the reader is not read again after an error
+        let mut reader = StalledReader {
+            data: b"a: 1\n",
+            result: Some(io::Error::new(io::ErrorKind::ConnectionReset, "reset")),
+            reads: 0
+        };
+        {
+            let mut parser = parser::YamlResumableParser::init(&mut reader, YamlUtf8Encoding);
+            let err = parser.resume().unwrap_err();
+            assert_eq!(YamlErrorType::YAML_READER_ERROR, err.kind);
+            assert!(parser.is_finished());
+            assert_eq!(0, parser.resume().unwrap().len());
+        }
+        assert_eq!(2, reader.reads);
+    }
+
+    #[test]
+    fn test_bom_consumed() {
+        let utf8_bom = b"\xef\xbb\xbfa: 1\n";
+        let utf16_bom = b"\xff\xfea\x00\n\x00";
+        let cases: Vec<(&[u8], _, bool)> = vec![
+            (utf8_bom, YamlAnyEncoding, true), (utf8_bom, YamlUtf8Encoding, true),
+            (utf16_bom, YamlAnyEncoding, true), (utf16_bom, YamlUtf16LeEncoding, true),
+            (b"a: 1\n", YamlAnyEncoding, false), (b"a: 1\n", YamlUtf8Encoding, false),
+            (b"a\x00\n\x00", YamlUtf16LeEncoding, false)
+        ];
+
+        for &(bytes, encoding, consumed) in cases.iter() {
+            let mut events = parser::YamlByteParser::init(bytes, encoding).parse();
+            assert!(events.all(|res| res.is_ok()));
+            assert_eq!(consumed, events.bom_consumed(), "{:?} {:?}", bytes, encoding);
+
+            let mut reader = BufReader::new(bytes);
+            let mut events = parser::YamlIoParser::init(&mut reader, encoding).parse();
+            assert!(!events.bom_consumed());
+            events.encoding().unwrap();
+            assert_eq!(consumed, events.bom_consumed(), "{:?} {:?}", bytes, encoding);
+        }
+    }
+
+    #[test]
+    fn test_file_parser() {
+        use std::env;
+        use std::fs;
+        use std::path::Path;
+
+        let path = env::temp_dir().join(format!("yaml-file-parser-{}.yml", ::std::process::id()));
+        for &(data, encoding) in [(&b"\xef\xbb\xbfa: [1, 2]\n"[..], YamlUtf8Encoding), (&b"a: [1, 2]\n"[..], YamlAnyEncoding)].iter() {
+            fs::write(&path, data).unwrap();
+            let parser = parser::YamlFileParser::init(&path, encoding).unwrap();
+            let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+            assert_eq!(1, docs.len());
+            assert_eq!(Ok(YamlMapping(vec![(YamlString("a".to_string()), YamlSequence(vec![YamlInteger(1), YamlInteger(2)]))])),
+                       docs[0].to_value());
+
+            let mut events = parser::YamlFileParser::init(&path, encoding).unwrap().parse();
+            let first_key = events.find_map(|res| match res.unwrap() {
+                YamlEvent { spec: YamlScalarEvent(_), start, .. } => Some(start.column),
+                _ => None
+            });
+            assert_eq!(Some(0), first_key);
+            assert_eq!(data.starts_with(b"\xef"), events.bom_consumed());
+        }
+        fs::remove_file(&path).unwrap();
+
+        let err = parser::YamlFileParser::init(Path::new("/nonexistent/input.yml"), YamlAnyEncoding).err().unwrap();
+        assert_eq!(YamlErrorType::YAML_READER_ERROR, err.kind);
+        assert_eq!(Some(io::ErrorKind::NotFound), err.io_error.map(|err| err.kind()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_mmap_input() {
+        use libc;
+        use std::env;
+        use std::fs::{self, File};
+        use std::os::unix::io::AsRawFd;
+        use std::ptr;
+        use std::slice;
+
+        let path = env::temp_dir().join(format!("yaml-mmap-test-{}.yml", ::std::process::id()));
+        fs::write(&path, "items: [1, 2]\n--- second\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let len = file.metadata().unwrap().len() as usize;
+
+        let docs: Vec<Box<YamlDocument>> = unsafe {
+            let addr = libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0);
+            assert!(addr != libc::MAP_FAILED);
+            let mapped = slice::from_raw_parts(addr as *const u8, len);
+
+            let parser = parser::YamlByteParser::init(mapped, YamlAnyEncoding);
+            let docs = parser.load().collect::<Result<_, _>>().unwrap();
+            libc::munmap(addr, len);
+            docs
+        };
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, docs.len());
+        match docs[1].root() {
+            Some(YamlNode::YamlScalarNode(scalar)) => assert_eq!("second", scalar.get_value()),
+            _ => panic!("unexpected result")
+        }
+    }
+
+    #[test]
+    fn test_document() {
+        let data = "[1, 2, 3]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs_res:Result<Vec<Box<YamlDocument>>, YamlError> = parser.load().collect();
+
+        match docs_res {
+            Err(e) => panic!("unexpected result: {:?}", e),
+            Ok(docs) => match docs[..].first().and_then(|doc| doc.root()) {
+                Some(YamlNode::YamlSequenceNode(seq)) => {
+                    let values:Vec<String> = seq.values().map(|node| {
+                        match node {
+                            YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
+                            _ => panic!("unexpected scalar")
+                        }
+                    }).collect();
+                    assert_eq!(vec!["1".to_string(), "2".to_string(), "3".to_string()], values)
+                },
+                _ => panic!("unexpected result")
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapping_document() {
+        let data = "{\"a\": 1, \"b\": 2}";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs_res:Result<Vec<Box<YamlDocument>>, YamlError> = parser.load().collect();
+
+        match docs_res {
+            Err(e) => panic!("unexpected result: {:?}", e),
+            Ok(docs) => match docs[..].first().and_then(|doc| doc.root()) {
+                Some(YamlNode::YamlMappingNode(seq)) => {
+                    let values:Vec<(String, String)> = seq.pairs().map(|(key, value)| {
+                        (
+                            match key {
+                                YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
+                                _ => panic!("unexpected scalar")
+                            },
+                            match value {
+                                YamlNode::YamlScalarNode(scalar) => scalar.get_value(),
+                                _ => panic!("unexpected scalar")
+                            }
+                        )
+                    }).collect();
+                    assert_eq!(vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())], values)
+                },
+                _ => panic!("unexpected result")
+            }
+        }
+    }
+
+    #[test]
+    fn test_document_anchors() {
+        let data = "base: &base {a: 1}\nlist: &list [1, *base]\nvalue: &value x\nref: *value";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(vec![("base".to_string(), YamlNodeKind::YamlMappingKind),
+                        ("list".to_string(), YamlNodeKind::YamlSequenceKind),
+                        ("value".to_string(), YamlNodeKind::YamlScalarKind)], docs[0].anchors());
+    }
+
+    #[test]
+    fn test_document_root_kind() {
+        let data = "a\n--- [a]\n--- {a: b}\n---\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let kinds: Vec<Option<YamlNodeKind>> = parser.load().map(|doc| doc.unwrap().root_kind()).collect();
+        assert_eq!(vec![Some(YamlNodeKind::YamlScalarKind), Some(YamlNodeKind::YamlSequenceKind),
+                        Some(YamlNodeKind::YamlMappingKind), Some(YamlNodeKind::YamlScalarKind)], kinds);
+    }
+
+    #[test]
+    fn test_document_check_aliases() {
+        let data = "a: &x [1, 2]\nb: *x\n---\nlist: &l\n  - 1\n  - *l\n---\n*u\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let mut docs = parser.load();
+        assert_eq!(Ok(()), docs.next().unwrap().unwrap().check_aliases());
+
+        let err = docs.next().unwrap().unwrap().check_aliases().unwrap_err();
+        assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+        assert_eq!(Some("found recursive alias *l".to_string()), err.problem);
+        let mark = err.context.unwrap().problem_mark;
+        assert_eq!((5, 4), (mark.line, mark.column));
+
+        // an undefined alias already fails to load
+        match docs.next() {
+            Some(Err(err)) => assert_eq!(Some("found undefined alias".to_string()), err.problem),
+            _ => panic!("expected an error")
+        }
+    }
+
+    #[test]
+    fn test_document_alias_nodes() {
+        let data = "a: &a 1\nb: *a\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let map = match doc.root() {
+            Some(YamlNode::YamlMappingNode(map)) => map,
+            _ => panic!("expected a mapping")
+        };
+        match (map.get("a"), map.get("b")) {
+            (Some(YamlNode::YamlScalarNode(a)), Some(YamlNode::YamlScalarNode(b))) => {
+                assert_eq!("1", b.get_value());
+                assert_eq!(a.start_mark(), b.start_mark());
+            },
+            _ => panic!("expected scalars")
+        }
+    }
+
+    #[test]
+    fn test_mapping_merged_pairs() {
+        fn merged(data: &str) -> Result<Vec<(String, String)>, YamlError> {
+            let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let doc = parser.load().next().unwrap().unwrap();
+            let item = match doc.root() {
+                Some(YamlNode::YamlMappingNode(root)) => match root.get("item") {
+                    Some(YamlNode::YamlMappingNode(item)) => item,
+                    _ => panic!("expected an item mapping")
+                },
+                _ => panic!("expected a mapping")
+            };
+            Ok(item.merged_pairs()?.into_iter().map(|pair| match pair {
+                (YamlNode::YamlScalarNode(key), YamlNode::YamlScalarNode(value)) => (key.get_value(), value.get_value()),
+                _ => panic!("expected scalars")
+            }).collect())
+        }
+        fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+            pairs.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+        }
+
+        assert_eq!(Ok(pairs(&[("a", "1"), ("b", "2"), ("c", "3")])), merged("base: &b {a: 1, b: 2}\nitem: {<<: *b, c: 3}\n"));
+        assert_eq!(Ok(pairs(&[("a", "1"), ("b", "2"), ("d", "5"), ("c", "4")])),
+                   merged("one: &1 {a: 1, b: 2}\ntwo: &2 {b: 3, c: 4, <<: {d: 5}}\nitem: {<<: [*1, *2]}\n"));
+        assert_eq!(Ok(pairs(&[("b", "2"), ("a", "0"), ("<<", "q")])), merged("item: {<<: {a: 1, b: 2}, a: 0, '<<': q}\n"));
+
+        let marks: Vec<(usize, usize)> = ["item: {<<: 1}\n", "s: &s 1\nitem: {<<: [{x: 1}, *s]}\n"].iter().map(|data| {
+            let err = merged(data).unwrap_err();
+            assert_eq!(Some("expected a mapping or a sequence of mappings to merge".to_string()), err.problem);
+            let mark = err.context.unwrap().problem_mark;
+            (mark.line, mark.column)
+        }).collect();
+        assert_eq!(vec![(0, 11), (0, 3)], marks);
+    }
+
+    #[test]
+    fn test_node_get() {
+        let data = "{a: [x, y], 'b': 1, [c]: 2, \"c\": 3, a: z}";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let map = match doc.root() {
+            Some(YamlNode::YamlMappingNode(map)) => map,
+            _ => panic!("expected a mapping")
+        };
+        let value = |key: &str| match map.get(key) {
+            Some(YamlNode::YamlScalarNode(scalar)) => Some(scalar.get_value()),
+            Some(_) => Some("collection".to_string()),
+            None => None
+        };
+        assert_eq!(vec![Some("collection".to_string()), Some("1".to_string()), Some("3".to_string()), None],
+                   ["a", "b", "c", "d"].iter().map(|key| value(key)).collect::<Vec<_>>());
+
+        let seq = match map.get("a") {
+            Some(YamlNode::YamlSequenceNode(seq)) => seq,
+            _ => panic!("expected a sequence")
+        };
+        let items: Vec<Option<String>> = (0..3).map(|idx| match seq.get(idx) {
+            Some(YamlNode::YamlScalarNode(scalar)) => Some(scalar.get_value()),
+            _ => None
+        }).collect();
+        assert_eq!(vec![Some("x".to_string()), Some("y".to_string()), None], items);
+    }
+
+    #[test]
+    fn test_scalar_coercion() {
+        let data = "[0x1F, 0o17, 1_000, -7, .nan, -.inf, 1.5, \"true\", TRUE, yes, off, ~, '', null, !!int '42', !!bool 'on', !!null x, !!float 2, text]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let scalars: Vec<_> = match doc.root() {
+            Some(YamlNode::YamlSequenceNode(seq)) => seq.values().map(|node| match node {
+                YamlNode::YamlScalarNode(scalar) => scalar,
+                _ => panic!("expected a scalar")
+            }).collect(),
+            _ => panic!("expected a sequence")
+        };
+
+        let ints: Vec<Option<i64>> = scalars.iter().map(|scalar| scalar.as_i64()).collect();
+        assert_eq!(vec![Some(31), Some(15), Some(1000), Some(-7), None, None, None, None, None, None, None, None, None, None,
+                        Some(42), None, None, None, None], ints);
+        assert_eq!(vec![Some(31), None], [&scalars[0], &scalars[3]].iter().map(|scalar| scalar.as_u64()).collect::<Vec<_>>());
+
+        assert!(scalars[4].as_f64().unwrap().is_nan());
+        assert_eq!(Some(f64::NEG_INFINITY), scalars[5].as_f64());
+        assert_eq!(Some(1.5), scalars[6].as_f64());
+        assert_eq!(Some(1000.0), scalars[2].as_f64());
+        assert_eq!(Some(2.0), scalars[17].as_f64());
+        assert_eq!(None, scalars[18].as_f64());
+
+        let bools: Vec<(Option<bool>, Option<bool>)> = scalars[7..11].iter().chain(Some(&scalars[15]))
+            .map(|scalar| (scalar.as_bool(), scalar.as_yaml11_bool())).collect();
+        assert_eq!(vec![(None, None), (Some(true), Some(true)), (None, Some(true)), (None, Some(false)), (Some(true), Some(true))], bools);
+
+        let nulls: Vec<bool> = scalars.iter().map(|scalar| scalar.is_null()).collect();
+        assert_eq!(vec![11, 13, 16], nulls.iter().enumerate().filter(|&(_, &null)| null).map(|(i, _)| i).collect::<Vec<_>>());
+
+        // a plain scalar with any other tag is not resolved by the core schema
+        let parser = parser::YamlByteParser::init(b"[!!str 123, !!str true, !!str ~, !!str yes, !x 1.5]", YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let scalars: Vec<_> = match doc.root() {
+            Some(YamlNode::YamlSequenceNode(seq)) => seq.values().map(|node| match node {
+                YamlNode::YamlScalarNode(scalar) => scalar,
+                _ => panic!("expected a scalar")
+            }).collect(),
+            _ => panic!("expected a sequence")
+        };
+        for scalar in scalars.iter() {
+            assert_eq!((None, None, None, None, false),
+                       (scalar.as_i64(), scalar.as_f64(), scalar.as_bool(), scalar.as_yaml11_bool(), scalar.is_null()),
+                       "{}", scalar.get_str());
+        }
+    }
+
+    #[test]
+    fn test_scalar_timestamp() {
+        use constructor::YamlTimestamp;
+
+        let data = "[2001-12-15T02:59:43.1Z, 2002-12-14, 2001-12-14 21:59:43.10 -5, 2001-12-14t21:59:43+05:30, \
+                    2001-12-15 2:59:43, '2002-12-14', !!timestamp '2002-12-14', 2002-2-30, 2002-02-30, 2002-1-5, 12:30:00]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let timestamps: Vec<Option<YamlTimestamp>> = doc.root().unwrap().as_sequence().unwrap().values()
+            .map(|node| node.as_scalar().unwrap().as_timestamp()).collect();
+        let timestamp = |date: (u16, u8, u8), time: (u8, u8, u8), nanosecond: u32, offset: Option<i32>| Some(YamlTimestamp {
+            year: date.0, month: date.1, day: date.2, hour: time.0, minute: time.1, second: time.2, nanosecond, offset
+        });
+        let date = timestamp((2002, 12, 14), (0, 0, 0), 0, None);
+
+        assert_eq!(vec![timestamp((2001, 12, 15), (2, 59, 43), 100_000_000, Some(0)), date,
+                        timestamp((2001, 12, 14), (21, 59, 43), 100_000_000, Some(-300)),
+                        timestamp((2001, 12, 14), (21, 59, 43), 0, Some(330)),
+                        timestamp((2001, 12, 15), (2, 59, 43), 0, None), None, date, None, None, None, None], timestamps);
+    }
+
+    #[test]
+    fn test_document_node_count() {
+        let data = "a: &x [1, 2]\nb: *x\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let docs: Vec<Box<YamlDocument>> = parser.load().collect::<Result<_, _>>().unwrap();
+        assert_eq!(6, docs[0].node_count());
+    }
+
+    #[test]
+    fn test_document_take_until() {
+        let data = "a: 1\n---\nb: 2\n--- END\n...\n\t@ not yaml [\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let mut docs = parser.load().take_until(|doc| match doc.root() {
+            Some(YamlNode::YamlScalarNode(scalar)) => scalar.get_value() == "END",
+            _ => false
+        });
+
+        assert_eq!(2, docs.by_ref().map(|doc| doc.unwrap()).count());
+        let end = docs.sentinel_end().unwrap();
+        assert_eq!("\t@ not yaml [\n", data[end.index..].trim_start_matches('\n'));
+        assert!(docs.next().is_none());
+
+        let parser = parser::YamlByteParser::init(b"a: 1\n", YamlUtf8Encoding);
+        let mut docs = parser.load().take_until(|_| false);
+        assert_eq!(1, docs.by_ref().count());
+        assert_eq!(None, docs.sentinel_end());
+    }
+
+    #[test]
+    fn test_document_on_document() {
+        let data = "a: 1\n---\n[b]\n---\nc\n";
+        let mut loaded = Vec::new();
+        {
+            let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            let mut docs = parser.load().on_document(|doc| loaded.push(doc.end_mark().line));
+            assert!(docs.next().unwrap().is_ok());
+            assert_eq!(3, docs.map(|doc| doc.unwrap().node_count()).sum::<usize>());
+        }
+        assert_eq!(vec![1, 3, 5], loaded);
+
+        let mut count = 0;
+        let parser = parser::YamlByteParser::init(b"a\n--- [b\n", YamlUtf8Encoding);
+        let results: Vec<bool> = parser.load().on_document(|_| count += 1).map(|doc| doc.is_ok()).collect();
+        assert_eq!(vec![true, false], results);
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn test_document_key_spans() {
+        let data = "name: app\n\"port\": 80\nnested:\n  inner: x\n? [a]\n: complex\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let spans = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping.key_spans(data),
+            _ => panic!("unexpected root")
+        };
+
+        assert_eq!(vec![("name".to_string(), 0..4), ("port".to_string(), 10..16), ("nested".to_string(), 21..27)], spans);
+        for (key, span) in spans {
+            assert!(data[span].contains(&key[..]));
+        }
+
+        // libyaml counts characters and skips the BOM, while the ranges are in bytes of the source
+        let data = "\u{feff}caf\u{e9}: 1\n\u{1f600}: 2\n'\u{e9}t\u{e9}': 3\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let spans = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping.key_spans(data),
+            _ => panic!("unexpected root")
+        };
+        assert_eq!(vec![("caf\u{e9}".to_string(), 3..8), ("\u{1f600}".to_string(), 12..16), ("\u{e9}t\u{e9}".to_string(), 20..27)], spans);
+        assert_eq!("'\u{e9}t\u{e9}'", &data[20..27]);
+    }
+
+    #[test]
+    fn test_document_reencode() {
+        use document;
+        use codecs;
+        use constructor::{YamlConstructor, YamlStandardConstructor};
+
+        let data = "name: caf\u{e9}\nitems: [\u{1f600}, 2]\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let value = YamlStandardConstructor::new().construct(doc.root().unwrap()).unwrap();
+
+        for &encoding in [YamlUtf16LeEncoding, YamlUtf16BeEncoding, YamlUtf8Encoding].iter() {
+            let output = document::reencode(&doc, encoding).unwrap();
+            assert!(output.starts_with(codecs::bom(encoding)) == (encoding != YamlUtf8Encoding));
+            assert_eq!(Ok(vec![value.clone()]), ::parse_bytes(&output[..], YamlAnyEncoding));
+
+            let parser = parser::YamlByteParser::init(&output[..], encoding);
+            let round_trip = parser.load().next().unwrap().unwrap();
+            let utf8 = document::reencode(&round_trip, YamlUtf8Encoding).unwrap();
+            assert_eq!(Ok(vec![value.clone()]), ::parse_bytes_utf8(&utf8[..]));
+        }
+    }
+
+    #[test]
+    fn test_document_to_string() {
+        let load = |data: &[u8]| parser::YamlByteParser::init(data, YamlUtf8Encoding).load().next().unwrap().unwrap();
+
+        let doc = load(b"{a: 1, b: [2, 3]}");
+        let output = doc.to_string().unwrap();
+        assert_eq!("{a: 1, b: [2, 3]}\n", output);
+        assert_eq!(doc.to_events(), load(output.as_bytes()).to_events());
+
+        let doc = load(b"--- !t\na: &x !!str [1]\nb: *x\n...\n");
+        let mut writer = Vec::new();
+        doc.to_writer(&mut writer).unwrap();
+        assert_eq!(&b"--- !t\na: &x !!str [1]\nb: *x\n...\n"[..], &writer[..]);
+        assert_eq!(doc.to_events(), load(&writer[..]).to_events());
+
+        // a tag given in the source is kept even when it is the one libyaml would default to
+        let doc = load(b"a: !!str 123\nb: !!seq [!!str x]\nc: !!map {}\n");
+        assert_eq!("a: !!str 123\nb: !!seq [!!str x]\nc: !!map {}\n", doc.to_string().unwrap());
+    }
+
+    #[test]
+    fn test_document_anchor_errors() {
+        let parser = parser::YamlByteParser::init(b"[&a 1, &a 2]", YamlUtf8Encoding);
+        match parser.load().next() {
+            Some(Err(err)) => {
+                assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+                assert_eq!(Some("second occurrence".to_string()), err.problem);
+                let context = err.context.unwrap();
+                assert_eq!(Some("found duplicate anchor; first occurrence".to_string()), context.context);
+                assert_eq!((1, 7), (context.context_mark.index, context.problem_mark.index));
+            },
+            res => panic!("unexpected result: {:?}", res.map(|res| res.is_ok()))
+        }
+
+        let parser = parser::YamlByteParser::init(b"[*b]", YamlUtf8Encoding);
+        match parser.load().next() {
+            Some(Err(err)) => {
+                assert_eq!(Some("found undefined alias".to_string()), err.problem);
+                assert_eq!(1, err.context.unwrap().problem_mark.index);
+            },
+            res => panic!("unexpected result: {:?}", res.map(|res| res.is_ok()))
+        }
+    }
+
+    #[test]
+    fn test_parse_all_events() {
+        let data = "a: [1, 2]\n---\nb\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let expected: Vec<YamlEventSpec> = parser.parse().map(|evt| evt.unwrap().spec).collect();
+        assert_eq!(Ok(expected), parser::parse_all_events(data.as_bytes()));
+        assert_eq!(Ok(vec![YamlStreamStartEvent(YamlUtf8Encoding), YamlStreamEndEvent]), parser::parse_all_events(b""));
+
+        let err = parser::parse_all_events(b"a: [1\n").unwrap_err();
+        assert_eq!(YamlErrorType::YAML_PARSER_ERROR, err.kind);
+    }
+
+    #[test]
+    fn test_dump_events() {
+        let data = "--- !!map\na: &x 1\nb: [*x, 'q', \"tab\\there\"]\nc: |\n  line\\\n...\n";
+        let expected = "+STR\n+DOC ---\n+MAP <tag:yaml.org,2002:map>\n=VAL :a\n=VAL &x :1\n=VAL :b\n+SEQ []\n=ALI *x\n=VAL 'q\n\
+                        =VAL \"tab\\there\n-SEQ\n=VAL :c\n=VAL |line\\\\\\n\n-MAP\n-DOC ...\n-STR\n";
+        assert_eq!(Ok(expected.to_string()), parser::dump_events(data.as_bytes()));
+        assert_eq!(Ok("+STR\n+DOC\n+MAP {}\n-MAP\n-DOC\n-STR\n".to_string()), parser::dump_events(b"{}"));
+        assert!(parser::dump_events(b"[").is_err());
+    }
+
+    #[test]
+    fn test_index_documents() {
+        let data = "\u{feff}# intro\nname: café\n---\n- ü\n- ß\n...\n%YAML 1.1\n--- !!str\ntext\n--- [last]\n";
+        let ranges = parser::index_documents(data.as_bytes()).unwrap();
+        let documents: Vec<&str> = ranges.iter().map(|range| &data[range.clone()]).collect();
+        assert_eq!(vec!["name: café\n", "---\n- ü\n- ß\n...", "%YAML 1.1\n--- !!str\ntext\n", "--- [last]\n"], documents);
+
+        assert_eq!(Ok(YamlSequence(vec![YamlString("ü".to_string()), YamlString("ß".to_string())])),
+                   parser::parse_document_range(data.as_bytes(), ranges[1].clone()));
+        assert_eq!(Ok(YamlString("text".to_string())), parser::parse_document_range(data.as_bytes(), ranges[2].clone()));
+        assert!(parser::parse_document_range(data.as_bytes(), 0..data.len() + 1).is_err());
+
+        assert_eq!(Ok(vec![]), parser::index_documents(b"# nothing\n"));
+        assert!(parser::index_documents(b"a\n--- [b\n").is_err());
+    }
+
+    #[test]
+    fn test_eq_ignoring_tags() {
+        let hand = "name: !!str app\nport: !!int 80\nratio: !!float 0.5\ndebug: !!bool false\nnone: !!null ~\nlist: !!seq [&a x, *a]\n";
+        let emitted = "name: 'app'\nport: 80\nratio: 0.5\ndebug: false\nnone: null\nlist:\n- &id001 \"x\"\n- *id001\n";
+        assert_eq!(Ok(false), parser::eq_ignoring_tags(hand.as_bytes(), emitted.as_bytes()));
+        let emitted = emitted.replace("null", "~");
+        assert_eq!(Ok(true), parser::eq_ignoring_tags(hand.as_bytes(), emitted.as_bytes()));
+
+        assert_eq!(Ok(true), parser::eq_ignoring_tags(b"! 1", b"'1'"));
+        assert_eq!(Ok(false), parser::eq_ignoring_tags(b"!!str 1", b"1"));
+        assert_eq!(Ok(false), parser::eq_ignoring_tags(b"!!int 1", b"'1'"));
+        assert_eq!(Ok(false), parser::eq_ignoring_tags(b"!local 1", b"1"));
+        assert_eq!(Ok(false), parser::eq_ignoring_tags(b"a\n--- b\n", b"a\n"));
+        assert!(parser::eq_ignoring_tags(b"a", b"[a").is_err());
+    }
+
+    #[test]
+    fn test_first_error() {
+        assert!(parser::first_error(b"a: &x 1\nb: *x\n---\n- 2\n").is_none());
+        assert!(parser::first_error(b"").is_none());
+
+        let err = parser::first_error(b"a: 1\n---\nb: [1, 2\n").unwrap();
+        assert_eq!(YamlErrorType::YAML_PARSER_ERROR, err.kind);
+        assert_eq!(Some("did not find expected ',' or ']'".to_string()), err.problem);
+
+        let err = parser::first_error(b"a: &x 1\n---\nb: *x\n").unwrap();
+        assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+        assert_eq!(Some("found undefined alias".to_string()), err.problem);
+        assert_eq!(2, err.context.unwrap().problem_mark.line);
+
+        // as loading reports it, though anchors may repeat across documents
+        let data = b"a: &x 1\n---\na: &x 1\nb: &x 2\n";
+        let err = parser::first_error(data).unwrap();
+        let load_err = parser::YamlByteParser::init(data, YamlUtf8Encoding).load().find_map(|res| res.err()).unwrap();
+        assert_eq!((YamlErrorType::YAML_COMPOSER_ERROR, Some("second occurrence".to_string())), (err.kind, err.problem));
+        let (context, load_context) = (err.context.unwrap(), load_err.context.unwrap());
+        assert_eq!(load_context.context, context.context);
+        assert_eq!((load_context.context_mark, load_context.problem_mark), (context.context_mark, context.problem_mark));
+        assert_eq!((2, 3), (context.context_mark.line, context.context_mark.column));
+        assert_eq!((3, 3), (context.problem_mark.line, context.problem_mark.column));
+    }
+
+    #[test]
+    fn test_parse_flat() {
+        let data = "server:\n  host: example.com\n  ports: [80, 443]\n  tls: {}\nnames: &n [a, '']\ncopy: *n\n";
+        let entries = parser::parse_flat(data.as_bytes()).unwrap();
+        let expected = [("server.host", "example.com"), ("server.ports.0", "80"), ("server.ports.1", "443"),
+                        ("names.0", "a"), ("names.1", ""), ("copy.0", "a"), ("copy.1", "")];
+        assert_eq!(expected.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>(), entries);
+
+        assert_eq!(Ok(vec![("".to_string(), "x".to_string())]), parser::parse_flat(b"x"));
+        assert_eq!(Some("cannot flatten a sequence key".to_string()), parser::parse_flat(b"a: {[1]: 2}").unwrap_err().problem);
+        assert_eq!(Some("cannot flatten a recursive alias".to_string()), parser::parse_flat(b"&a [1, *a]").unwrap_err().problem);
+    }
+
+    #[test]
+    fn test_parse_untrusted() {
+        assert_eq!(Ok(YamlMapping(vec![(YamlString("a".to_string()), YamlSequence(vec![YamlInteger(1), YamlInteger(2)]))])),
+                   parser::parse_untrusted(b"a: [1, 2]"));
+    }
+
+    fn untrusted_error(data: &str, config: &parser::YamlUntrustedConfig) -> YamlError {
+        match parser::parse_untrusted_with_config(data.as_bytes(), config) {
+            Err(err) => err,
+            res => panic!("unexpected result: {:?}", res)
+        }
+    }
+
+    #[test]
+    fn test_parse_untrusted_limits() {
+        let config = parser::YamlUntrustedConfig {
+            max_bytes: 64,
+            max_aliases: 1,
+            max_scalar_len: 3,
+            limits: parser::YamlParserLimits { max_depth: 2, max_alias_expansions: 8, max_document_nodes: 4 },
+        };
+
+        let err = untrusted_error("[[[1]]]", &config);
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, err.kind);
+        assert_eq!(2, err.context.unwrap().problem_mark.index);
+
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, untrusted_error("[abcd]", &config).kind);
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, untrusted_error("[&a 1, *a, *a]", &config).kind);
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, untrusted_error(&"1 ".repeat(40), &config).kind);
+        let err = untrusted_error("[1, 2, 3, 4]", &config);
+        assert_eq!(Some("document exceeds the maximum of 4 nodes".to_string()), err.problem);
+        assert_eq!(10, err.context.unwrap().problem_mark.index);
+        assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, untrusted_error("--- 1\n--- 2", &config).kind);
+    }
+
+    #[test]
+    fn test_parse_untrusted_alias_expansion() {
+        let data = "a: &a [x, x, x, x, x, x, x, x, x, x]\n\
+                    b: &b [*a, *a, *a, *a, *a, *a, *a, *a, *a, *a]\n\
+                    c: &c [*b, *b, *b, *b, *b, *b, *b, *b, *b, *b]\n\
+                    d: &d [*c, *c, *c, *c, *c, *c, *c, *c, *c, *c]\n\
+                    e: &e [*d, *d, *d, *d, *d, *d, *d, *d, *d, *d]\n\
+                    f: [*e, *e, *e, *e, *e, *e, *e, *e, *e, *e]\n";
+        let err = untrusted_error(data, &parser::YamlUntrustedConfig::default());
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, err.kind);
+        assert_eq!(4, err.context.unwrap().problem_mark.line);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let data = "name: a\nport: 1\nnested: {x: 1, 'x': 2, 0x1: y, 1: z}\nname: b\n";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let root = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping,
+            _ => panic!("expected a mapping")
+        };
+        let duplicates: Vec<(usize, usize, String)> = root.find_duplicate_keys().into_iter()
+            .map(|(mark, key)| (mark.line, mark.column, key)).collect();
+        assert_eq!(vec![(3, 0, "name".to_string())], duplicates);
+        let nested = match root.get("nested") {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping,
+            _ => panic!("expected a mapping")
+        };
+        assert_eq!(vec![(15, "x".to_string()), (31, "1".to_string())],
+                   nested.find_duplicate_keys().into_iter().map(|(mark, key)| (mark.column, key)).collect::<Vec<_>>());
+
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+        let err = parser.load().reject_duplicate_keys(true).next().unwrap().err().unwrap();
+        assert_eq!(Some("found duplicate mapping key".to_string()), err.problem);
+        let context = err.context.unwrap();
+        assert_eq!((0, 0), (context.context_mark.line, context.context_mark.column));
+        assert_eq!((3, 0), (context.problem_mark.line, context.problem_mark.column));
+
+        let parser = parser::YamlByteParser::init(b"a: 1\nb: {a: 2}\n", YamlUtf8Encoding);
+        assert!(parser.load().reject_duplicate_keys(true).next().unwrap().is_ok());
+
+        let parser = parser::YamlByteParser::init(b"{1.0: a, 1e0: b, .nan: c, .NaN: d, -0.0: e, 0.0: f, ~: g, null: h, 1: i}", YamlUtf8Encoding);
+        let doc = parser.load().next().unwrap().unwrap();
+        let keys: Vec<String> = match doc.root() {
+            Some(YamlNode::YamlMappingNode(mapping)) => mapping.find_duplicate_keys().into_iter().map(|(_, key)| key).collect(),
+            _ => panic!("expected a mapping")
+        };
+        assert_eq!(vec!["1e0", "0.0", "null"], keys);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let data = format!("{}{}", "[".repeat(10_000), "]".repeat(10_000));
+        let mut events = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).with_max_depth(64).parse();
+        let starts = events.by_ref().take_while(|res| res.is_ok()).count();
+        // STREAM-START, DOCUMENT-START and 64 sequences before the error
+        assert_eq!(66, starts);
+        assert!(events.next().is_none());
+
+        let mut events = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).with_max_depth(64).parse();
+        let err = events.find_map(|res| res.err()).unwrap();
+        assert_eq!(YamlErrorType::YAML_LIMIT_ERROR, err.kind);
+        assert_eq!(Some("input exceeds the maximum nesting depth of 64".to_string()), err.problem);
+        assert_eq!(64, err.context.unwrap().problem_mark.column);
+
+        let mut borrowed = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).with_max_depth(64).parse_borrowed();
+        let mut results = Vec::new();
+        while let Some(res) = borrowed.next_event() {
+            results.push(res.is_ok());
+        }
+        assert_eq!(67, results.len());
+        assert_eq!(Some(&false), results.last());
+
+        let data = "a: [[1], {b: [2]}]\nc: [3]\n";
+        let depth_ok = |max_depth: usize| parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding)
+            .with_max_depth(max_depth).parse().all(|res| res.is_ok());
+        assert!(depth_ok(4));
+        assert!(!depth_ok(3));
+    }
+
+    #[test]
+    fn test_document_limits() {
+        let limited = |data: &str, limits: parser::YamlParserLimits| -> Vec<Result<usize, (YamlErrorType, usize)>> {
+            let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding);
+            parser.load().with_limits(limits).map(|res| match res {
+                Ok(doc) => Ok(doc.node_count()),
+                Err(err) => Err((err.kind, err.context.unwrap().problem_mark.line))
+            }).collect()
+        };
+
+        // a billion laughs: nine levels of ten aliases each
+        let mut bomb = "a0: &a0 [lol, lol, lol, lol, lol, lol, lol, lol, lol, lol]\n".to_string();
+        for level in 1..10 {
+            let alias = format!("*a{}", level - 1);
+            bomb.push_str(&format!("a{}: &a{} [{}]\n", level, level, vec![&alias[..]; 10].join(", ")));
+        }
+        assert_eq!(vec![Err((YamlErrorType::YAML_LIMIT_ERROR, 4))], limited(&bomb, parser::YamlParserLimits::default()));
+
+        let limits = parser::YamlParserLimits { max_depth: 2, max_alias_expansions: 8, max_document_nodes: 6 };
+        assert_eq!(vec![Ok(3), Err((YamlErrorType::YAML_LIMIT_ERROR, 1)), Ok(3)],
+                   limited("[&a [1], *a, *a]\n--- [&b [[1]],\n  *b]\n--- [1, 2]\n", limits));
+        assert_eq!(vec![Err((YamlErrorType::YAML_LIMIT_ERROR, 0))], limited("[&a [1, 2], *a, *a]", limits));
+        assert_eq!(vec![Err((YamlErrorType::YAML_LIMIT_ERROR, 0))], limited("[1, 2, 3, 4, 5, 6]", limits));
+        assert_eq!(vec![Err((YamlErrorType::YAML_COMPOSER_ERROR, 0))], limited("&a [*a]", limits));
+    }
+
+    #[test]
+    fn test_tag_resolver() {
+        use constructor::{YamlConstructor, YamlStandardConstructor, YamlCoreTagResolver, YamlTagResolver};
+        use ffi;
+
+        struct StringResolver;
+
+        impl YamlTagResolver for StringResolver {
+            fn resolve(&self, _: &str, _: ffi::YamlScalarStyle) -> Option<String> {
+                Some(ffi::YAML_STR_TAG.to_string())
+            }
+        }
+
+        let data = "[42, !!int 7]";
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).with_tag_resolver(StringResolver);
+        let doc = parser.load().next().unwrap().unwrap();
+        let root = doc.root().unwrap();
+        let items: Vec<YamlNode> = root.as_sequence().unwrap().values().collect();
+        let answer = items[0].as_scalar().unwrap();
+        assert!(answer.has_resolved_tag());
+        assert_eq!(Some(ffi::YAML_STR_TAG.to_string()), answer.tag());
+        assert_eq!(None, answer.as_i64());
+        let seven = items[1].as_scalar().unwrap();
+        assert!(!seven.has_resolved_tag());
+        assert_eq!(Some(7), seven.as_i64());
+        assert_eq!(Ok(YamlSequence(vec![YamlString("42".to_string()), YamlInteger(7)])),
+                   YamlStandardConstructor::new().construct(root));
+        // the constructor's own resolver comes first
+        let ctor = YamlStandardConstructor::new().with_scalar_resolver(|_| Some(ffi::YAML_INT_TAG.to_string()));
+        assert_eq!(Ok(YamlSequence(vec![YamlInteger(42), YamlInteger(7)])), ctor.construct(doc.root().unwrap()));
+        // only the tag given in the source is written
+        assert_eq!(Ok("[42, !!int 7]\n".to_string()), doc.to_string());
+
+        let parser = parser::YamlByteParser::init(data.as_bytes(), YamlUtf8Encoding).with_tag_resolver(YamlCoreTagResolver);
+        let doc = parser.load().next().unwrap().unwrap();
+        let root = doc.root().unwrap();
+        let answer = root.as_sequence().unwrap().values().next().unwrap();
+        let answer = answer.as_scalar().unwrap();
+        assert_eq!(Some(ffi::YAML_INT_TAG.to_string()), answer.tag());
+        assert_eq!(None, answer.explicit_tag());
+        assert_eq!(Some(42), answer.as_i64());
     }
 }
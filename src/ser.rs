@@ -0,0 +1,426 @@
+use document;
+use emitter::{YamlEventEmitter, YamlByteEmitter, YamlEmitter};
+use error::YamlError;
+use event::{YamlEventSpec, YamlScalarParam, YamlSequenceParam};
+use ffi;
+use ffi::YamlErrorType::*;
+
+use serde::ser;
+use serde::ser::Serialize;
+
+use std::fmt;
+use std::io::Write;
+
+/// Serializes `value` as a single UTF-8 document.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, YamlError> {
+    let mut emitter = YamlByteEmitter::init(ffi::YamlEncoding::YamlUtf8Encoding);
+    to_emitter(&mut *emitter, value)?;
+    // libyaml emits valid UTF-8
+    Ok(String::from_utf8(emitter.into_bytes()).unwrap())
+}
+
+pub fn to_writer<T: Serialize + ?Sized>(writer: &mut dyn Write, value: &T) -> Result<(), YamlError> {
+    let mut emitter = YamlEmitter::init(writer);
+    to_emitter(&mut *emitter, value)
+}
+
+/// Serializes `value` as a stream of one document, in the emitter's encoding and configuration.
+pub fn to_emitter<E: YamlEventEmitter, T: Serialize + ?Sized>(emitter: &mut E, value: &T) -> Result<(), YamlError> {
+    let mut serializer = YamlSerializer::new();
+    value.serialize(&mut serializer)?;
+    let events = serializer.into_events();
+    emitter.emit_stream(ffi::YamlEncoding::YamlAnyEncoding, |e|
+        e.emit_document(None, &[], true, |e| e.emit_events(&events))
+    )
+}
+
+/// Serializer collecting the events of a node, which `YamlEventEmitter::emit_events` can write out
+/// within a document.
+///
+/// Strings are plain unless they would read back as another type, like `"42"` or `"null"`,
+/// and are single-quoted then. `None`, `()` and unit structs are `null`. Enums take the form
+/// `YamlDeserializer` reads: a unit variant is its name, and other variants are a mapping from
+/// their name to their content.
+pub struct YamlSerializer {
+    events: Vec<YamlEventSpec>
+}
+
+impl YamlSerializer {
+    pub fn new() -> YamlSerializer {
+        YamlSerializer { events: Vec::new() }
+    }
+
+    pub fn into_events(self) -> Vec<YamlEventSpec> {
+        self.events
+    }
+
+    fn scalar(&mut self, value: String, style: ffi::YamlScalarStyle) -> Result<(), YamlError> {
+        self.events.push(YamlEventSpec::YamlScalarEvent(YamlScalarParam {
+            anchor: None,
+            tag: None,
+            value,
+            plain_implicit: true,
+            quoted_implicit: true,
+            style
+        }));
+        Ok(())
+    }
+
+    fn plain(&mut self, value: String) -> Result<(), YamlError> {
+        self.scalar(value, ffi::YamlScalarStyle::YamlPlainScalarStyle)
+    }
+
+    fn string(&mut self, value: &str) -> Result<(), YamlError> {
+        let style = document::string_style(value, document::core_constructor());
+        self.scalar(value.to_string(), style)
+    }
+
+    fn sequence_start(&mut self) {
+        self.events.push(YamlEventSpec::YamlSequenceStartEvent(collection_param()));
+    }
+
+    fn mapping_start(&mut self) {
+        self.events.push(YamlEventSpec::YamlMappingStartEvent(collection_param()));
+    }
+
+    // opens the mapping holding a variant's content under its name
+    fn variant_start(&mut self, variant: &str) -> Result<(), YamlError> {
+        self.mapping_start();
+        self.string(variant)
+    }
+}
+
+impl Default for YamlSerializer {
+    fn default() -> YamlSerializer {
+        YamlSerializer::new()
+    }
+}
+
+fn collection_param() -> YamlSequenceParam {
+    YamlSequenceParam {
+        anchor: None,
+        tag: None,
+        implicit: true,
+        style: ffi::YamlSequenceStyle::YamlAnySequenceStyle
+    }
+}
+
+impl ser::Error for YamlError {
+    fn custom<T: fmt::Display>(msg: T) -> YamlError {
+        YamlError::new(YAML_EMITTER_ERROR, Some(msg.to_string()))
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut YamlSerializer {
+    type Ok = ();
+    type Error = YamlError;
+
+    type SerializeSeq = YamlCompound<'a>;
+    type SerializeTuple = YamlCompound<'a>;
+    type SerializeTupleStruct = YamlCompound<'a>;
+    type SerializeTupleVariant = YamlCompound<'a>;
+    type SerializeMap = YamlCompound<'a>;
+    type SerializeStruct = YamlCompound<'a>;
+    type SerializeStructVariant = YamlCompound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), YamlError> {
+        self.plain(v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), YamlError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), YamlError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), YamlError> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), YamlError> {
+        self.plain(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), YamlError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), YamlError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), YamlError> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), YamlError> {
+        self.plain(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), YamlError> {
+        self.serialize_f64(v as f64)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), YamlError> {
+        self.plain(document::float_repr(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), YamlError> {
+        self.string(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), YamlError> {
+        self.string(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), YamlError> {
+        use serde::ser::SerializeSeq;
+
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for byte in v {
+            seq.serialize_element(byte)?;
+        }
+        seq.end()
+    }
+
+    fn serialize_none(self) -> Result<(), YamlError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), YamlError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), YamlError> {
+        self.plain("null".to_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), YamlError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), YamlError> {
+        self.string(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), YamlError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, variant: &'static str,
+        value: &T) -> Result<(), YamlError>
+    {
+        self.variant_start(variant)?;
+        value.serialize(&mut *self)?;
+        self.events.push(YamlEventSpec::YamlMappingEndEvent);
+        Ok(())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<YamlCompound<'a>, YamlError> {
+        self.sequence_start();
+        Ok(YamlCompound { ser: self, variant: false })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<YamlCompound<'a>, YamlError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<YamlCompound<'a>, YamlError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+        _len: usize) -> Result<YamlCompound<'a>, YamlError>
+    {
+        self.variant_start(variant)?;
+        self.sequence_start();
+        Ok(YamlCompound { ser: self, variant: true })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<YamlCompound<'a>, YamlError> {
+        self.mapping_start();
+        Ok(YamlCompound { ser: self, variant: false })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<YamlCompound<'a>, YamlError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+        _len: usize) -> Result<YamlCompound<'a>, YamlError>
+    {
+        self.variant_start(variant)?;
+        self.mapping_start();
+        Ok(YamlCompound { ser: self, variant: true })
+    }
+}
+
+/// A sequence or mapping being serialized, which for a variant is nested in the mapping from
+/// its name.
+pub struct YamlCompound<'a> {
+    ser: &'a mut YamlSerializer,
+    variant: bool
+}
+
+impl<'a> YamlCompound<'a> {
+    fn close(self, event: YamlEventSpec) -> Result<(), YamlError> {
+        self.ser.events.push(event);
+        if self.variant {
+            self.ser.events.push(YamlEventSpec::YamlMappingEndEvent);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeSeq for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), YamlError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlSequenceEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeTuple for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), YamlError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlSequenceEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), YamlError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlSequenceEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), YamlError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlSequenceEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeMap for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), YamlError> {
+        key.serialize(&mut *self.ser)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), YamlError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlMappingEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeStruct for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), YamlError> {
+        self.ser.string(key)?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlMappingEndEvent)
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for YamlCompound<'a> {
+    type Ok = ();
+    type Error = YamlError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), YamlError> {
+        self.ser.string(key)?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<(), YamlError> {
+        self.close(YamlEventSpec::YamlMappingEndEvent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use de::from_str;
+    use ser::to_string;
+
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Job {
+        name: String,
+        retries: u8,
+        labels: Vec<String>,
+        env: BTreeMap<String, Option<f64>>,
+        unit: Marker,
+        steps: Vec<Step>
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Step {
+        Build,
+        Run(String),
+        Copy(String, String),
+        Wait { seconds: u32 }
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(Ok("- 42\n- '42'\n- 'null'\n- null\n- 1.0\n- .nan\n".to_string()),
+                   to_string(&(42, "42", "null", None::<u8>, 1.0, f64::NAN)));
+        assert_eq!(Ok("- Build\n- Wait:\n    seconds: 5\n".to_string()), to_string(&vec![Step::Build, Step::Wait { seconds: 5 }]));
+
+        let env: BTreeMap<String, Option<f64>> = vec![("LEVEL".to_string(), Some(2.0)), ("TRUE".to_string(), None)].into_iter().collect();
+        let job = Job {
+            name: "0x1F".to_string(),
+            retries: 3,
+            labels: vec!["".to_string(), "a: b".to_string(), "~".to_string()],
+            env,
+            unit: Marker,
+            steps: vec![Step::Build, Step::Run("make".to_string()), Step::Copy("a".to_string(), "b".to_string()),
+                        Step::Wait { seconds: 5 }]
+        };
+        let output = to_string(&job).unwrap();
+        assert_eq!(Ok(job), from_str(&output), "{}", output);
+
+        let large = vec![u64::MAX, i64::MAX as u64 + 1];
+        let output = to_string(&large).unwrap();
+        assert_eq!("- 18446744073709551615\n- 9223372036854775808\n", output);
+        assert_eq!(Ok(large), from_str(&output));
+    }
+}
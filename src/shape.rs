@@ -0,0 +1,405 @@
+use ffi;
+use error::{YamlError, YamlErrorContext, YamlMark};
+use event::{YamlEvent, YamlEventSpec};
+use parser::{YamlParser, YamlEventStream, YamlByteParser};
+
+use std::collections::HashMap;
+
+/// Structural template matched by `validate_shape`. Scalar values are never inspected.
+#[derive(Debug, PartialEq, Clone)]
+pub enum YamlShape {
+    /// Any node at all.
+    YamlAnyShape,
+    YamlScalarShape,
+    /// A sequence whose items all match the given shape.
+    YamlSequenceShape(Box<YamlShape>),
+    /// A mapping containing at least the given scalar keys, with values of the given shapes.
+    /// Other keys are allowed and not checked.
+    YamlMappingShape(Vec<(String, YamlShape)>),
+}
+
+fn shape_error(problem: String, mark: &YamlMark) -> YamlError {
+    let context = YamlErrorContext {
+        byte_offset: mark.index,
+        problem_mark: *mark,
+        context: None,
+        context_mark: *mark,
+    };
+
+    YamlError {
+        kind: ffi::YamlErrorType::YAML_VALIDATION_ERROR,
+        problem: Some(problem),
+        io_error: None,
+        context: Some(context)
+    }
+}
+
+fn describe(spec: &YamlEventSpec) -> &'static str {
+    match *spec {
+        YamlEventSpec::YamlScalarEvent(_) => "a scalar",
+        YamlEventSpec::YamlSequenceStartEvent(_) => "a sequence",
+        YamlEventSpec::YamlMappingStartEvent(_) => "a mapping",
+        YamlEventSpec::YamlAliasEvent(_) => "an alias",
+        _ => "the end of a node"
+    }
+}
+
+fn node_anchor(spec: &YamlEventSpec) -> Option<&String> {
+    match *spec {
+        YamlEventSpec::YamlScalarEvent(ref param) => param.anchor.as_ref(),
+        YamlEventSpec::YamlSequenceStartEvent(ref param) | YamlEventSpec::YamlMappingStartEvent(ref param) => param.anchor.as_ref(),
+        _ => None
+    }
+}
+
+struct YamlShapeMatcher<'r> {
+    events: YamlEventStream<YamlByteParser<'r>>,
+    // events to read before the stream: pushed back events and expanded aliases, last first
+    pending: Vec<YamlEvent>,
+    // anchored nodes whose events are still being recorded, with their nesting depth
+    recording: Vec<(String, usize, Vec<YamlEvent>)>,
+    anchors: HashMap<String, Vec<YamlEvent>>,
+}
+
+impl<'r> YamlShapeMatcher<'r> {
+    fn next_event(&mut self) -> Result<YamlEvent, YamlError> {
+        if let Some(evt) = self.pending.pop() {
+            return Ok(evt);
+        }
+
+        let evt = match self.events.next() {
+            Some(res) => res?,
+            None => return Err(YamlError::new(ffi::YamlErrorType::YAML_PARSER_ERROR, Some("unexpected end of stream".to_string())))
+        };
+        self.record(&evt);
+        Ok(evt)
+    }
+
+    fn record(&mut self, evt: &YamlEvent) {
+        if let Some(anchor) = node_anchor(&evt.spec) {
+            self.recording.push((anchor.clone(), 0, Vec::new()));
+        }
+
+        let delta: isize = match evt.spec {
+            YamlEventSpec::YamlSequenceStartEvent(_) | YamlEventSpec::YamlMappingStartEvent(_) => 1,
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => -1,
+            _ => 0
+        };
+        for &mut (_, ref mut depth, ref mut events) in self.recording.iter_mut() {
+            events.push(evt.clone());
+            *depth = (*depth as isize + delta) as usize;
+        }
+
+        while self.recording.last().is_some_and(|&(_, depth, _)| depth == 0) {
+            let (anchor, _, events) = self.recording.pop().unwrap();
+            self.anchors.insert(anchor, events);
+        }
+    }
+
+    fn match_node(&mut self, shape: &YamlShape) -> Result<(), YamlError> {
+        let mut evt = self.next_event()?;
+
+        if let YamlEventSpec::YamlAliasEvent(ref anchor) = evt.spec.clone() {
+            if *shape == YamlShape::YamlAnyShape {
+                return Ok(());
+            }
+            match self.anchors.get(anchor) {
+                Some(events) => self.pending.extend(events.iter().rev().cloned()),
+                None => return Err(shape_error(format!("alias *{} does not refer to a complete node", anchor), &evt.start))
+            }
+            evt = self.next_event()?;
+        }
+
+        match (shape, &evt.spec) {
+            (YamlShape::YamlAnyShape, YamlEventSpec::YamlScalarEvent(_))
+                | (YamlShape::YamlScalarShape, YamlEventSpec::YamlScalarEvent(_)) => Ok(()),
+            (YamlShape::YamlAnyShape, YamlEventSpec::YamlSequenceStartEvent(_))
+                | (YamlShape::YamlAnyShape, YamlEventSpec::YamlMappingStartEvent(_)) => {
+                while !self.at_collection_end()? {
+                    self.match_node(&YamlShape::YamlAnyShape)?;
+                }
+                Ok(())
+            },
+            (YamlShape::YamlSequenceShape(item), YamlEventSpec::YamlSequenceStartEvent(_)) => {
+                while !self.at_collection_end()? {
+                    self.match_node(item)?;
+                }
+                Ok(())
+            },
+            (YamlShape::YamlMappingShape(fields), YamlEventSpec::YamlMappingStartEvent(_)) => {
+                let mut seen = vec![false; fields.len()];
+                while !self.at_collection_end()? {
+                    let key = self.next_event()?;
+                    let field = match key.spec {
+                        YamlEventSpec::YamlScalarEvent(ref param) => fields.iter().position(|(name, _)| *name == param.value),
+                        _ => {
+                            self.pending.push(key.clone());
+                            self.match_node(&YamlShape::YamlAnyShape)?;
+                            None
+                        }
+                    };
+                    match field {
+                        Some(index) => {
+                            seen[index] = true;
+                            self.match_node(&fields[index].1)?;
+                        },
+                        None => self.match_node(&YamlShape::YamlAnyShape)?
+                    }
+                }
+
+                match seen.iter().position(|&found| !found) {
+                    Some(index) => Err(shape_error(format!("mapping is missing the key {:?}", fields[index].0), &evt.start)),
+                    None => Ok(())
+                }
+            },
+            (expected, spec) => {
+                let expected = match *expected {
+                    YamlShape::YamlScalarShape => "a scalar",
+                    YamlShape::YamlSequenceShape(_) => "a sequence",
+                    _ => "a mapping"
+                };
+                Err(shape_error(format!("expected {} but found {}", expected, describe(spec)), &evt.start))
+            }
+        }
+    }
+
+    fn at_collection_end(&mut self) -> Result<bool, YamlError> {
+        let evt = self.next_event()?;
+        match evt.spec {
+            YamlEventSpec::YamlSequenceEndEvent | YamlEventSpec::YamlMappingEndEvent => Ok(true),
+            _ => {
+                self.pending.push(evt);
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Checks that every document of a UTF-8 stream has the given shape, reporting the first
+/// mismatch as a `YAML_VALIDATION_ERROR` at the offending node.
+///
+/// Aliases are only expanded where the shape needs to look inside them.
+pub fn validate_shape(bytes: &[u8], shape: &YamlShape) -> Result<(), YamlError> {
+    let parser = YamlByteParser::init(bytes, ffi::YamlEncoding::YamlUtf8Encoding);
+    let mut matcher = YamlShapeMatcher {
+        events: parser.parse(),
+        pending: Vec::new(),
+        recording: Vec::new(),
+        anchors: HashMap::new()
+    };
+
+    loop {
+        let evt = matcher.next_event()?;
+        match evt.spec {
+            YamlEventSpec::YamlStreamEndEvent => return Ok(()),
+            YamlEventSpec::YamlDocumentStartEvent(..) => {
+                matcher.anchors.clear();
+                matcher.match_node(shape)?;
+            },
+            _ => ()
+        }
+    }
+}
+
+/// Reads the single document of a flat mapping or list of scalars straight from the events.
+struct YamlFlatReader<'r> {
+    events: YamlEventStream<YamlByteParser<'r>>,
+    // values of the anchored scalars seen so far
+    anchors: HashMap<String, String>,
+}
+
+impl<'r> YamlFlatReader<'r> {
+    fn new(input: &'r str) -> YamlFlatReader<'r> {
+        YamlFlatReader {
+            events: YamlByteParser::init(input.as_bytes(), ffi::YamlEncoding::YamlUtf8Encoding).parse(),
+            anchors: HashMap::new()
+        }
+    }
+
+    fn next_event(&mut self) -> Result<YamlEvent, YamlError> {
+        match self.events.next() {
+            Some(res) => res,
+            None => Err(YamlError::new(ffi::YamlErrorType::YAML_PARSER_ERROR, Some("unexpected end of stream".to_string())))
+        }
+    }
+
+    // The event starting the root node, or `None` when the stream has no document.
+    fn root(&mut self) -> Result<Option<YamlEvent>, YamlError> {
+        loop {
+            let evt = self.next_event()?;
+            match evt.spec {
+                YamlEventSpec::YamlStreamStartEvent(_) | YamlEventSpec::YamlDocumentStartEvent(..) => (),
+                YamlEventSpec::YamlStreamEndEvent => return Ok(None),
+                _ => return Ok(Some(evt))
+            }
+        }
+    }
+
+    fn scalar(&mut self, evt: YamlEvent) -> Result<String, YamlError> {
+        match evt.spec {
+            YamlEventSpec::YamlScalarEvent(param) => {
+                if let Some(anchor) = param.anchor {
+                    self.anchors.insert(anchor, param.value.clone());
+                }
+                Ok(param.value)
+            },
+            YamlEventSpec::YamlAliasEvent(ref anchor) => match self.anchors.get(anchor) {
+                Some(value) => Ok(value.clone()),
+                None => Err(shape_error(format!("alias *{} does not refer to a scalar", anchor), &evt.start))
+            },
+            ref spec => Err(shape_error(format!("expected a scalar but found {}", describe(spec)), &evt.start))
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), YamlError> {
+        loop {
+            let evt = self.next_event()?;
+            match evt.spec {
+                YamlEventSpec::YamlDocumentEndEvent(_) => (),
+                YamlEventSpec::YamlStreamEndEvent => return Ok(()),
+                // the kind `parse_single_document` gives for this
+                _ => return Err(YamlError {
+                    kind: ffi::YamlErrorType::YAML_COMPOSER_ERROR,
+                    ..shape_error("expected a single document but found another".to_string(), &evt.start)
+                })
+            }
+        }
+    }
+}
+
+/// Loads a UTF-8 mapping of scalar keys to scalar values, such as a flat configuration file.
+///
+/// Values are the text of the scalars whatever they resolve to, so `port: 80` gives `"80"`, and
+/// a later key replaces an earlier equal one. Input without a document gives an empty map. Any
+/// other root node or nested collection is a `YAML_VALIDATION_ERROR`, and a second document a
+/// `YAML_COMPOSER_ERROR`.
+pub fn load_string_map(input: &str) -> Result<HashMap<String, String>, YamlError> {
+    let mut reader = YamlFlatReader::new(input);
+    let mut map = HashMap::new();
+    match reader.root()? {
+        None => return Ok(map),
+        Some(YamlEvent { spec: YamlEventSpec::YamlMappingStartEvent(_), .. }) => (),
+        Some(evt) => return Err(shape_error(format!("expected a mapping but found {}", describe(&evt.spec)), &evt.start))
+    }
+
+    loop {
+        let evt = reader.next_event()?;
+        if let YamlEventSpec::YamlMappingEndEvent = evt.spec {
+            break;
+        }
+        let key = reader.scalar(evt)?;
+        let evt = reader.next_event()?;
+        let value = reader.scalar(evt)?;
+        map.insert(key, value);
+    }
+    reader.finish()?;
+    Ok(map)
+}
+
+/// Like `load_string_map`, but for a sequence of scalars.
+pub fn load_string_list(input: &str) -> Result<Vec<String>, YamlError> {
+    let mut reader = YamlFlatReader::new(input);
+    let mut list = Vec::new();
+    match reader.root()? {
+        None => return Ok(list),
+        Some(YamlEvent { spec: YamlEventSpec::YamlSequenceStartEvent(_), .. }) => (),
+        Some(evt) => return Err(shape_error(format!("expected a sequence but found {}", describe(&evt.spec)), &evt.start))
+    }
+
+    loop {
+        let evt = reader.next_event()?;
+        if let YamlEventSpec::YamlSequenceEndEvent = evt.spec {
+            break;
+        }
+        list.push(reader.scalar(evt)?);
+    }
+    reader.finish()?;
+    Ok(list)
+}
+
+#[cfg(test)]
+mod test {
+    use shape::{load_string_list, load_string_map, validate_shape};
+    use shape::YamlShape::*;
+    use ffi::YamlErrorType;
+
+    use std::collections::HashMap;
+
+    fn items_shape() -> ::shape::YamlShape {
+        YamlMappingShape(vec![
+            ("name".to_string(), YamlScalarShape),
+            ("items".to_string(), YamlSequenceShape(Box::new(YamlMappingShape(vec![("id".to_string(), YamlAnyShape)]))))
+        ])
+    }
+
+    #[test]
+    fn test_validate_shape() {
+        let data = "name: order\nextra: [1, {x: y}]\nitems:\n- id: 1\n  qty: 2\n- {id: [a, b]}\n";
+        assert_eq!(Ok(()), validate_shape(data.as_bytes(), &items_shape()));
+        assert_eq!(Ok(()), validate_shape(b"", &items_shape()));
+    }
+
+    #[test]
+    fn test_validate_shape_mismatch() {
+        let err = validate_shape(b"name: order\nitems:\n- id: 1\n- [2]\n", &items_shape()).unwrap_err();
+        assert_eq!(YamlErrorType::YAML_VALIDATION_ERROR, err.kind);
+        assert_eq!(Some("expected a mapping but found a sequence".to_string()), err.problem);
+        let mark = err.context.unwrap().problem_mark;
+        assert_eq!((3, 2), (mark.line, mark.column));
+
+        let err = validate_shape(b"name: order\nitems: []\n---\nitems: []\n", &items_shape()).unwrap_err();
+        assert_eq!(Some("mapping is missing the key \"name\"".to_string()), err.problem);
+        assert_eq!(3, err.context.unwrap().problem_mark.line);
+    }
+
+    #[test]
+    fn test_validate_shape_aliases() {
+        let shape = YamlMappingShape(vec![("items".to_string(), YamlSequenceShape(Box::new(YamlScalarShape)))]);
+        assert_eq!(Ok(()), validate_shape(b"base: &list [a, b]\nitems: *list\n", &shape));
+
+        let err = validate_shape(b"base: &map {a: b}\nitems: *map\n", &shape).unwrap_err();
+        assert_eq!(Some("expected a sequence but found a mapping".to_string()), err.problem);
+    }
+
+    #[test]
+    fn test_load_string_map() {
+        let map = load_string_map("host: example.com\nport: 80\nname: &n x\nalias: *n\n\"quoted\": ''\n").unwrap();
+        let expected: HashMap<String, String> = [("host", "example.com"), ("port", "80"), ("name", "x"), ("alias", "x"), ("quoted", "")]
+            .iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(expected, map);
+        assert_eq!(Ok(HashMap::new()), load_string_map("# nothing\n"));
+    }
+
+    #[test]
+    fn test_load_string_list() {
+        assert_eq!(Ok(vec!["a".to_string(), "1".to_string(), "true".to_string()]), load_string_list("[a, 1, true]"));
+        assert_eq!(Ok(vec!["x".to_string(), "x".to_string()]), load_string_list("- &a x\n- *a\n"));
+        assert_eq!(Ok(Vec::new()), load_string_list(""));
+    }
+
+    #[test]
+    fn test_load_string_errors() {
+        let err = load_string_map("a: 1\nb:\n  c: 2\n").unwrap_err();
+        assert_eq!(YamlErrorType::YAML_VALIDATION_ERROR, err.kind);
+        assert_eq!(Some("expected a scalar but found a mapping".to_string()), err.problem);
+        assert_eq!(2, err.context.unwrap().problem_mark.line);
+
+        let err = load_string_list("[a, [b]]").unwrap_err();
+        assert_eq!(Some("expected a scalar but found a sequence".to_string()), err.problem);
+
+        let err = load_string_list("a: b").unwrap_err();
+        assert_eq!(Some("expected a sequence but found a mapping".to_string()), err.problem);
+
+        let err = load_string_map("[a]").unwrap_err();
+        assert_eq!(Some("expected a mapping but found a sequence".to_string()), err.problem);
+
+        let err = load_string_map("--- {a: 1}\n--- {b: 2}\n").unwrap_err();
+        assert_eq!(YamlErrorType::YAML_COMPOSER_ERROR, err.kind);
+        assert_eq!(Some("expected a single document but found another".to_string()), err.problem);
+
+        let err = load_string_list("[a, *b]").unwrap_err();
+        assert_eq!(Some("alias *b does not refer to a scalar".to_string()), err.problem);
+
+        assert_eq!(YamlErrorType::YAML_PARSER_ERROR, load_string_list("[a").unwrap_err().kind);
+    }
+}
@@ -0,0 +1,74 @@
+extern crate yaml;
+
+use yaml::parser::{YamlParser, YamlByteParser};
+use yaml::document::YamlNode;
+use yaml::ffi::YamlEncoding;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counts the Rust heap only; libyaml allocates its documents with malloc directly.
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+// The peak number of bytes allocated by `f` on top of what was live before it ran.
+fn peak_allocation<F: FnOnce()>(f: F) -> usize {
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    f();
+    PEAK.load(Ordering::SeqCst) - base
+}
+
+fn documents(count: usize) -> String {
+    (0..count).map(|i| format!("--- {{id: &a {}, name: *a, tags: [x, y]}}\n", i)).collect()
+}
+
+fn for_each_peak(data: &str) -> usize {
+    peak_allocation(|| {
+        let mut count = 0;
+        let parser = YamlByteParser::init(data.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+        parser.for_each_document(|doc| {
+            if let Some(YamlNode::YamlMappingNode(_)) = doc.root() {
+                count += 1;
+            }
+        }).unwrap();
+        assert_eq!(data.matches("---").count(), count);
+    })
+}
+
+#[test]
+fn test_for_each_document_memory() {
+    let few = documents(10);
+    let many = documents(10_000);
+
+    let few_peak = for_each_peak(&few);
+    let many_peak = for_each_peak(&many);
+    assert!(many_peak <= few_peak, "{} bytes for 10k documents, {} bytes for 10", many_peak, few_peak);
+
+    let collected_peak = peak_allocation(|| {
+        let parser = YamlByteParser::init(many.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+        assert_eq!(10_000, parser.load().collect::<Result<Vec<_>, _>>().unwrap().len());
+    });
+    assert!(collected_peak > 100 * many_peak);
+}
@@ -43,6 +43,59 @@ fn error_cause_test_read() {
     }
 }
 
+// Returns `data` a byte at a time, with an error of each kind in `errors` before the byte at
+// the matching position, and then an error of the kind `last` if there is one.
+struct FlakyReader {
+    data: &'static [u8],
+    errors: Vec<(usize, io::ErrorKind)>,
+    last: Option<io::ErrorKind>
+}
+
+impl Read for FlakyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(pos) = self.errors.iter().position(|&(at, _)| at == 0) {
+            let (_, kind) = self.errors.remove(pos);
+            return Err(io::Error::new(kind, "flaky"));
+        }
+        if self.data.is_empty() {
+            return match self.last.take() {
+                Some(kind) => Err(io::Error::new(kind, "flaky")),
+                None => Ok(0)
+            };
+        }
+        for error in self.errors.iter_mut() {
+            error.0 -= 1;
+        }
+        buf[0] = self.data[0];
+        self.data = &self.data[1..];
+        Ok(1)
+    }
+}
+
+#[test]
+fn error_kind_test_read() {
+    let mut reader = FlakyReader {
+        data: b"[1, 2]",
+        errors: vec![(0, io::ErrorKind::Interrupted), (4, io::ErrorKind::Interrupted)],
+        last: None
+    };
+    assert!(yaml::parse_io_utf8(&mut reader).is_ok());
+
+    let mut reader = FlakyReader {
+        data: b"[1, 2",
+        errors: vec![(0, io::ErrorKind::Interrupted), (4, io::ErrorKind::Interrupted)],
+        last: Some(io::ErrorKind::PermissionDenied)
+    };
+    match yaml::parse_io_utf8(&mut reader) {
+        Ok(_) => panic!("Should return an error"),
+        Err(e) => {
+            let io_error = e.io_error.expect("io_error");
+            assert_eq!(io::ErrorKind::PermissionDenied, io_error.kind());
+            assert_eq!("flaky", io_error.to_string());
+        }
+    }
+}
+
 fn write_to_bad_stream() -> Result<(), YamlError> {
     let mut mock_writer = MockRW::new();
     let mut emitter = YamlEmitter::init(&mut mock_writer);
@@ -0,0 +1,62 @@
+extern crate yaml;
+
+use yaml::parser;
+use yaml::parser::{YamlParser, YamlByteParser};
+use yaml::event::{YamlEvent, YamlEventSpec};
+use yaml::ffi::YamlEncoding;
+
+use std::time::Instant;
+
+// Timing comparison rather than a correctness test; run it with
+// `cargo test --release --test parse_bench_test -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_parse_all_events() {
+    let mut data = String::new();
+    for i in 0..100_000 {
+        data.push_str(&format!("- {{id: {}, name: item{}, tags: [a, b, c]}}\n", i, i));
+    }
+
+    let start = Instant::now();
+    let bulk = parser::parse_all_events(data.as_bytes()).unwrap();
+    let bulk_time = start.elapsed();
+
+    let start = Instant::now();
+    let parser = YamlByteParser::init(data.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+    let collected: Vec<YamlEvent> = parser.parse().collect::<Result<_, _>>().unwrap();
+    let collect_time = start.elapsed();
+
+    assert_eq!(bulk.len(), collected.len());
+    println!("{} events: parse_all_events {:?}, parse().collect() {:?}", bulk.len(), bulk_time, collect_time);
+}
+
+#[test]
+#[ignore]
+fn bench_parse_borrowed() {
+    let mut data = String::new();
+    for i in 0..100_000 {
+        data.push_str(&format!("- {{id: {}, name: item{}, text: \"some longer scalar value {}\"}}\n", i, i, i));
+    }
+
+    let start = Instant::now();
+    let parser = YamlByteParser::init(data.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+    let mut owned_len = 0;
+    for evt in parser.parse() {
+        if let YamlEventSpec::YamlScalarEvent(param) = evt.unwrap().spec {
+            owned_len += param.value.len();
+        }
+    }
+    let owned_time = start.elapsed();
+
+    let start = Instant::now();
+    let parser = YamlByteParser::init(data.as_bytes(), YamlEncoding::YamlUtf8Encoding);
+    let mut events = parser.parse_borrowed();
+    let mut borrowed_len = 0;
+    while let Some(evt) = events.next_event() {
+        borrowed_len += evt.unwrap().scalar_bytes().map_or(0, |value| value.len());
+    }
+    let borrowed_time = start.elapsed();
+
+    assert_eq!(owned_len, borrowed_len);
+    println!("{} bytes: parse() {:?}, parse_borrowed() {:?}", data.len(), owned_time, borrowed_time);
+}
@@ -0,0 +1,267 @@
+extern crate yaml;
+
+use yaml::parser;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Conformance harness for cases in the yaml-test-suite layouts: a directory holding `in.yaml`,
+// `test.event` and, for invalid input, an empty `error` file, or a `.tml` file of `===` cases.
+// The expected events are parsed from the suite's notation and compared with `dump_events`.
+// For error cases only the failure is checked, since `dump_events` drops the events before it.
+
+struct SuiteCase {
+    name: String,
+    input: Vec<u8>,
+    events: String,
+    error: bool
+}
+
+#[derive(Debug, PartialEq)]
+enum SuiteEvent {
+    StreamStart,
+    StreamEnd,
+    DocumentStart { explicit: bool },
+    DocumentEnd { explicit: bool },
+    SequenceStart { flow: bool, anchor: Option<String>, tag: Option<String> },
+    SequenceEnd,
+    MappingStart { flow: bool, anchor: Option<String>, tag: Option<String> },
+    MappingEnd,
+    Scalar { anchor: Option<String>, tag: Option<String>, style: char, value: String },
+    Alias(String)
+}
+
+fn unescape(value: &str) -> Result<String, String> {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next() {
+            Some('\\') => '\\',
+            Some('0') => '\0',
+            Some('b') => '\x08',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            other => return Err(format!("invalid escape {:?} in {:?}", other, value))
+        });
+    }
+    Ok(unescaped)
+}
+
+// Reads the `[]`/`{}` marker, anchor and tag following a node event code. For `=VAL`, the
+// remainder starting at the style character is returned as well.
+fn node_props(rest: &str, scalar: bool) -> Result<(bool, Option<String>, Option<String>, Option<&str>), String> {
+    let (mut flow, mut anchor, mut tag) = (false, None, None);
+    let mut rest = rest.trim_start_matches(' ');
+    while !rest.is_empty() {
+        if scalar && rest.starts_with(|c| ":'\"|>".contains(c)) {
+            return Ok((flow, anchor, tag, Some(rest)));
+        }
+        let (token, tail) = match rest.find(' ') {
+            Some(end) => (&rest[..end], &rest[end + 1..]),
+            None => (rest, "")
+        };
+        if token == "[]" || token == "{}" {
+            flow = true;
+        } else if token.starts_with('&') {
+            anchor = Some(token[1..].to_string());
+        } else if token.starts_with('<') && token.ends_with('>') {
+            tag = Some(token[1..token.len() - 1].to_string());
+        } else {
+            return Err(format!("unexpected {:?}", token));
+        }
+        rest = tail;
+    }
+
+    if scalar {
+        Err("missing scalar value".to_string())
+    } else {
+        Ok((flow, anchor, tag, None))
+    }
+}
+
+fn parse_event_notation(text: &str) -> Result<Vec<SuiteEvent>, String> {
+    let mut events = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_start();
+        if line.is_empty() {
+            continue;
+        }
+        let (code, rest) = line.split_at(line.len().min(4));
+        let evt = match code {
+            "+STR" => SuiteEvent::StreamStart,
+            "-STR" => SuiteEvent::StreamEnd,
+            "+DOC" => SuiteEvent::DocumentStart { explicit: rest.trim() == "---" },
+            "-DOC" => SuiteEvent::DocumentEnd { explicit: rest.trim() == "..." },
+            "+SEQ" | "+MAP" => {
+                let (flow, anchor, tag, _) = node_props(rest, false).map_err(|err| format!("{} in {:?}", err, line))?;
+                if code == "+SEQ" {
+                    SuiteEvent::SequenceStart { flow, anchor, tag }
+                } else {
+                    SuiteEvent::MappingStart { flow, anchor, tag }
+                }
+            },
+            "-SEQ" => SuiteEvent::SequenceEnd,
+            "-MAP" => SuiteEvent::MappingEnd,
+            "=VAL" => {
+                let (_, anchor, tag, value) = node_props(rest, true).map_err(|err| format!("{} in {:?}", err, line))?;
+                let value = value.unwrap();
+                SuiteEvent::Scalar { anchor, tag, style: value.chars().next().unwrap(), value: unescape(&value[1..])? }
+            },
+            "=ALI" => match rest.trim().strip_prefix('*') {
+                Some(anchor) => SuiteEvent::Alias(anchor.to_string()),
+                None => return Err(format!("missing alias name in {:?}", line))
+            },
+            _ => return Err(format!("unknown event {:?}", line))
+        };
+        events.push(evt);
+    }
+    Ok(events)
+}
+
+// Replaces the visible whitespace markers of `.tml` files.
+fn unmark_whitespace(text: &str) -> String {
+    let mut text = text.replace("<SPC>", " ").replace("<TAB>", "\t").replace("∎\n", "")
+        .replace('↵', "").replace('←', "\r").replace('⇔', "\u{feff}");
+    while let Some(start) = text.find('—') {
+        let end = start + text[start..].find('»').expect("unterminated tab marker");
+        text.replace_range(start..end + '»'.len_utf8(), "\t");
+    }
+    text
+}
+
+fn unindent(lines: &[&str]) -> Vec<String> {
+    let indent = lines.iter().filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches(' ').len()).min().unwrap_or(0);
+    lines.iter().map(|line| line.get(indent..).unwrap_or("").to_string()).collect()
+}
+
+fn load_tml(path: &Path) -> Vec<SuiteCase> {
+    let text = fs::read_to_string(path).unwrap();
+    let mut cases = Vec::new();
+
+    for block in text.split("\n=== ").skip(1) {
+        let mut lines = block.lines();
+        let mut case = SuiteCase {
+            name: format!("{}: {}", path.display(), lines.next().unwrap()),
+            input: Vec::new(),
+            events: String::new(),
+            error: false
+        };
+
+        let mut section: Option<(String, Vec<&str>)> = None;
+        for line in lines.chain(Some("--- end")) {
+            let header = line.strip_prefix("--- ").map(|name| name.split('(').next().unwrap())
+                .filter(|name| ["in-yaml", "in-json", "out-yaml", "emit-yaml", "test-event", "error", "from", "tags", "end"].contains(name));
+            let name = match header {
+                Some(name) => name,
+                None => {
+                    if let Some((_, ref mut body)) = section {
+                        body.push(line);
+                    }
+                    continue;
+                }
+            };
+
+            if let Some((kind, mut body)) = section.take() {
+                while body.last().is_some_and(|line| line.is_empty()) {
+                    body.pop();
+                }
+                let body = if kind.ends_with("(<)") { unindent(&body) } else { body.iter().map(|line| line.to_string()).collect() };
+                let content: String = body.iter().map(|line| format!("{}\n", line)).collect();
+                match kind.split('(').next().unwrap() {
+                    "in-yaml" => case.input = unmark_whitespace(&content).into_bytes(),
+                    "test-event" => case.events = unmark_whitespace(&content),
+                    _ => ()
+                }
+            }
+            if name == "error" {
+                case.error = true;
+            }
+            section = Some((line[4..].to_string(), Vec::new()));
+        }
+        cases.push(case);
+    }
+    cases
+}
+
+fn load_dir(path: &Path) -> SuiteCase {
+    SuiteCase {
+        name: path.display().to_string(),
+        input: fs::read(path.join("in.yaml")).unwrap(),
+        events: fs::read_to_string(path.join("test.event")).unwrap(),
+        error: path.join("error").exists()
+    }
+}
+
+fn check(case: &SuiteCase) -> Result<(), String> {
+    let expected = parse_event_notation(&case.events).map_err(|err| format!("{}: bad test.event: {}", case.name, err))?;
+    match (parser::dump_events(&case.input), case.error) {
+        (Ok(dump), false) => {
+            let actual = parse_event_notation(&dump).map_err(|err| format!("{}: bad dump: {}", case.name, err))?;
+            if actual == expected {
+                Ok(())
+            } else {
+                Err(format!("{}: events differ, got\n{}", case.name, dump))
+            }
+        },
+        (Err(_), true) => Ok(()),
+        (Ok(_), true) => Err(format!("{}: expected a parse error", case.name)),
+        (Err(err), false) => Err(format!("{}: unexpected error {:?}", case.name, err))
+    }
+}
+
+fn suite_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("suite")
+}
+
+fn assert_cases(cases: &[SuiteCase]) {
+    assert!(!cases.is_empty());
+    let failures: Vec<String> = cases.iter().filter_map(|case| check(case).err()).collect();
+    assert!(failures.is_empty(), "{} of {} cases failed:\n{}", failures.len(), cases.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_suite_directories() {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(suite_dir()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("in.yaml").exists())
+        .collect();
+    dirs.sort();
+    let cases: Vec<SuiteCase> = dirs.iter().map(|dir| load_dir(dir)).collect();
+    assert_cases(&cases);
+}
+
+#[test]
+fn test_suite_tml() {
+    let cases = load_tml(&suite_dir().join("cases.tml"));
+    assert_eq!(5, cases.len());
+    assert_eq!(b"a:\tb\n", &cases[1].input[..]);
+    assert_eq!(b"--- text", &cases[2].input[..]);
+    assert_cases(&cases);
+}
+
+#[test]
+fn test_event_notation() {
+    let events = parse_event_notation("+STR\n +DOC ---\n  +MAP {} &m <tag:yaml.org,2002:map>\n   =VAL &k :a b\n   =VAL <!x> \"tab\\there\n   =ALI *k\n  -MAP\n -DOC ...\n-STR\n");
+    assert_eq!(Ok(vec![
+        SuiteEvent::StreamStart,
+        SuiteEvent::DocumentStart { explicit: true },
+        SuiteEvent::MappingStart { flow: true, anchor: Some("m".to_string()), tag: Some("tag:yaml.org,2002:map".to_string()) },
+        SuiteEvent::Scalar { anchor: Some("k".to_string()), tag: None, style: ':', value: "a b".to_string() },
+        SuiteEvent::Scalar { anchor: None, tag: Some("!x".to_string()), style: '"', value: "tab\there".to_string() },
+        SuiteEvent::Alias("k".to_string()),
+        SuiteEvent::MappingEnd,
+        SuiteEvent::DocumentEnd { explicit: true },
+        SuiteEvent::StreamEnd
+    ]), events);
+
+    assert!(parse_event_notation("+SEQ x\n").is_err());
+    assert!(parse_event_notation("=VAL &a\n").is_err());
+    assert!(parse_event_notation("=VAL :a\\q\n").is_err());
+    assert!(parse_event_notation("+FOO\n").is_err());
+}
@@ -184,3 +184,14 @@ fn utf16be() {
                yseq![ystr!("Hello"), ystr!("世界")]
     )
 }
+
+#[test]
+fn utf16be_scalars() {
+    let expected = ymap!{
+        "ключ" => ystr!("значение"),
+        "emoji" => ystr!("😀 é 😀"),
+        "list" => yseq![ystr!("世界"), ystr!("🎉")]
+    };
+    test_file!(YamlEncoding::YamlUtf16BeEncoding, "source/utf16be_scalars.yml", expected);
+    test_file!(YamlEncoding::YamlAnyEncoding, "source/utf16be_scalars.yml", expected);
+}